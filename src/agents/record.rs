@@ -0,0 +1,107 @@
+use crate::control_interfaces::pcap;
+
+use tokio_stream::StreamExt;
+
+/// Determines when the recorder moves on to a new file.
+/// Limits are checked whenever a packet is captured, so a file may slightly exceed them.
+#[derive(Default)]
+pub struct Rotation {
+    /// The maximal size of a single file, in bytes.
+    pub max_bytes: Option<u64>,
+    /// The maximal time that a single file is written to.
+    pub max_duration: Option<std::time::Duration>,
+}
+
+pub struct Agent {
+    handle: pcap::CaptureHandle,
+    /// The path of the first file. Later files are numbered (e.g. `out.pcap`, `out.1.pcap`, `out.2.pcap`).
+    path: String,
+    rotation: Rotation,
+    /// The index of the file that is currently written to.
+    file_index: usize,
+    /// The time at which the current file was opened.
+    opened_at: std::time::Instant,
+    /// The time at which buffered packets were last written to the file.
+    flushed_at: std::time::Instant,
+}
+
+/// The interval at which buffered packets are written to the file, so that little is lost if the recorder is killed.
+const FLUSH_INTERVAL: std::time::Duration = std::time::Duration::from_secs(1);
+
+impl Agent {
+    /// Constructs the recorder and opens the first file.
+    pub fn new(
+        mut handle: pcap::CaptureHandle,
+        path: &str,
+        rotation: Rotation,
+    ) -> Result<Agent, pcap::Error> {
+        let savefile = pcap::Savefile::create(&mut handle, path)?;
+        handle.set_savefile(Some(savefile));
+        Ok(Agent {
+            handle,
+            path: String::from(path),
+            rotation,
+            file_index: 0,
+            opened_at: std::time::Instant::now(),
+            flushed_at: std::time::Instant::now(),
+        })
+    }
+
+    /// The path of the file with the given index.
+    fn file_name(&self, index: usize) -> String {
+        if index == 0 {
+            return self.path.clone();
+        }
+        let path = std::path::Path::new(self.path.as_str());
+        let stem = path.with_extension("");
+        match path.extension() {
+            Some(ext) => format!("{}.{}.{}", stem.display(), index, ext.to_string_lossy()),
+            None => format!("{}.{}", stem.display(), index),
+        }
+    }
+
+    /// Checks whether the current file has reached one of its limits.
+    fn should_rotate(&mut self) -> bool {
+        let too_big = match (self.rotation.max_bytes, self.handle.savefile()) {
+            (Some(max), Some(f)) => f.size().is_some_and(|s| s >= max),
+            _ => false,
+        };
+        let too_old = match self.rotation.max_duration {
+            Some(max) => self.opened_at.elapsed() >= max,
+            None => false,
+        };
+        too_big || too_old
+    }
+
+    /// Closes the current file and starts writing to the next one.
+    fn rotate(&mut self) -> Result<(), pcap::Error> {
+        let name = self.file_name(self.file_index + 1);
+        let savefile = pcap::Savefile::create(&mut self.handle, name.as_str())?;
+        // The previous file is closed once it is dropped.
+        self.handle.set_savefile(Some(savefile));
+        self.file_index += 1;
+        self.opened_at = std::time::Instant::now();
+        Ok(())
+    }
+
+    /// While running, the recorder writes every captured packet (including ones that can not be parsed) to the current file.
+    pub async fn run(mut self) -> () {
+        while self.handle.next().await.is_some() {
+            if self.flushed_at.elapsed() >= FLUSH_INTERVAL {
+                if let Some(f) = self.handle.savefile() {
+                    if let Err(e) = f.flush() {
+                        eprintln!("{}", e);
+                        break;
+                    }
+                }
+                self.flushed_at = std::time::Instant::now();
+            }
+            if self.should_rotate() {
+                if let Err(e) = self.rotate() {
+                    eprintln!("Could not rotate capture file: {}", e);
+                    break;
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,161 @@
+use crate::control_interfaces::{self, pcap, PacketInterface};
+use crate::protocols::{linklayer, networklayer, sessionlayer};
+
+use tokio_stream::StreamExt;
+
+/// Determines when each frame is injected.
+#[derive(Clone, Copy)]
+pub enum Timing {
+    /// Frames keep the gaps between them, which are divided by the speed (e.g. a speed of 2 replays the file twice as fast).
+    Original { speed: f64 },
+    /// Frames are injected as fast as possible.
+    TopSpeed,
+}
+
+/// Addresses that replace the ones in every replayed IPv4 packet. Other frames are replayed unchanged.
+#[derive(Default)]
+pub struct Rewrite {
+    pub mac_src: Option<[u8; linklayer::ethernet::HW_ADDR_LEN]>,
+    pub mac_dst: Option<[u8; linklayer::ethernet::HW_ADDR_LEN]>,
+    pub ip_src: Option<[u8; 4]>,
+    pub ip_dst: Option<[u8; 4]>,
+}
+
+impl Rewrite {
+    pub fn is_empty(&self) -> bool {
+        self.mac_src.is_none()
+            && self.mac_dst.is_none()
+            && self.ip_src.is_none()
+            && self.ip_dst.is_none()
+    }
+
+    /// Rebuilds a frame with the new addresses, or returns None if the frame does not carry an IPv4 packet.
+    fn apply(&self, raw: &pcap::RawFrame) -> Option<pcap::Data> {
+        let parsed = match raw.parse() {
+            Ok(pcap::Data::LinkLayer(l)) => l,
+            _ => return None,
+        };
+        let raw_ip = matches!(parsed, linklayer::Data::RawIp(_));
+        let (mut session, mut metadata) =
+            control_interfaces::stack::ipv4::Metadata::extract(parsed)?;
+        metadata.mac_src = self.mac_src.unwrap_or(metadata.mac_src);
+        metadata.mac_dst = self.mac_dst.unwrap_or(metadata.mac_dst);
+        metadata.ip_src = self.ip_src.unwrap_or(metadata.ip_src);
+        metadata.ip_dst = self.ip_dst.unwrap_or(metadata.ip_dst);
+        // The UDP checksum covers the IP addresses. TCP checksums are always calculated again.
+        if let sessionlayer::Data::Udp(ref mut d) = session {
+            d.clear_checksum();
+        }
+        Some(pcap::Data::LinkLayer(match metadata.embellish(session) {
+            // Frames that had no link-layer header are replayed without one.
+            linklayer::Data::Ethernet(linklayer::ethernet::Frame {
+                next: networklayer::Data::Ip(p),
+                ..
+            }) if raw_ip => linklayer::Data::RawIp(p),
+            l => l,
+        }))
+    }
+}
+
+/// Replays the frames of a savefile by injecting them into an interface.
+pub struct Agent<I: PacketInterface> {
+    output: I,
+    path: String,
+    timing: Timing,
+    /// The number of times that the file is replayed, or None to replay it until the agent is stopped.
+    loops: Option<u64>,
+    rewrite: Rewrite,
+}
+
+impl<I: PacketInterface> Agent<I> {
+    /// Constructs an agent that replays the file once, with its original timing.
+    pub fn new(output: I, path: &str) -> Agent<I> {
+        Agent {
+            output,
+            path: String::from(path),
+            timing: Timing::Original { speed: 1.0 },
+            loops: Some(1),
+            rewrite: Rewrite::default(),
+        }
+    }
+
+    pub fn with_timing(mut self, timing: Timing) -> Self {
+        self.timing = timing;
+        self
+    }
+
+    pub fn with_loops(mut self, loops: Option<u64>) -> Self {
+        self.loops = loops;
+        self
+    }
+
+    pub fn with_rewrite(mut self, rewrite: Rewrite) -> Self {
+        self.rewrite = rewrite;
+        self
+    }
+
+    /// Injects the frames of a single pass over the file, and returns the number of frames that have been injected.
+    async fn replay_once(&mut self) -> Result<u64, pcap::Error> {
+        let mut input = pcap::CaptureHandle::open_offline(self.path.as_str())?;
+        input.set_raw(true);
+        if input.link_type() != self.output.link_type() {
+            eprintln!(
+                "Warning: the file has link type {}, but the interface has link type {}",
+                input.link_type(),
+                self.output.link_type()
+            );
+        }
+        // The timestamp of the first frame, and the time at which it has been injected.
+        let mut start: Option<(std::time::SystemTime, tokio::time::Instant)> = None;
+        let mut injected = 0;
+        while let Some(p) = input.next().await {
+            let (ts, data) = match p {
+                Ok(p) => p,
+                Err(e) => {
+                    eprintln!("{}", e);
+                    continue;
+                }
+            };
+            if let Timing::Original { speed } = self.timing {
+                match start {
+                    None => start = Some((ts, tokio::time::Instant::now())),
+                    Some((first_ts, first_instant)) => {
+                        // Frames that are older than the first one are injected immediately.
+                        let gap = ts.duration_since(first_ts).unwrap_or_default();
+                        tokio::time::sleep_until(first_instant + gap.div_f64(speed)).await;
+                    }
+                }
+            }
+            let result = match data {
+                pcap::Data::Raw(r) => match self.rewrite.is_empty() {
+                    true => self.output.inject_buffer(&r.bytes),
+                    false => match self.rewrite.apply(&r) {
+                        Some(d) => self.output.inject(d),
+                        None => self.output.inject_buffer(&r.bytes),
+                    },
+                },
+                d => self.output.inject(d),
+            };
+            match result {
+                Ok(_) => injected += 1,
+                Err(e) => eprintln!("{}", e),
+            }
+        }
+        Ok(injected)
+    }
+
+    /// While running, the agent replays the file as many times as it has been configured to.
+    pub async fn run(mut self) -> () {
+        let mut pass = 0;
+        while self.loops.is_none_or(|l| pass < l) {
+            match self.replay_once().await {
+                Ok(n) => println!("Replayed {} frames from {}", n, self.path),
+                Err(e) => {
+                    eprintln!("{}", e);
+                    return;
+                }
+            }
+            pass += 1;
+        }
+    }
+}
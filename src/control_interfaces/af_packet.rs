@@ -0,0 +1,675 @@
+use super::pcap;
+use crate::pcap_c;
+use crate::protocols::linklayer::ethernet;
+
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::task::Poll;
+use tokio_stream::Stream;
+
+// Values from linux/if_packet.h and linux/if_arp.h that libc does not provide.
+const PACKET_RX_RING: libc::c_int = 5;
+const PACKET_STATISTICS: libc::c_int = 6;
+const PACKET_VERSION: libc::c_int = 10;
+const TPACKET_V3: libc::c_int = 2;
+const TP_STATUS_KERNEL: u32 = 0;
+const TP_STATUS_USER: u32 = 1;
+const TP_STATUS_VLAN_VALID: u32 = 1 << 4;
+const TP_STATUS_VLAN_TPID_VALID: u32 = 1 << 6;
+const PACKET_OUTGOING: u8 = 4;
+const ARPHRD_ETHER: u16 = 1;
+const ARPHRD_LOOPBACK: u16 = 772;
+const ARPHRD_RAWIP: u16 = 519;
+const ARPHRD_NONE: u16 = 0xfffe;
+
+/// struct tpacket_req3, which describes the ring that the kernel allocates.
+#[repr(C)]
+struct TpacketReq3 {
+    tp_block_size: libc::c_uint,
+    tp_block_nr: libc::c_uint,
+    tp_frame_size: libc::c_uint,
+    tp_frame_nr: libc::c_uint,
+    tp_retire_blk_tov: libc::c_uint,
+    tp_sizeof_priv: libc::c_uint,
+    tp_feature_req_word: libc::c_uint,
+}
+
+/// struct tpacket_stats_v3. The kernel resets the counts every time they are read.
+#[repr(C)]
+#[derive(Default)]
+struct TpacketStatsV3 {
+    tp_packets: libc::c_uint,
+    tp_drops: libc::c_uint,
+    tp_freeze_q_cnt: libc::c_uint,
+}
+
+// Offsets into struct tpacket_block_desc, which starts every block.
+const BLOCK_STATUS: usize = 8;
+const BLOCK_NUM_PKTS: usize = 12;
+const BLOCK_FIRST_PKT: usize = 16;
+
+// Offsets into struct tpacket3_hdr, which precedes every frame in a block.
+const PKT_NEXT_OFFSET: usize = 0;
+const PKT_SEC: usize = 4;
+const PKT_NSEC: usize = 8;
+const PKT_SNAPLEN: usize = 12;
+const PKT_LEN: usize = 16;
+const PKT_STATUS: usize = 20;
+const PKT_MAC: usize = 24;
+const PKT_VLAN_TCI: usize = 32;
+const PKT_VLAN_TPID: usize = 36;
+/// The struct sockaddr_ll that describes a frame follows its header, aligned to 16 bytes.
+const PKT_SLL: usize = 48;
+const SLL_PKTTYPE: usize = 10;
+
+#[derive(Debug)]
+pub enum Error {
+    /// The device does not exist.
+    NoSuchDevice(String),
+    /// The frames of the device have a link-layer header that is not supported (identified by its ARPHRD_ value).
+    UnsupportedHardware(u16),
+    /// The size of a block is not a multiple of the page size, or the ring has no blocks.
+    InvalidRing,
+    Socket(std::io::Error),
+    SetVersion(std::io::Error),
+    SetupRing(std::io::Error),
+    MapRing(std::io::Error),
+    Bind(std::io::Error),
+    SetPromisc(std::io::Error),
+    /// The socket could not be registered with the runtime.
+    Register(std::io::Error),
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Error::NoSuchDevice(d) => write!(f, "no such device {}", d),
+            Error::UnsupportedHardware(t) => write!(f, "unsupported hardware type {}", t),
+            Error::InvalidRing => write!(
+                f,
+                "the block size must be a positive multiple of the page size, and there must be at least one block"
+            ),
+            Error::Socket(e) => write!(f, "could not open a packet socket: {}", e),
+            Error::SetVersion(e) => write!(f, "could not use TPACKET_V3: {}", e),
+            Error::SetupRing(e) => write!(f, "could not set up the receive ring: {}", e),
+            Error::MapRing(e) => write!(f, "could not map the receive ring: {}", e),
+            Error::Bind(e) => write!(f, "could not bind to the device: {}", e),
+            Error::SetPromisc(e) => write!(f, "could not enter promiscuous mode: {}", e),
+            Error::Register(e) => write!(f, "could not wait for the socket: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::NoSuchDevice(_) | Error::UnsupportedHardware(_) | Error::InvalidRing => None,
+            Error::Socket(e) => Some(e),
+            Error::SetVersion(e) => Some(e),
+            Error::SetupRing(e) => Some(e),
+            Error::MapRing(e) => Some(e),
+            Error::Bind(e) => Some(e),
+            Error::SetPromisc(e) => Some(e),
+            Error::Register(e) => Some(e),
+        }
+    }
+}
+
+/// A packet socket, which is closed once it is dropped.
+struct Socket(RawFd);
+
+impl AsRawFd for Socket {
+    fn as_raw_fd(&self) -> RawFd {
+        self.0
+    }
+}
+
+impl Drop for Socket {
+    fn drop(&mut self) {
+        unsafe {
+            libc::close(self.0);
+        }
+    }
+}
+
+/// Configures a ring handle before it is opened.
+pub struct Builder {
+    dev: String,
+    mode: pcap::CaptureMode,
+    block_size: u32,
+    block_count: u32,
+    timeout_ms: u32,
+    raw: bool,
+}
+
+/// Captures frames from a memory-mapped TPACKET_V3 ring that the kernel fills, without going through libpcap.
+/// The kernel hands over whole blocks of frames, which are read directly from the ring and returned to it once every frame has been read.
+pub struct RingHandle {
+    device: String,
+    ifindex: libc::c_int,
+    /// The LINKTYPE_ value of the frames that the device carries.
+    link_type: i32,
+    socket: tokio::io::unix::AsyncFd<Socket>,
+    /// The mapped ring, which is unmapped once the handle is dropped.
+    ring: std::sync::atomic::AtomicPtr<u8>,
+    block_size: usize,
+    block_count: usize,
+    /// The block that the kernel will fill next.
+    current_block: usize,
+    /// Whether frames that the host sends are captured, and whether frames that it receives are.
+    capture_out: bool,
+    capture_in: bool,
+    /// Whether captured frames are returned as raw data, to be parsed on demand.
+    raw: bool,
+    /// Frames that have been read from a block but have not been returned yet.
+    queue:
+        std::collections::VecDeque<Result<(std::time::SystemTime, pcap::Data), pcap::CaptureError>>,
+    /// The counts that the kernel has reported so far.
+    stats: pcap::Stats,
+}
+
+impl RingHandle {
+    /// Starts configuring a handle for the given device. The default ring holds 8 blocks of 1 MiB.
+    pub fn builder(dev: &str) -> Builder {
+        Builder {
+            dev: String::from(dev),
+            mode: pcap::CaptureMode::Promisc,
+            block_size: 1 << 20,
+            block_count: 8,
+            timeout_ms: 100,
+            raw: false,
+        }
+    }
+
+    /// Reads every frame of the current block into the queue and returns the block to the kernel.
+    /// Returns false if the kernel has not finished filling the block yet.
+    fn read_block(&mut self) -> bool {
+        unsafe {
+            let block = self
+                .ring
+                .get_mut()
+                .add(self.current_block * self.block_size);
+            let status = &*(block.add(BLOCK_STATUS) as *const AtomicU32);
+            // The frames of the block may only be read after its status has been seen.
+            if status.load(Ordering::Acquire) & TP_STATUS_USER == 0 {
+                return false;
+            }
+            let num_pkts = read_u32(block, BLOCK_NUM_PKTS);
+            let mut pkt = block.add(read_u32(block, BLOCK_FIRST_PKT) as usize);
+            for _ in 0..num_pkts {
+                let outgoing = *pkt.add(PKT_SLL + SLL_PKTTYPE) == PACKET_OUTGOING;
+                if (outgoing && self.capture_out) || (!outgoing && self.capture_in) {
+                    let mut caplen = read_u32(pkt, PKT_SNAPLEN);
+                    let mut original_len = read_u32(pkt, PKT_LEN);
+                    let mut frame = std::slice::from_raw_parts(
+                        pkt.add(std::ptr::read_unaligned(pkt.add(PKT_MAC) as *const u16) as usize),
+                        caplen as usize,
+                    );
+                    // The kernel strips the VLAN tag of a frame and reports it separately, so it is put back to show the frame as it was on the wire.
+                    let tagged;
+                    if let (pcap_c::DLT_EN10MB, Some(tag)) = (self.link_type, stripped_tag(pkt)) {
+                        if let Some(t) = insert_tag(frame, tag) {
+                            tagged = t;
+                            frame = &tagged;
+                            caplen += 4;
+                            original_len += 4;
+                        }
+                    }
+                    let ts = std::time::UNIX_EPOCH
+                        + std::time::Duration::new(
+                            read_u32(pkt, PKT_SEC) as u64,
+                            read_u32(pkt, PKT_NSEC),
+                        );
+                    // Parsed frames are read straight from the ring, and only raw frames are copied out of it.
+                    self.queue.push_back(
+                        match self.raw {
+                            true => Ok(pcap::Data::Raw(pcap::RawFrame {
+                                link_type: self.link_type,
+                                caplen,
+                                original_len,
+                                bytes: Box::from(frame),
+                            })),
+                            false => pcap::Data::from_link_type(self.link_type, frame),
+                        }
+                        .map(|d| (ts, d)),
+                    );
+                }
+                pkt = pkt.add(read_u32(pkt, PKT_NEXT_OFFSET) as usize);
+            }
+            status.store(TP_STATUS_KERNEL, Ordering::Release);
+        }
+        self.current_block = (self.current_block + 1) % self.block_count;
+        true
+    }
+
+    pub fn link_type(&self) -> i32 {
+        self.link_type
+    }
+
+    /// Injects a frame using sendto. Frames are sent as they are, so they must include the link-layer header of the device.
+    pub fn inject_buffer(&mut self, raw: &[u8]) -> Result<(), pcap::Error> {
+        let mut addr: libc::sockaddr_ll = unsafe { std::mem::zeroed() };
+        addr.sll_family = libc::AF_PACKET as u16;
+        addr.sll_ifindex = self.ifindex;
+        // Devices without a link-layer header can not tell the protocol of a frame by themselves.
+        addr.sll_protocol = match (self.link_type, raw.first().map(|b| b >> 4)) {
+            (pcap_c::LINKTYPE_RAW, Some(4)) => (libc::ETH_P_IP as u16).to_be(),
+            (pcap_c::LINKTYPE_RAW, Some(6)) => (libc::ETH_P_IPV6 as u16).to_be(),
+            _ => (libc::ETH_P_ALL as u16).to_be(),
+        };
+        let sent = unsafe {
+            libc::sendto(
+                self.socket.get_ref().as_raw_fd(),
+                raw.as_ptr() as *const libc::c_void,
+                raw.len(),
+                0,
+                &addr as *const libc::sockaddr_ll as *const libc::sockaddr,
+                std::mem::size_of::<libc::sockaddr_ll>() as libc::socklen_t,
+            )
+        };
+        match sent {
+            -1 => Err(pcap::Error::new(
+                pcap::Operation::Inject,
+                &std::io::Error::last_os_error().to_string(),
+            )
+            .with_context(self.device.as_str())),
+            _ => Ok(()),
+        }
+    }
+
+    /// Attaches a compiled filter to the socket, so that the kernel drops the frames that do not match it before they reach the ring.
+    pub fn apply_filter(&mut self, filter: &pcap::Filter) -> Result<(), pcap::Error> {
+        let program = filter.program();
+        // struct bpf_insn and struct sock_filter have the same layout.
+        let fprog = libc::sock_fprog {
+            len: program.bf_len as libc::c_ushort,
+            filter: program.bf_insns as *mut libc::sock_filter,
+        };
+        match unsafe {
+            setsockopt(
+                self.socket.get_ref().as_raw_fd(),
+                libc::SOL_SOCKET,
+                libc::SO_ATTACH_FILTER,
+                &fprog,
+            )
+        } {
+            Ok(_) => Ok(()),
+            Err(e) => Err(
+                pcap::Error::new(pcap::Operation::ApplyFilter, &e.to_string())
+                    .with_context(self.device.as_str()),
+            ),
+        }
+    }
+}
+
+impl Builder {
+    pub fn mode(mut self, mode: pcap::CaptureMode) -> Self {
+        self.mode = mode;
+        self
+    }
+    /// Sets the size of every block in the ring, in bytes. It must be a multiple of the page size, and larger than any frame.
+    pub fn block_size(mut self, block_size: u32) -> Self {
+        self.block_size = block_size;
+        self
+    }
+    pub fn block_count(mut self, block_count: u32) -> Self {
+        self.block_count = block_count;
+        self
+    }
+    /// Sets the time after which the kernel hands over a block that is not full.
+    pub fn timeout_ms(mut self, timeout_ms: u32) -> Self {
+        self.timeout_ms = timeout_ms;
+        self
+    }
+    /// Makes the handle return frames exactly as they were captured, instead of parsing them.
+    pub fn raw(mut self, raw: bool) -> Self {
+        self.raw = raw;
+        self
+    }
+
+    /// Opens a packet socket, maps its receive ring and binds it to the device.
+    pub fn open(self) -> Result<RingHandle, Error> {
+        let page_size = unsafe { libc::sysconf(libc::_SC_PAGESIZE) } as u32;
+        // The page size is a power of two.
+        if self.block_count == 0 || self.block_size == 0 || self.block_size & (page_size - 1) != 0 {
+            return Err(Error::InvalidRing);
+        }
+        let ifindex = match std::ffi::CString::new(self.dev.as_str()) {
+            Ok(n) => unsafe { libc::if_nametoindex(n.as_ptr()) },
+            Err(_) => 0,
+        };
+        if ifindex == 0 {
+            return Err(Error::NoSuchDevice(self.dev));
+        }
+        let link_type = match hardware_type(self.dev.as_str()) {
+            Some(ARPHRD_ETHER) | Some(ARPHRD_LOOPBACK) => pcap_c::DLT_EN10MB,
+            Some(ARPHRD_NONE) | Some(ARPHRD_RAWIP) => pcap_c::LINKTYPE_RAW,
+            Some(t) => return Err(Error::UnsupportedHardware(t)),
+            None => return Err(Error::NoSuchDevice(self.dev)),
+        };
+
+        let socket = match unsafe {
+            libc::socket(
+                libc::AF_PACKET,
+                libc::SOCK_RAW | libc::SOCK_NONBLOCK | libc::SOCK_CLOEXEC,
+                (libc::ETH_P_ALL as u16).to_be() as libc::c_int,
+            )
+        } {
+            -1 => return Err(Error::Socket(std::io::Error::last_os_error())),
+            fd => Socket(fd),
+        };
+        let fd = socket.as_raw_fd();
+        unsafe { setsockopt(fd, libc::SOL_PACKET, PACKET_VERSION, &TPACKET_V3) }
+            .map_err(Error::SetVersion)?;
+
+        // Frames in a TPACKET_V3 ring have variable sizes, so the frame size only has to divide the block size.
+        let frame_size = 2048;
+        let req = TpacketReq3 {
+            tp_block_size: self.block_size,
+            tp_block_nr: self.block_count,
+            tp_frame_size: frame_size,
+            tp_frame_nr: self.block_size / frame_size * self.block_count,
+            tp_retire_blk_tov: self.timeout_ms,
+            tp_sizeof_priv: 0,
+            tp_feature_req_word: 0,
+        };
+        unsafe { setsockopt(fd, libc::SOL_PACKET, PACKET_RX_RING, &req) }
+            .map_err(Error::SetupRing)?;
+
+        let ring_len = self.block_size as usize * self.block_count as usize;
+        let ring = match unsafe {
+            libc::mmap(
+                std::ptr::null_mut(),
+                ring_len,
+                libc::PROT_READ | libc::PROT_WRITE,
+                libc::MAP_SHARED,
+                fd,
+                0,
+            )
+        } {
+            libc::MAP_FAILED => return Err(Error::MapRing(std::io::Error::last_os_error())),
+            r => r as *mut u8,
+        };
+        // The ring is unmapped by the handle from here on.
+        let mut handle = RingHandle {
+            device: self.dev,
+            ifindex: ifindex as libc::c_int,
+            link_type,
+            socket: match tokio::io::unix::AsyncFd::new(socket) {
+                Ok(s) => s,
+                Err(e) => {
+                    unsafe { libc::munmap(ring as *mut libc::c_void, ring_len) };
+                    return Err(Error::Register(e));
+                }
+            },
+            ring: std::sync::atomic::AtomicPtr::new(ring),
+            block_size: self.block_size as usize,
+            block_count: self.block_count as usize,
+            current_block: 0,
+            capture_out: true,
+            capture_in: true,
+            raw: self.raw,
+            queue: std::collections::VecDeque::new(),
+            stats: pcap::Stats::default(),
+        };
+
+        let mut addr: libc::sockaddr_ll = unsafe { std::mem::zeroed() };
+        addr.sll_family = libc::AF_PACKET as u16;
+        addr.sll_protocol = (libc::ETH_P_ALL as u16).to_be();
+        addr.sll_ifindex = handle.ifindex;
+        if unsafe {
+            libc::bind(
+                fd,
+                &addr as *const libc::sockaddr_ll as *const libc::sockaddr,
+                std::mem::size_of::<libc::sockaddr_ll>() as libc::socklen_t,
+            )
+        } != 0
+        {
+            return Err(Error::Bind(std::io::Error::last_os_error()));
+        }
+
+        if let pcap::CaptureMode::Promisc = self.mode {
+            let mreq = libc::packet_mreq {
+                mr_ifindex: handle.ifindex,
+                mr_type: libc::PACKET_MR_PROMISC as u16,
+                mr_alen: 0,
+                mr_address: [0; 8],
+            };
+            unsafe { setsockopt(fd, libc::SOL_PACKET, libc::PACKET_ADD_MEMBERSHIP, &mreq) }
+                .map_err(Error::SetPromisc)?;
+        }
+        // Frames that arrived before the device was bound may be in the ring, and are discarded.
+        while handle.read_block() {}
+        handle.queue.clear();
+        Ok(handle)
+    }
+}
+
+impl Drop for RingHandle {
+    fn drop(&mut self) {
+        unsafe {
+            libc::munmap(
+                *self.ring.get_mut() as *mut libc::c_void,
+                self.block_size * self.block_count,
+            );
+        }
+    }
+}
+
+impl Stream for RingHandle {
+    type Item = Result<(std::time::SystemTime, pcap::Data), pcap::CaptureError>;
+
+    /// Waits for the kernel to hand over a block, and returns its frames one by one.
+    fn poll_next(
+        mut self: Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+    ) -> Poll<Option<Self::Item>> {
+        loop {
+            if let Some(p) = self.queue.pop_front() {
+                return Poll::Ready(Some(p));
+            }
+            if self.read_block() {
+                continue;
+            }
+            // The socket becomes readable once a block is ready. Readiness is cleared before the block is checked again, so that no block is missed.
+            match self.socket.poll_read_ready(cx) {
+                Poll::Ready(Ok(mut r)) => r.clear_ready(),
+                Poll::Ready(Err(e)) => {
+                    return Poll::Ready(Some(Err(pcap::CaptureError::CouldNotRead(e))))
+                }
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+    }
+}
+
+impl super::PacketInterface for RingHandle {
+    fn name(&self) -> Option<&str> {
+        Some(self.device.as_str())
+    }
+    fn link_type(&self) -> i32 {
+        RingHandle::link_type(self)
+    }
+    /// Frames are only truncated if they do not fit in a block.
+    fn snaplen(&self) -> i32 {
+        self.block_size as i32
+    }
+    fn inject(&mut self, data: pcap::Data) -> Result<(), pcap::Error> {
+        self.inject_buffer(&data.into_buffer((0, 0)))
+    }
+    fn inject_buffer(&mut self, raw: &[u8]) -> Result<(), pcap::Error> {
+        RingHandle::inject_buffer(self, raw)
+    }
+    /// Filters are compiled with the netmask of the device, if it has an IPv4 address.
+    fn set_filter(&mut self, code: &str) -> Result<(), pcap::Error> {
+        #[cfg(feature = "libpcap")]
+        let netmask = match super::devices::find(self.device.as_str()) {
+            Ok(Some(d)) => d.ipv4_netmask(),
+            _ => None,
+        };
+        #[cfg(not(feature = "libpcap"))]
+        let netmask = None;
+        let filter = pcap::Filter::compile(self.link_type, self.snaplen(), code, netmask)?;
+        self.apply_filter(&filter)
+    }
+    /// The direction is determined by the packet type that the kernel reports for every frame.
+    fn set_direction(&mut self, direction: pcap::Direction) -> Result<(), pcap::Error> {
+        let (capture_in, capture_out) = match direction {
+            pcap::Direction::InOut => (true, true),
+            pcap::Direction::In => (true, false),
+            pcap::Direction::Out => (false, true),
+        };
+        self.capture_in = capture_in;
+        self.capture_out = capture_out;
+        Ok(())
+    }
+    fn stats(&mut self) -> Result<pcap::Stats, pcap::Error> {
+        let mut raw = TpacketStatsV3::default();
+        let mut len = std::mem::size_of::<TpacketStatsV3>() as libc::socklen_t;
+        if unsafe {
+            libc::getsockopt(
+                self.socket.get_ref().as_raw_fd(),
+                libc::SOL_PACKET,
+                PACKET_STATISTICS,
+                &mut raw as *mut TpacketStatsV3 as *mut libc::c_void,
+                &mut len,
+            )
+        } != 0
+        {
+            return Err(pcap::Error::new(
+                pcap::Operation::Stats,
+                &std::io::Error::last_os_error().to_string(),
+            )
+            .with_context(self.device.as_str()));
+        }
+        // The kernel counts dropped packets as received, as libpcap does.
+        self.stats.received = self.stats.received.wrapping_add(raw.tp_packets);
+        self.stats.dropped = self.stats.dropped.wrapping_add(raw.tp_drops);
+        Ok(self.stats)
+    }
+}
+
+/// Reads a field of a block or frame header, which the kernel writes in native byte order.
+unsafe fn read_u32(base: *const u8, offset: usize) -> u32 {
+    std::ptr::read_unaligned(base.add(offset) as *const u32)
+}
+
+unsafe fn setsockopt<T>(
+    fd: RawFd,
+    level: libc::c_int,
+    name: libc::c_int,
+    value: &T,
+) -> Result<(), std::io::Error> {
+    match libc::setsockopt(
+        fd,
+        level,
+        name,
+        value as *const T as *const libc::c_void,
+        std::mem::size_of::<T>() as libc::socklen_t,
+    ) {
+        0 => Ok(()),
+        _ => Err(std::io::Error::last_os_error()),
+    }
+}
+
+/// The 802.1Q tag that the kernel has stripped from a frame, if it reports one. The tag is given as it appears on the wire.
+unsafe fn stripped_tag(pkt: *const u8) -> Option<[u8; 4]> {
+    let status = read_u32(pkt, PKT_STATUS);
+    if status & TP_STATUS_VLAN_VALID == 0 {
+        return None;
+    }
+    // Older kernels do not report the TPID, and only strip 802.1Q tags.
+    let tpid = match status & TP_STATUS_VLAN_TPID_VALID {
+        0 => ethernet::TPID_8021Q,
+        _ => std::ptr::read_unaligned(pkt.add(PKT_VLAN_TPID) as *const u16),
+    };
+    let tci = read_u32(pkt, PKT_VLAN_TCI) as u16;
+    let mut tag = [0; 4];
+    tag[..2].copy_from_slice(&tpid.to_be_bytes());
+    tag[2..].copy_from_slice(&tci.to_be_bytes());
+    Some(tag)
+}
+
+/// Copies an Ethernet frame with a tag inserted after its addresses. Frames that were captured without both addresses are left alone.
+fn insert_tag(frame: &[u8], tag: [u8; 4]) -> Option<Vec<u8>> {
+    let addrs = 2 * ethernet::HW_ADDR_LEN;
+    if frame.len() < addrs {
+        return None;
+    }
+    let mut tagged = Vec::with_capacity(frame.len() + tag.len());
+    tagged.extend_from_slice(&frame[..addrs]);
+    tagged.extend_from_slice(&tag);
+    tagged.extend_from_slice(&frame[addrs..]);
+    Some(tagged)
+}
+
+/// Reads the ARPHRD_ value of a device, which determines the link-layer header of its frames.
+fn hardware_type(dev: &str) -> Option<u16> {
+    std::fs::read_to_string(format!("/sys/class/net/{}/type", dev))
+        .ok()?
+        .trim()
+        .parse()
+        .ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A struct tpacket3_hdr with the given status and VLAN fields.
+    fn header(status: u32, tci: u32, tpid: u16) -> [u8; PKT_SLL] {
+        let mut hdr = [0; PKT_SLL];
+        hdr[PKT_STATUS..PKT_STATUS + 4].copy_from_slice(&status.to_ne_bytes());
+        hdr[PKT_VLAN_TCI..PKT_VLAN_TCI + 4].copy_from_slice(&tci.to_ne_bytes());
+        hdr[PKT_VLAN_TPID..PKT_VLAN_TPID + 2].copy_from_slice(&tpid.to_ne_bytes());
+        hdr
+    }
+
+    #[test]
+    fn stripped_tags() {
+        unsafe {
+            assert_eq!(
+                stripped_tag(header(TP_STATUS_USER, 5, 0x88A8).as_ptr()),
+                None
+            );
+            assert_eq!(
+                stripped_tag(header(TP_STATUS_USER | TP_STATUS_VLAN_VALID, 0x2064, 0).as_ptr()),
+                Some([0x81, 0x00, 0x20, 0x64])
+            );
+            assert_eq!(
+                stripped_tag(
+                    header(
+                        TP_STATUS_USER | TP_STATUS_VLAN_VALID | TP_STATUS_VLAN_TPID_VALID,
+                        0x0064,
+                        0x88A8
+                    )
+                    .as_ptr()
+                ),
+                Some([0x88, 0xA8, 0x00, 0x64])
+            );
+        }
+    }
+
+    #[test]
+    fn tags_are_inserted_after_the_addresses() {
+        // An ARP request from 192.168.0.1 for 192.168.0.2.
+        let mut frame: Vec<u8> = (0..12).collect();
+        frame.extend_from_slice(&[0x08, 0x06, 0, 1, 0x08, 0, 6, 4, 0, 1]);
+        frame.extend_from_slice(&[6, 7, 8, 9, 10, 11, 192, 168, 0, 1]);
+        frame.extend_from_slice(&[0, 0, 0, 0, 0, 0, 192, 168, 0, 2]);
+        let tagged = insert_tag(&frame, [0x81, 0x00, 0x00, 0x64]).unwrap();
+        assert_eq!(&tagged[..12], &frame[..12]);
+        assert_eq!(&tagged[12..16], &[0x81, 0x00, 0x00, 0x64]);
+        assert_eq!(&tagged[16..], &frame[12..]);
+        match pcap::Data::from_link_type(pcap_c::DLT_EN10MB, &tagged) {
+            Ok(pcap::Data::LinkLayer(crate::protocols::linklayer::Data::Ethernet(f))) => {
+                assert_eq!(f.tags.len(), 1);
+                assert_eq!(f.tags[0].tpid, ethernet::TPID_8021Q);
+                assert_eq!(f.tags[0].vid, 100);
+            }
+            r => panic!("{:?}", r),
+        }
+        assert_eq!(insert_tag(&frame[..11], [0x81, 0x00, 0x00, 0x64]), None);
+    }
+}
@@ -0,0 +1,222 @@
+use super::{neighbors, pcap};
+use crate::pcap_c;
+use crate::protocols::linklayer::{ethernet::HW_ADDR_LEN, sll};
+
+use std::convert::TryInto;
+
+/// An address that has been assigned to a device.
+#[derive(Clone, Debug)]
+pub enum Address {
+    V4(std::net::Ipv4Addr),
+    V6(std::net::Ipv6Addr),
+    /// A hardware address (e.g. a MAC address).
+    Link(Vec<u8>),
+    /// An address of a family that is not supported. Only the family is kept.
+    Other(u16),
+}
+
+/// An address of a device, along with the addresses that accompany it.
+#[derive(Clone, Debug)]
+pub struct DeviceAddress {
+    pub addr: Address,
+    pub netmask: Option<Address>,
+    pub broadcast: Option<Address>,
+    /// The address of the other end of a point-to-point link.
+    pub destination: Option<Address>,
+}
+
+/// A device that libpcap can capture on.
+#[derive(Clone, Debug)]
+pub struct Device {
+    pub name: String,
+    pub description: Option<String>,
+    pub addresses: Vec<DeviceAddress>,
+    /// The PCAP_IF_ flags of the device.
+    pub flags: u32,
+}
+
+impl Address {
+    /// Reads an address from a socket address, which may be null.
+    unsafe fn from_sockaddr(raw: *const pcap_c::sockaddr) -> Option<Address> {
+        let sa = raw.as_ref()?;
+        Some(match sa.sa_family as libc::c_int {
+            libc::AF_INET => {
+                let sin = &*(raw as *const libc::sockaddr_in);
+                // s_addr is stored in network order, so its bytes are the octets of the address.
+                Address::V4(std::net::Ipv4Addr::from(sin.sin_addr.s_addr.to_ne_bytes()))
+            }
+            libc::AF_INET6 => {
+                let sin6 = &*(raw as *const libc::sockaddr_in6);
+                Address::V6(std::net::Ipv6Addr::from(sin6.sin6_addr.s6_addr))
+            }
+            libc::AF_PACKET => {
+                let sll = &*(raw as *const libc::sockaddr_ll);
+                Address::Link(sll.sll_addr[..std::cmp::min(sll.sll_halen as usize, 8)].to_vec())
+            }
+            family => Address::Other(family as u16),
+        })
+    }
+}
+
+impl std::fmt::Display for Address {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Address::V4(a) => write!(f, "{}", a),
+            Address::V6(a) => write!(f, "{}", a),
+            Address::Link(a) => sll::fmt_addr(f, a),
+            Address::Other(family) => write!(f, "<family {}>", family),
+        }
+    }
+}
+
+impl Device {
+    pub fn is_loopback(&self) -> bool {
+        self.flags & pcap_c::PCAP_IF_LOOPBACK != 0
+    }
+
+    pub fn is_up(&self) -> bool {
+        self.flags & pcap_c::PCAP_IF_UP != 0
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.flags & pcap_c::PCAP_IF_RUNNING != 0
+    }
+
+    pub fn is_wireless(&self) -> bool {
+        self.flags & pcap_c::PCAP_IF_WIRELESS != 0
+    }
+
+    /// The device's MAC address, if it has an Ethernet address.
+    pub fn mac(&self) -> Option<[u8; HW_ADDR_LEN]> {
+        self.addresses.iter().find_map(|a| match a.addr {
+            Address::Link(ref m) => m.as_slice().try_into().ok(),
+            _ => None,
+        })
+    }
+
+    /// The device's first IPv4 address.
+    pub fn ipv4(&self) -> Option<[u8; 4]> {
+        self.addresses.iter().find_map(|a| match a.addr {
+            Address::V4(i) => Some(i.octets()),
+            _ => None,
+        })
+    }
+
+    /// The addresses that the device has on an Ethernet network, if it has both a MAC address and an IPv4 address.
+    pub fn host(&self) -> Option<neighbors::Host> {
+        Some(neighbors::Host {
+            mac: self.mac()?,
+            ip: self.ipv4()?,
+        })
+    }
+
+    /// The netmask of the device's first IPv4 address, in the form that pcap_compile expects.
+    pub fn ipv4_netmask(&self) -> Option<pcap_c::bpf_u_int32> {
+        self.addresses
+            .iter()
+            .find_map(|a| match (&a.addr, &a.netmask) {
+                (Address::V4(_), Some(Address::V4(mask))) => {
+                    Some(pcap_c::bpf_u_int32::from_ne_bytes(mask.octets()))
+                }
+                _ => None,
+            })
+    }
+}
+
+impl std::fmt::Display for Device {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}", self.name)?;
+        if let Some(ref d) = self.description {
+            write!(f, " ({})", d)?;
+        }
+        let states: Vec<&str> = [
+            (self.is_up(), "up"),
+            (self.is_running(), "running"),
+            (self.is_loopback(), "loopback"),
+            (self.is_wireless(), "wireless"),
+        ]
+        .iter()
+        .filter(|s| s.0)
+        .map(|s| s.1)
+        .collect();
+        write!(f, " [{}]", states.join(", "))?;
+        for a in self.addresses.iter() {
+            write!(
+                f,
+                "\n    {} {}",
+                match a.addr {
+                    Address::V4(_) => "inet",
+                    Address::V6(_) => "inet6",
+                    Address::Link(_) => "link",
+                    Address::Other(_) => "other",
+                },
+                a.addr
+            )?;
+            if let Some(ref m) = a.netmask {
+                write!(f, " netmask {}", m)?;
+            }
+            if let Some(ref b) = a.broadcast {
+                write!(f, " broadcast {}", b)?;
+            }
+            if let Some(ref d) = a.destination {
+                write!(f, " destination {}", d)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Lists the devices that libpcap can capture on.
+pub fn find_all() -> Result<Vec<Device>, pcap::Error> {
+    let mut err_buf = [0 as libc::c_char; pcap_c::PCAP_ERRBUF_SIZE as usize];
+    let mut list: *mut pcap_c::pcap_if_t = std::ptr::null_mut();
+    unsafe {
+        if pcap_c::pcap_findalldevs(&mut list, err_buf.as_mut_ptr()) != 0 {
+            return Err(pcap::Error::new(
+                pcap::Operation::FindDevices,
+                &std::ffi::CStr::from_ptr(err_buf.as_ptr()).to_string_lossy(),
+            ));
+        }
+        let mut devices = Vec::new();
+        let mut current = list;
+        while let Some(dev) = current.as_ref() {
+            let mut addresses = Vec::new();
+            let mut addr_ptr = dev.addresses;
+            while let Some(a) = addr_ptr.as_ref() {
+                if let Some(addr) = Address::from_sockaddr(a.addr) {
+                    addresses.push(DeviceAddress {
+                        addr,
+                        netmask: Address::from_sockaddr(a.netmask),
+                        broadcast: Address::from_sockaddr(a.broadaddr),
+                        destination: Address::from_sockaddr(a.dstaddr),
+                    });
+                }
+                addr_ptr = a.next;
+            }
+            devices.push(Device {
+                name: std::ffi::CStr::from_ptr(dev.name)
+                    .to_string_lossy()
+                    .into_owned(),
+                description: match dev.description.is_null() {
+                    true => None,
+                    false => Some(
+                        std::ffi::CStr::from_ptr(dev.description)
+                            .to_string_lossy()
+                            .into_owned(),
+                    ),
+                },
+                addresses,
+                flags: dev.flags,
+            });
+            current = dev.next;
+        }
+        // The list is owned by libpcap, so everything that is needed has been copied out of it before it is freed.
+        pcap_c::pcap_freealldevs(list);
+        Ok(devices)
+    }
+}
+
+/// Looks for a device with the given name.
+pub fn find(name: &str) -> Result<Option<Device>, pcap::Error> {
+    Ok(find_all()?.into_iter().find(|d| d.name == name))
+}
@@ -1,14 +1,45 @@
+/// Captures from memory-mapped AF_PACKET rings on Linux, without going through libpcap.
+pub mod af_packet;
+/// Lists the devices that can be captured on.
+#[cfg(feature = "libpcap")]
+pub mod devices;
+/// An in-memory wire that stands in for real interfaces in tests, so that agents can run without root or real devices.
+#[cfg(test)]
+pub mod memory;
 /// A simple control interface that wraps an [tokio::sync::mpsc](MPSC channel)
 pub mod mpsc;
+/// Resolves the MAC addresses of IPv4 neighbors through ARP, and remembers them for a while.
+pub mod neighbors;
 /// The initial control interface that extracts packets from libpcap.
 pub mod pcap;
 /// An interface that splits a pcap handle into a recieving component and an injection component.
 pub mod pcap_cloud;
+/// Reads and writes pcapng files, which can hold packets from several interfaces.
+pub mod pcapng;
 /// Extracts data from specific protocol stacks.
 pub mod stack;
 /// Tunnels can encode some protocols within others.
 pub mod tunnels;
+/// TUN and TAP devices, which exchange frames with the kernel's network stack directly.
+pub mod tuntap;
 
 use tokio_stream::Stream;
 
-pub trait ControlInterface<O>: Stream<Item = O> {}
+/// A source of captured packets that packets can also be injected into, such as a libpcap handle or an in-memory wire.
+pub trait PacketInterface:
+    Stream<Item = Result<(std::time::SystemTime, pcap::Data), pcap::CaptureError>> + Unpin
+{
+    /// The name of the interface, if it has one.
+    fn name(&self) -> Option<&str>;
+    /// The LINKTYPE_ value of the frames that are captured and injected.
+    fn link_type(&self) -> i32;
+    /// The maximal number of bytes that are captured from each packet.
+    fn snaplen(&self) -> i32;
+    fn inject(&mut self, data: pcap::Data) -> Result<(), pcap::Error>;
+    /// Injects a frame that has already been written to a buffer.
+    fn inject_buffer(&mut self, raw: &[u8]) -> Result<(), pcap::Error>;
+    /// Only captures the packets that match a BPF expression.
+    fn set_filter(&mut self, code: &str) -> Result<(), pcap::Error>;
+    fn set_direction(&mut self, direction: pcap::Direction) -> Result<(), pcap::Error>;
+    fn stats(&mut self) -> Result<pcap::Stats, pcap::Error>;
+}
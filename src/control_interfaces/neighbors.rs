@@ -0,0 +1,277 @@
+use super::{pcap, PacketInterface};
+use crate::protocols::linklayer::{self, ethernet::HW_ADDR_LEN};
+use crate::protocols::networklayer::{self, arp};
+
+use std::collections::HashMap;
+use tokio_stream::StreamExt;
+
+/// The time for which a learned address is trusted. Linux keeps entries reachable for a similar time.
+pub const MAX_AGE: std::time::Duration = std::time::Duration::from_secs(60);
+
+/// The number of requests that are sent for an address before giving up.
+const RESOLVE_ATTEMPTS: u32 = 3;
+
+/// The time to wait for a reply to each request.
+const RESOLVE_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(1);
+
+const BROADCAST: [u8; HW_ADDR_LEN] = [0xff; HW_ADDR_LEN];
+
+#[derive(Debug)]
+pub enum Error {
+    /// Nobody answered the requests for an address.
+    Unresolved([u8; 4]),
+    /// A request could not be injected.
+    Inject(pcap::Error),
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Error::Unresolved(ip) => write!(
+                f,
+                "{}.{}.{}.{} did not answer ARP requests",
+                ip[0], ip[1], ip[2], ip[3]
+            ),
+            Error::Inject(e) => write!(f, "could not send an ARP request: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Inject(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+/// A host on the local network.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Host {
+    pub mac: [u8; HW_ADDR_LEN],
+    pub ip: [u8; 4],
+}
+
+impl Host {
+    /// Builds an ARP packet from the host in an Ethernet frame.
+    pub fn arp(self, eth_dst: [u8; HW_ADDR_LEN], action: arp::Action, target: Host) -> pcap::Data {
+        pcap::Data::LinkLayer(linklayer::Data::Ethernet(linklayer::ethernet::Frame {
+            dst: eth_dst,
+            src: self.mac,
+            tags: Vec::new(),
+            next: networklayer::Data::Arp(arp::Packet {
+                hw_addr: arp::LLAddressPair::Ethernet(self.mac, target.mac),
+                sw_addr: arp::NLAddressPair::Ipv4(self.ip, target.ip),
+                action,
+            }),
+        }))
+    }
+}
+
+struct Entry {
+    mac: [u8; HW_ADDR_LEN],
+    learned: std::time::Instant,
+}
+
+/// The IPv4 neighbors of a host on an Ethernet network, which are learned from ARP traffic.
+pub struct Table {
+    /// The addresses that requests are sent from.
+    me: Host,
+    entries: std::sync::Mutex<HashMap<[u8; 4], Entry>>,
+}
+
+impl Table {
+    pub fn new(me: Host) -> Table {
+        Table {
+            me,
+            entries: std::sync::Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Learns the sender of an ARP packet, if the frame carries one. Requests, replies and gratuitous ARPs all announce their sender.
+    /// Returns whether an address has been learned.
+    pub fn learn(&self, data: &pcap::Data) -> bool {
+        let parsed;
+        let data = match data {
+            pcap::Data::Raw(r) => match r.parse() {
+                Ok(d) => {
+                    parsed = d;
+                    &parsed
+                }
+                Err(_) => return false,
+            },
+            d => d,
+        };
+        let (mac, ip) = match data {
+            pcap::Data::LinkLayer(linklayer::Data::Ethernet(linklayer::ethernet::Frame {
+                next:
+                    networklayer::Data::Arp(arp::Packet {
+                        hw_addr: arp::LLAddressPair::Ethernet(mac, _),
+                        sw_addr: arp::NLAddressPair::Ipv4(ip, _),
+                        ..
+                    }),
+                ..
+            })) => (*mac, *ip),
+            _ => return false,
+        };
+        // Probes have no sender address, and the frames of the host itself may be captured as well.
+        if ip == [0; 4] || mac == self.me.mac {
+            return false;
+        }
+        self.entries.lock().unwrap().insert(
+            ip,
+            Entry {
+                mac,
+                learned: std::time::Instant::now(),
+            },
+        );
+        true
+    }
+
+    /// Looks for an address that has been learned recently. Entries that have aged are dropped.
+    pub fn lookup(&self, ip: [u8; 4]) -> Option<[u8; HW_ADDR_LEN]> {
+        let mut entries = self.entries.lock().unwrap();
+        match entries.get(&ip) {
+            Some(e) if e.learned.elapsed() < MAX_AGE => Some(e.mac),
+            Some(_) => {
+                entries.remove(&ip);
+                None
+            }
+            None => None,
+        }
+    }
+
+    /// Builds a broadcast request for an address.
+    pub fn request(&self, ip: [u8; 4]) -> pcap::Data {
+        self.me.arp(
+            BROADCAST,
+            arp::Action::Request,
+            Host {
+                mac: [0; HW_ADDR_LEN],
+                ip,
+            },
+        )
+    }
+
+    /// Resolves an address by injecting requests into an interface and reading its frames directly, before the interface is used for anything else.
+    /// The frames that are read while waiting are only used to learn addresses.
+    pub async fn resolve_on<I: PacketInterface>(
+        &self,
+        interface: &mut I,
+        ip: [u8; 4],
+    ) -> Result<[u8; HW_ADDR_LEN], Error> {
+        for _ in 0..RESOLVE_ATTEMPTS {
+            if let Some(mac) = self.lookup(ip) {
+                return Ok(mac);
+            }
+            if let Err(e) = interface.inject(self.request(ip)) {
+                return Err(Error::Inject(e));
+            }
+            let deadline = tokio::time::Instant::now() + RESOLVE_TIMEOUT;
+            while let Ok(Some(p)) = tokio::time::timeout_at(deadline, interface.next()).await {
+                if let Ok((_, ref d)) = p {
+                    if self.learn(d) {
+                        if let Some(mac) = self.lookup(ip) {
+                            return Ok(mac);
+                        }
+                    }
+                }
+            }
+        }
+        Err(Error::Unresolved(ip))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::control_interfaces::memory;
+    use crate::pcap_c;
+
+    const ME: Host = Host {
+        mac: [2, 0, 0, 0, 0, 1],
+        ip: [10, 0, 0, 1],
+    };
+    const PEER: Host = Host {
+        mac: [2, 0, 0, 0, 0, 2],
+        ip: [10, 0, 0, 2],
+    };
+
+    #[test]
+    fn learns_senders() {
+        let table = Table::new(ME);
+        assert!(table.learn(&PEER.arp(ME.mac, arp::Action::Response, ME)));
+        assert_eq!(table.lookup(PEER.ip), Some(PEER.mac));
+
+        // Frames that are parsed only when they are needed are learned from as well.
+        let other = Host {
+            mac: [2, 0, 0, 0, 0, 3],
+            ip: [10, 0, 0, 3],
+        };
+        let buf = other
+            .arp(BROADCAST, arp::Action::Request, ME)
+            .into_buffer((0, 0));
+        let raw = pcap::Data::Raw(pcap::RawFrame {
+            link_type: pcap_c::DLT_EN10MB,
+            caplen: buf.len() as u32,
+            original_len: buf.len() as u32,
+            bytes: buf.into(),
+        });
+        assert!(table.learn(&raw));
+        assert_eq!(table.lookup(other.ip), Some(other.mac));
+        assert_eq!(table.lookup([10, 0, 0, 4]), None);
+    }
+
+    #[test]
+    fn ignores_probes_and_its_own_frames() {
+        let table = Table::new(ME);
+        let probe = Host {
+            mac: PEER.mac,
+            ip: [0; 4],
+        };
+        assert!(!table.learn(&probe.arp(BROADCAST, arp::Action::Request, PEER)));
+        assert!(!table.learn(&table.request(PEER.ip)));
+        assert_eq!(table.lookup([0; 4]), None);
+        assert_eq!(table.lookup(ME.ip), None);
+    }
+
+    #[tokio::test]
+    async fn resolves_through_an_interface() {
+        let (mut port, mut peer) = memory::wire(pcap_c::DLT_EN10MB, ("me", "peer"));
+        let table = Table::new(ME);
+        let answer = async {
+            // Frames that do not answer the request are skipped.
+            peer.inject(ME.arp(BROADCAST, arp::Action::Request, PEER))
+                .unwrap();
+            match peer.next().await {
+                Some(Ok((_, d))) => assert_eq!(
+                    d.into_buffer((0, 0)),
+                    table.request(PEER.ip).into_buffer((0, 0))
+                ),
+                p => panic!("{:?}", p.map(|p| p.map(|(_, d)| d))),
+            }
+            peer.inject(PEER.arp(ME.mac, arp::Action::Response, ME))
+                .unwrap();
+        };
+        let (resolved, _) = tokio::join!(table.resolve_on(&mut port, PEER.ip), answer);
+        assert_eq!(resolved.unwrap(), PEER.mac);
+
+        // Learned addresses are not requested again.
+        drop(peer);
+        assert_eq!(
+            table.resolve_on(&mut port, PEER.ip).await.unwrap(),
+            PEER.mac
+        );
+    }
+
+    #[tokio::test]
+    async fn fails_if_requests_can_not_be_sent() {
+        let (mut port, peer) = memory::wire(pcap_c::DLT_EN10MB, ("me", "peer"));
+        drop(peer);
+        match Table::new(ME).resolve_on(&mut port, PEER.ip).await {
+            Err(Error::Inject(_)) => {}
+            r => panic!("{:?}", r),
+        }
+    }
+}
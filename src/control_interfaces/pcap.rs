@@ -1,5 +1,5 @@
 use crate::pcap_c;
-use crate::protocols::linklayer;
+use crate::protocols::{linklayer, networklayer};
 use libc;
 
 #[cfg(unix)]
@@ -8,6 +8,73 @@ use tokio;
 #[derive(Debug)]
 pub enum Data {
     LinkLayer(linklayer::Data),
+    /// A frame that has been captured without being parsed.
+    Raw(RawFrame),
+}
+
+/// A frame exactly as it was captured, which can be parsed later.
+#[derive(Debug, Clone)]
+pub struct RawFrame {
+    /// The link type of the handle that captured the frame.
+    pub link_type: i32,
+    /// The number of bytes that have been captured, which may be smaller than the frame itself.
+    pub caplen: u32,
+    /// The length of the frame on the wire.
+    pub original_len: u32,
+    pub bytes: Box<[u8]>,
+}
+
+impl RawFrame {
+    /// Parses the frame according to its link type.
+    pub fn parse(&self) -> Result<Data, CaptureError> {
+        Data::from_link_type(self.link_type, &self.bytes)
+    }
+}
+
+impl Data {
+    /// Parses a raw frame according to its link type. Both DLT_ and LINKTYPE_ values are accepted (they only differ for raw IP).
+    pub fn from_link_type(link_type: i32, raw: &[u8]) -> Result<Data, CaptureError> {
+        let parsed: Result<linklayer::Data, linklayer::Error> = match link_type {
+            pcap_c::DLT_EN10MB => linklayer::ethernet::Frame::from_raw_slice(raw)
+                .map(linklayer::Data::Ethernet)
+                .map_err(linklayer::Error::from),
+            pcap_c::DLT_LINUX_SLL => linklayer::sll::Frame::from_raw_slice(raw)
+                .map(linklayer::Data::LinuxSll)
+                .map_err(linklayer::Error::from),
+            pcap_c::DLT_LINUX_SLL2 => linklayer::sll2::Frame::from_raw_slice(raw)
+                .map(linklayer::Data::LinuxSll2)
+                .map_err(linklayer::Error::from),
+            pcap_c::DLT_RAW | pcap_c::LINKTYPE_RAW | pcap_c::DLT_IPV4 | pcap_c::DLT_IPV6 => {
+                networklayer::ip::Packet::from_buffer(raw)
+                    .map(linklayer::Data::RawIp)
+                    .map_err(linklayer::Error::RawIp)
+            }
+            pcap_c::DLT_NULL | pcap_c::DLT_LOOP => {
+                linklayer::null::Frame::from_raw_slice(raw, link_type == pcap_c::DLT_LOOP)
+                    .map(linklayer::Data::Null)
+                    .map_err(linklayer::Error::from)
+            }
+            pcap_c::DLT_PPP => linklayer::ppp::Frame::from_raw_slice(raw)
+                .map(linklayer::Data::Ppp)
+                .map_err(linklayer::Error::from),
+            t => Ok(linklayer::Data::Unknown(t, raw.into())),
+        };
+        match parsed {
+            Ok(d) => Ok(Data::LinkLayer(d)),
+            Err(e) => Err(CaptureError::LinkLayer(e)),
+        }
+    }
+    pub fn into_buffer(&self, offset_needed: (usize, usize)) -> Vec<u8> {
+        match self {
+            Data::LinkLayer(f) => f.into_buffer(offset_needed),
+            Data::Raw(r) => {
+                let mut out = vec![0; offset_needed.0];
+                out.extend_from_slice(&r.bytes);
+                out.resize(out.len() + offset_needed.1, 0);
+                out
+            }
+        }
+    }
 }
 
 #[derive(Clone, Copy)]
@@ -36,6 +103,17 @@ impl<'a> std::fmt::Display for PrintableData<'a> {
                         data: frame
                     }
                 ),
+                &Data::Raw(ref raw) => {
+                    write!(
+                        f,
+                        "Raw frame (link type {}, {} of {} bytes): ",
+                        raw.link_type, raw.caplen, raw.original_len
+                    )?;
+                    for b in raw.bytes.iter() {
+                        write!(f, "{:02x}", b)?;
+                    }
+                    Ok(())
+                }
             },
         }
     }
@@ -57,178 +135,488 @@ impl std::fmt::Display for PrintableDataOwned {
 #[derive(Debug)]
 pub enum CaptureError {
     LinkLayer(linklayer::Error),
-    CouldNotCapture,
-    CouldNotReadSelectableFd,
+    CouldNotCapture(Error),
+    CouldNotReadSelectableFd(std::io::Error),
     CouldNotCaptureAfterFdReady,
+    /// A frame could not be read from a device that is not backed by libpcap.
+    CouldNotRead(std::io::Error),
+}
+
+impl std::fmt::Display for CaptureError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            CaptureError::LinkLayer(e) => write!(f, "could not parse frame: {}", e),
+            CaptureError::CouldNotCapture(e) => write!(f, "{}", e),
+            CaptureError::CouldNotReadSelectableFd(e) => {
+                write!(
+                    f,
+                    "could not wait for the selectable file descriptor: {}",
+                    e
+                )
+            }
+            CaptureError::CouldNotCaptureAfterFdReady => {
+                write!(
+                    f,
+                    "no packet was available after the handle became readable"
+                )
+            }
+            CaptureError::CouldNotRead(e) => write!(f, "could not read a frame: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for CaptureError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            CaptureError::LinkLayer(e) => Some(e),
+            CaptureError::CouldNotCapture(e) => Some(e),
+            CaptureError::CouldNotReadSelectableFd(e) => Some(e),
+            CaptureError::CouldNotRead(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+/// The operations that can fail when working with libpcap.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Operation {
+    Create,
+    Configure,
+    Activate,
+    OpenOffline,
+    SetNonBlocking,
+    GetSelectableFd,
+    Capture,
+    Inject,
+    CompileFilter,
+    ApplyFilter,
+    SetDirection,
+    Stats,
+    OpenSavefile,
+    FlushSavefile,
+    FindDevices,
+}
+
+/// An error that has been reported by libpcap (or that occured while preparing a call to it).
+#[derive(Debug)]
+pub struct Error {
+    pub operation: Operation,
+    /// The status code returned by libpcap, if it returned one.
+    pub code: Option<i32>,
+    /// The description of the error, as given by pcap_geterr, pcap_statustostr or an error buffer.
+    pub message: String,
+    /// What the operation was performed on (e.g. a device or a file).
+    pub context: Option<String>,
+}
+
+impl Error {
+    pub fn new(operation: Operation, message: &str) -> Error {
+        Error {
+            operation,
+            code: None,
+            message: String::from(message),
+            context: None,
+        }
+    }
+
+    /// Describes the last error of a handle using pcap_geterr.
+    #[cfg(feature = "libpcap")]
+    unsafe fn from_handle(
+        operation: Operation,
+        handle_ptr: *mut pcap_c::pcap_t,
+        code: libc::c_int,
+    ) -> Error {
+        Error {
+            operation,
+            code: Some(code),
+            message: std::ffi::CStr::from_ptr(pcap_c::pcap_geterr(handle_ptr))
+                .to_string_lossy()
+                .into_owned(),
+            context: None,
+        }
+    }
+
+    /// Describes a status code using pcap_statustostr.
+    #[cfg(feature = "libpcap")]
+    unsafe fn from_status(operation: Operation, code: libc::c_int) -> Error {
+        Error {
+            operation,
+            code: Some(code),
+            message: std::ffi::CStr::from_ptr(pcap_c::pcap_statustostr(code))
+                .to_string_lossy()
+                .into_owned(),
+            context: None,
+        }
+    }
+
+    /// Reads the description of an error from an error buffer that has been filled by libpcap.
+    #[cfg(feature = "libpcap")]
+    fn from_err_buf(operation: Operation, err_buf: &[libc::c_char]) -> Error {
+        Error {
+            operation,
+            code: None,
+            message: unsafe { std::ffi::CStr::from_ptr(err_buf.as_ptr()) }
+                .to_string_lossy()
+                .into_owned(),
+            context: None,
+        }
+    }
+
+    pub fn with_context(mut self, context: &str) -> Error {
+        self.context = Some(String::from(context));
+        self
+    }
+}
+
+impl std::fmt::Display for Operation {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                Operation::Create => "could not create handle",
+                Operation::Configure => "could not configure handle",
+                Operation::Activate => "could not activate handle",
+                Operation::OpenOffline => "could not open capture file",
+                Operation::SetNonBlocking => "could not set non-blocking mode",
+                Operation::GetSelectableFd => "could not get selectable file descriptor",
+                Operation::Capture => "could not capture",
+                Operation::Inject => "could not inject packet",
+                Operation::CompileFilter => "could not compile filter",
+                Operation::ApplyFilter => "could not apply filter",
+                Operation::SetDirection => "could not set capture direction",
+                Operation::Stats => "could not read statistics",
+                Operation::OpenSavefile => "could not open savefile",
+                Operation::FlushSavefile => "could not write to savefile",
+                Operation::FindDevices => "could not list devices",
+            }
+        )
+    }
 }
 
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}", self.operation)?;
+        if let Some(ref c) = self.context {
+            write!(f, " ({})", c)?;
+        }
+        write!(f, ": {}", self.message)?;
+        if let Some(code) = self.code {
+            write!(f, " [code {}]", code)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// The directions of the packets that a handle captures.
 #[allow(dead_code)]
+#[derive(Clone, Copy)]
+pub enum Direction {
+    InOut,
+    In,
+    Out,
+}
+
+#[allow(dead_code)]
+#[derive(Clone, Copy)]
 pub enum CaptureMode {
     NonPromisc,
     Promisc,
 }
 
+/// The precision of the timestamps that libpcap attaches to captured packets.
+#[cfg(feature = "libpcap")]
+#[derive(Clone, Copy)]
+pub enum TimestampPrecision {
+    Micro,
+    Nano,
+}
+
+/// The source of the timestamps that libpcap attaches to captured packets. Not every device supports every source.
+#[cfg(feature = "libpcap")]
+#[allow(dead_code)]
+#[derive(Clone, Copy)]
+pub enum TimestampType {
+    Host,
+    HostLowPrec,
+    HostHiPrec,
+    Adapter,
+    AdapterUnsynced,
+}
+
+/// Configures a capture handle before it is activated.
+#[cfg(feature = "libpcap")]
+pub struct Builder {
+    dev: String,
+    mode: CaptureMode,
+    snaplen: i32,
+    timeout_ms: i32,
+    /// The size of the kernel buffer. The platform's default is used if no size is given.
+    buffer_size: Option<i32>,
+    /// Whether packets are delivered as soon as they arrive, instead of being batched until the timeout expires.
+    immediate: bool,
+    precision: TimestampPrecision,
+    tstamp_type: Option<TimestampType>,
+    batch_size: i32,
+    raw: bool,
+}
+
+/// The number of packets that are read from libpcap at once, unless configured otherwise.
+/// Reading several packets whenever the handle is ready saves a round trip through tokio for every packet.
+#[cfg(feature = "libpcap")]
+pub const DEFAULT_BATCH_SIZE: i32 = 64;
+
+#[cfg(feature = "libpcap")]
 enum State {
     Idle,
     WaitingForFd,
 }
 
+#[cfg(feature = "libpcap")]
 #[allow(dead_code)]
 pub struct CaptureHandle {
     /// The action pcap_t that contains the handle.
     handle: std::sync::atomic::AtomicPtr<pcap_c::pcap_t>,
     /// The file descriptor that will be used to perform asynchronous reading from libpcap.
-    selectable_fd: tokio::io::unix::AsyncFd<std::os::unix::io::RawFd>,
+    /// Offline handles have no such descriptor, as reading from a file never blocks.
+    selectable_fd: Option<tokio::io::unix::AsyncFd<std::os::unix::io::RawFd>>,
     /// The current state of the handle. This will be used in order to perform asynchronous reads from libpcap.
     state: State,
     /// The mode at which the handle operates.
     mode: CaptureMode,
-    /// the snaplen parameter that has been passed to open_live.
+    /// the snaplen that the handle has been opened with.
     snaplen: i32,
-    /// The timeout parameter that has been passed to open_live.
+    /// The timeout that the handle has been opened with.
     timeout_ms: i32,
     /// the frame type that the handle listens for.
     frame_type_code: i32,
+    /// The precision of the timestamps that libpcap provides.
+    precision: TimestampPrecision,
+    /// The name of the captured device. Offline handles have no device.
+    device: Option<String>,
+    /// The maximal number of packets that are read from libpcap at once.
+    batch_size: i32,
+    /// Whether captured frames are returned as raw data, to be parsed on demand.
+    raw: bool,
+    /// Packets that have been read from libpcap but have not been returned yet.
+    queue: std::collections::VecDeque<Result<(std::time::SystemTime, Data), CaptureError>>,
+    /// A savefile that every captured packet is written to before it is parsed.
+    savefile: Option<Savefile>,
+    /// The warning that libpcap reported when the handle was activated, if any.
+    warning: Option<Error>,
+}
+
+/// Packet counts that libpcap keeps for a live handle, from the moment it has been activated.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Stats {
+    /// The number of packets that have been received, including ones that have been dropped.
+    pub received: u32,
+    /// The number of packets that have been dropped because the buffer was full.
+    pub dropped: u32,
+    /// The number of packets that have been dropped by the network interface or its driver.
+    pub if_dropped: u32,
+}
+
+impl std::fmt::Display for Stats {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "{} received, {} dropped, {} dropped by interface",
+            self.received, self.dropped, self.if_dropped
+        )
+    }
 }
 
-pub enum FilterErr {
-    InvalidFilter,
-    CouldNotApply,
+/// A file that raw captured packets are written to using libpcap's savefile format.
+#[cfg(feature = "libpcap")]
+pub struct Savefile {
+    /// The dumper returned by pcap_dump_open.
+    dumper: std::sync::atomic::AtomicPtr<pcap_c::pcap_dumper_t>,
 }
 
+/// The state that is passed to the libpcap callback through its `user` parameter.
+#[cfg(feature = "libpcap")]
+struct Dispatch<'a> {
+    /// The queue that captured packets are appended to.
+    out: &'a mut std::collections::VecDeque<Result<(std::time::SystemTime, Data), CaptureError>>,
+    /// The dumper that raw packets are written to, or NULL if the handle is not recording.
+    dumper: *mut pcap_c::pcap_dumper_t,
+    /// The DLT_ value that determines how captured frames are parsed.
+    link_type: i32,
+    /// The precision of the timestamps in the packet headers.
+    precision: TimestampPrecision,
+    /// Whether frames are kept as they are instead of being parsed.
+    raw: bool,
+}
+
+/// A compiled BPF program. The program is freed once the filter is dropped.
+#[cfg(feature = "libpcap")]
 pub struct Filter {
     bpf_prog: pcap_c::bpf_program,
 }
 
+// The program is owned by the filter alone, and libpcap never modifies it after compilation.
+#[cfg(feature = "libpcap")]
+unsafe impl Send for Filter {}
+
+/// BPF programs are compiled by libpcap, so there are no filters without it.
+#[cfg(not(feature = "libpcap"))]
+pub enum Filter {}
+
+#[cfg(feature = "libpcap")]
 impl CaptureHandle {
     /// This method opens a device using libpcap and registers a selectable file descriptor for asynchronous operations.
+    #[allow(dead_code)]
     pub fn open_live(
         dev: &str,
         mode: CaptureMode,
         timeout_ms: i32,
         snaplen: i32,
-    ) -> Result<CaptureHandle, String> {
-        let handle: *mut pcap_c::pcap_t;
-        if let Ok(err_buf) = std::ffi::CString::new([1; pcap_c::PCAP_ERRBUF_SIZE as usize]) {
-            if let Ok(dev_name) = std::ffi::CString::new(dev) {
-                unsafe {
-                    handle = pcap_c::pcap_open_live(
-                        dev_name.as_ptr(),
-                        snaplen,
-                        match mode {
-                            CaptureMode::Promisc => 1,
-                            CaptureMode::NonPromisc => 0,
-                        },
-                        timeout_ms,
-                        err_buf.as_ptr(),
-                    );
-                    match handle.as_mut() {
-                        None => match err_buf.into_string() {
-                            Err(_) => {
-                                return Err(String::from("Could not read error from libcap!"));
-                            }
-                            Ok(s) => {
-                                return Err(s);
-                            }
-                        },
-                        Some(x) => {
-                            match pcap_c::pcap_setnonblock(x, 1, err_buf.as_ptr() as *mut i8) {
-                                pcap_c::PCAP_ERROR => {
-                                    return Err(match err_buf.into_string() {
-                                        Err(_) => String::from("Could not read error from libcap!"),
-                                        Ok(s) => s,
-                                    })
-                                }
-                                _ => {
-                                    return match pcap_c::pcap_get_selectable_fd(x) {
-                                        -1 => Err(String::from("Could not get FD!")),
-                                        fd => Ok(CaptureHandle {
-                                            handle: std::sync::atomic::AtomicPtr::new(x),
-                                            state: State::Idle,
-                                            mode,
-                                            snaplen,
-                                            timeout_ms,
-                                            frame_type_code: pcap_c::pcap_datalink(handle),
-                                            selectable_fd: match tokio::io::unix::AsyncFd::new(fd) {
-                                                Err(_) => {
-                                                    return Err(String::from(
-                                                        "Could not read selectable fd!",
-                                                    ));
-                                                }
-                                                Ok(s) => s,
-                                            },
-                                        }),
-                                    }
-                                }
-                            };
-                        }
-                    }
+    ) -> Result<CaptureHandle, Error> {
+        CaptureHandle::builder(dev)
+            .mode(mode)
+            .timeout_ms(timeout_ms)
+            .snaplen(snaplen)
+            .activate()
+    }
+
+    /// Starts configuring a handle for the given device. The configuration is applied once the handle is activated.
+    pub fn builder(dev: &str) -> Builder {
+        Builder {
+            dev: String::from(dev),
+            mode: CaptureMode::Promisc,
+            snaplen: 65535,
+            timeout_ms: 100,
+            buffer_size: None,
+            immediate: false,
+            precision: TimestampPrecision::Micro,
+            tstamp_type: None,
+            batch_size: DEFAULT_BATCH_SIZE,
+            raw: false,
+        }
+    }
+
+    /// This method opens a savefile (a .pcap file) using libpcap. Packets are read from the file in order and keep the timestamps that were recorded in it.
+    pub fn open_offline(path: &str) -> Result<CaptureHandle, Error> {
+        let mut err_buf = [0 as libc::c_char; pcap_c::PCAP_ERRBUF_SIZE as usize];
+        let file_name = match std::ffi::CString::new(path) {
+            Ok(f) => f,
+            Err(_) => {
+                return Err(Error::new(
+                    Operation::OpenOffline,
+                    "the file name contains a NUL byte",
+                )
+                .with_context(path));
+            }
+        };
+        unsafe {
+            // Timestamps are read with the highest precision so that files with nanosecond timestamps keep them.
+            match pcap_c::pcap_open_offline_with_tstamp_precision(
+                file_name.as_ptr(),
+                pcap_c::PCAP_TSTAMP_PRECISION_NANO,
+                err_buf.as_mut_ptr(),
+            )
+            .as_mut()
+            {
+                None => {
+                    Err(Error::from_err_buf(Operation::OpenOffline, &err_buf).with_context(path))
                 }
-            } else {
-                return Err(String::from("Could not read device name as a C string!"));
+                Some(x) => Ok(CaptureHandle {
+                    handle: std::sync::atomic::AtomicPtr::new(x),
+                    state: State::Idle,
+                    mode: CaptureMode::NonPromisc,
+                    snaplen: pcap_c::pcap_snapshot(x),
+                    timeout_ms: 0,
+                    frame_type_code: pcap_c::pcap_datalink(x),
+                    precision: TimestampPrecision::Nano,
+                    device: None,
+                    batch_size: DEFAULT_BATCH_SIZE,
+                    raw: false,
+                    queue: std::collections::VecDeque::new(),
+                    // Savefiles are always readable, so they are read without waiting on a file descriptor.
+                    selectable_fd: None,
+                    savefile: None,
+                    warning: None,
+                }),
             }
-        } else {
-            return Err(String::from("Internal error"));
         }
     }
-    /// Asynchronously reads a packet from libpcap.
-    pub async fn next(&mut self) -> Result<(std::time::SystemTime, Data), CaptureError> {
-        let mut res: Result<(std::time::SystemTime, Data), CaptureError> =
-            Err(CaptureError::CouldNotCapture);
+
+    /// Reads at most `batch_size` packets from libpcap into the queue and returns the value returned by `pcap_dispatch`.
+    fn dispatch(&mut self) -> libc::c_int {
         let handle_ptr: *mut pcap_c::pcap_t = *self.handle.get_mut();
-        match unsafe {
+        let mut dispatch = Dispatch {
+            out: &mut self.queue,
+            dumper: match self.savefile {
+                Some(ref mut f) => *f.dumper.get_mut(),
+                None => std::ptr::null_mut(),
+            },
+            link_type: self.frame_type_code,
+            precision: self.precision,
+            raw: self.raw,
+        };
+        unsafe {
             pcap_c::pcap_dispatch(
                 handle_ptr,
-                1,
-                handle_packet_ethernet,
-                ((&mut res) as *mut Result<(std::time::SystemTime, Data), CaptureError>) as *mut u8,
+                self.batch_size,
+                handle_packet,
+                // The user parameter is used in the callback to determine the location of the output.
+                (&mut dispatch as *mut Dispatch) as *mut u8,
             )
-        } {
-	    //If libpcap returned data immediately, then it can be returned without waiting.
-            1 => {
-                return res;
-            }
-            0 => {}
-            _ => {
-                return Err(CaptureError::CouldNotCapture);
-            }
         }
+    }
 
-	//The file descriptor indicates when libpcap recieves a packet.
-        match self.selectable_fd.readable().await {
-            Ok(mut s) => {
-		// Clear the file descriptor so that later reads can be performed.
-                s.clear_ready();
-                match unsafe {
-                    pcap_c::pcap_dispatch(
-                        handle_ptr,
-                        1,
-                        handle_packet_ethernet,
-			// The user parameter is used in the callback to determine the location of the output.
-                        ((&mut res) as *mut Result<(std::time::SystemTime, Data), CaptureError>)
-                            as *mut u8,
-                    )
-                } {
-                    1 => {
-                        return res;
-                    }
-		    //This case should not be reached. It indicates that pcap signaled the program that data is available but none has been found.
-                    _ => {
-                        return Err(CaptureError::CouldNotCaptureAfterFdReady);
-                    }
-                }
-            }
-            Err(_) => {
-                return Err(CaptureError::CouldNotReadSelectableFd);
-            }
+    /// Describes an error that has been returned by `pcap_dispatch`.
+    fn capture_error(&mut self, code: libc::c_int) -> CaptureError {
+        let handle_ptr: *mut pcap_c::pcap_t = *self.handle.get_mut();
+        CaptureError::CouldNotCapture(unsafe {
+            Error::from_handle(Operation::Capture, handle_ptr, code)
+        })
+    }
+
+    /// Determines whether frames are returned exactly as they were captured (to be parsed on demand), or parsed as they are captured.
+    pub fn set_raw(&mut self, raw: bool) {
+        self.raw = raw;
+    }
+
+    /// Starts writing every captured packet to `savefile`, replacing (and returning) the savefile that was previously used.
+    /// Packets are written exactly as they were captured, even if they can not be parsed.
+    pub fn set_savefile(&mut self, savefile: Option<Savefile>) -> Option<Savefile> {
+        std::mem::replace(&mut self.savefile, savefile)
+    }
+
+    /// The LINKTYPE_ value of the frames that the handle captures, as used in capture files.
+    pub fn link_type(&self) -> i32 {
+        match self.frame_type_code {
+            pcap_c::DLT_RAW => pcap_c::LINKTYPE_RAW,
+            t => t,
         }
     }
 
+    /// The maximal number of bytes that are captured from each packet.
+    pub fn snaplen(&self) -> i32 {
+        self.snaplen
+    }
+
+    /// The savefile that captured packets are currently written to.
+    pub fn savefile(&mut self) -> Option<&mut Savefile> {
+        self.savefile.as_mut()
+    }
+
+    /// Takes the warning that libpcap reported when the handle was activated (for example, an unsupported timestamp type).
+    pub fn take_warning(&mut self) -> Option<Error> {
+        self.warning.take()
+    }
+
     /// Injects a packet using libpcap.
-    pub fn inject(&mut self, to_inject: Data) -> Result<(), ()> {
-        let raw_thing = match to_inject {
-            Data::LinkLayer(f) => f.into_buffer((0, 0)).into_boxed_slice(),
-        };
+    pub fn inject(&mut self, to_inject: Data) -> Result<(), Error> {
+        self.inject_buffer(&to_inject.into_buffer((0, 0)))
+    }
+
+    /// Injects a frame that has already been written to a buffer.
+    pub fn inject_buffer(&mut self, raw_thing: &[u8]) -> Result<(), Error> {
         let handle_ptr: *mut pcap_c::pcap_t = *self.handle.get_mut();
         unsafe {
             match pcap_c::pcap_inject(
@@ -236,7 +624,7 @@ impl CaptureHandle {
                 raw_thing.as_ptr() as *const libc::c_void,
                 raw_thing.len(),
             ) {
-                -1 => Err(()),
+                -1 => Err(Error::from_handle(Operation::Inject, handle_ptr, -1)),
                 _ => Ok(()), //Maybe check that the needed numebr of bytes were written
             }
         }
@@ -246,130 +634,185 @@ impl CaptureHandle {
         &mut self,
         code: &str,
         netmask: Option<pcap_c::bpf_u_int32>,
-    ) -> Result<Filter, ()> {
-        let mut prog: pcap_c::bpf_program = unsafe { std::mem::zeroed() };
+    ) -> Result<Filter, Error> {
+        let handle_ptr: *mut pcap_c::pcap_t = *self.handle.get_mut();
+        unsafe { Filter::compile_with(handle_ptr, code, netmask) }
+    }
+    pub fn apply_filter(&mut self, filter: &Filter) -> Result<(), Error> {
+        let handle_ptr: *mut pcap_c::pcap_t = *self.handle.get_mut();
+        unsafe {
+            match pcap_c::pcap_setfilter(handle_ptr, &filter.bpf_prog as *const pcap_c::bpf_program)
+            {
+                0 => Ok(()),
+                code => Err(Error::from_handle(Operation::ApplyFilter, handle_ptr, code)),
+            }
+        }
+    }
+
+    /// Restricts the directions of the packets that are captured. Not every platform (nor savefiles) supports this.
+    pub fn set_direction(&mut self, direction: Direction) -> Result<(), Error> {
         let handle_ptr: *mut pcap_c::pcap_t = *self.handle.get_mut();
         unsafe {
-            match pcap_c::pcap_compile(
+            match pcap_c::pcap_setdirection(
                 handle_ptr,
-                &mut prog as *mut pcap_c::bpf_program,
-                (match std::ffi::CString::new(code) {
-                    Ok(n) => n,
-                    Err(_) => {
-                        return Err(());
-                    }
-                })
-                .into_raw(),
-                1,
-                match netmask {
-                    None => pcap_c::PCAP_NETMASK_UNKNOWN,
-                    Some(n) => n,
+                match direction {
+                    Direction::InOut => pcap_c::pcap_direction_t_PCAP_D_INOUT,
+                    Direction::In => pcap_c::pcap_direction_t_PCAP_D_IN,
+                    Direction::Out => pcap_c::pcap_direction_t_PCAP_D_OUT,
                 },
             ) {
-                0 => {}
-                _ => {
-                    return Err(());
-                }
+                0 => Ok(()),
+                code => Err(Error::from_handle(
+                    Operation::SetDirection,
+                    handle_ptr,
+                    code,
+                )),
             }
         }
-        return Ok(Filter { bpf_prog: prog });
     }
-    pub fn apply_filter(&mut self, filter: Filter) -> Result<(), ()> {
+
+    /// Reads the packet counts of the handle. Offline handles have no such counts.
+    pub fn stats(&mut self) -> Result<Stats, Error> {
         let handle_ptr: *mut pcap_c::pcap_t = *self.handle.get_mut();
-        match unsafe {
-            pcap_c::pcap_setfilter(handle_ptr, &filter.bpf_prog as *const pcap_c::bpf_program)
-        } {
-            0 => Ok(()),
-            _ => Err(()),
+        let mut raw = pcap_c::pcap_stat {
+            ps_recv: 0,
+            ps_drop: 0,
+            ps_ifdrop: 0,
+        };
+        unsafe {
+            match pcap_c::pcap_stats(handle_ptr, &mut raw) {
+                0 => Ok(Stats {
+                    received: raw.ps_recv,
+                    dropped: raw.ps_drop,
+                    if_dropped: raw.ps_ifdrop,
+                }),
+                code => Err(Error::from_handle(Operation::Stats, handle_ptr, code)),
+            }
         }
     }
 
     /// Applies a filter to a handle.
+    /// If no netmask is given, the netmask of the captured device is used (if it has an IPv4 address).
     pub fn with_filter(
         &mut self,
         code: &str,
         netmask: Option<pcap_c::bpf_u_int32>,
-    ) -> Result<(), FilterErr> {
-        match self.compile_filter_optimized(code, netmask) {
-            Ok(f) => match self.apply_filter(f) {
-                Ok(_) => Ok(()),
-                Err(_) => Err(FilterErr::CouldNotApply),
+    ) -> Result<(), Error> {
+        let netmask = netmask.or_else(|| match self.device {
+            Some(ref dev) => match super::devices::find(dev.as_str()) {
+                Ok(Some(d)) => d.ipv4_netmask(),
+                _ => None,
             },
-            Err(_) => Err(FilterErr::InvalidFilter),
-        }
+            None => None,
+        });
+        let filter = self.compile_filter_optimized(code, netmask)?;
+        self.apply_filter(&filter)
     }
 }
 
+#[cfg(feature = "libpcap")]
 use std::pin::Pin;
+#[cfg(feature = "libpcap")]
 use std::task::Poll;
+#[cfg(feature = "libpcap")]
 use tokio_stream::Stream;
 
+#[cfg(feature = "libpcap")]
+impl super::PacketInterface for CaptureHandle {
+    fn name(&self) -> Option<&str> {
+        self.device.as_deref()
+    }
+    fn link_type(&self) -> i32 {
+        CaptureHandle::link_type(self)
+    }
+    fn snaplen(&self) -> i32 {
+        CaptureHandle::snaplen(self)
+    }
+    fn inject(&mut self, data: Data) -> Result<(), Error> {
+        CaptureHandle::inject(self, data)
+    }
+    fn inject_buffer(&mut self, raw: &[u8]) -> Result<(), Error> {
+        CaptureHandle::inject_buffer(self, raw)
+    }
+    fn set_filter(&mut self, code: &str) -> Result<(), Error> {
+        self.with_filter(code, None)
+    }
+    fn set_direction(&mut self, direction: Direction) -> Result<(), Error> {
+        CaptureHandle::set_direction(self, direction)
+    }
+    fn stats(&mut self) -> Result<Stats, Error> {
+        CaptureHandle::stats(self)
+    }
+}
+
+#[cfg(feature = "libpcap")]
 impl Stream for CaptureHandle {
     type Item = Result<(std::time::SystemTime, Data), CaptureError>;
 
-    /// This method is identical in operation to the next function, but it is implemented on the type itself.
+    /// Asynchronously reads a packet from libpcap. The stream ends once an offline handle reaches the end of its file.
     fn poll_next(
         mut self: Pin<&mut Self>,
         cx: &mut std::task::Context<'_>,
     ) -> Poll<Option<Self::Item>> {
-        let mut res: Result<(std::time::SystemTime, Data), CaptureError> =
-            Err(CaptureError::CouldNotCapture);
-        let handle_ptr: *mut pcap_c::pcap_t = *self.handle.get_mut();
-        match self.state {
-            State::Idle => {
-                match unsafe {
-                    pcap_c::pcap_dispatch(
-                        handle_ptr,
-                        1,
-                        handle_packet_ethernet,
-                        ((&mut res) as *mut Result<(std::time::SystemTime, Data), CaptureError>)
-                            as *mut u8,
-                    )
-                } {
-                    1 => {
-                        return Poll::Ready(Some(res));
-                    }
-                    0 => {}
-                    _ => {
-                        return Poll::Ready(Some(Err(CaptureError::CouldNotCapture)));
-                    }
+        // Packets that have been read in a previous batch are returned first.
+        if let Some(p) = self.queue.pop_front() {
+            return Poll::Ready(Some(p));
+        }
+        if self.selectable_fd.is_none() {
+            // Reading from a savefile never blocks, and a return value of 0 indicates that the end of the file has been reached.
+            return match self.dispatch() {
+                n if n > 0 => Poll::Ready(self.queue.pop_front()),
+                0 => Poll::Ready(None),
+                code => Poll::Ready(Some(Err(self.capture_error(code)))),
+            };
+        }
+        if let State::Idle = self.state {
+            match self.dispatch() {
+                //If libpcap returned data immediately, then it can be returned without waiting.
+                n if n > 0 => {
+                    return Poll::Ready(self.queue.pop_front());
+                }
+                0 => {}
+                code => {
+                    return Poll::Ready(Some(Err(self.capture_error(code))));
                 }
             }
-            _ => {}
         }
-        match self.selectable_fd.poll_read_ready(cx) {
-            Poll::Ready(Ok(mut s)) => {
-                s.clear_ready();
+        //The file descriptor indicates when libpcap recieves a packet.
+        let ready = match self.selectable_fd {
+            Some(ref fd) => match fd.poll_read_ready(cx) {
+                Poll::Ready(Ok(mut s)) => {
+                    // Clear the file descriptor so that later reads can be performed.
+                    s.clear_ready();
+                    Poll::Ready(Ok(()))
+                }
+                Poll::Ready(Err(e)) => Poll::Ready(Err(e)),
+                Poll::Pending => Poll::Pending,
+            },
+            None => Poll::Ready(Ok(())),
+        };
+        match ready {
+            Poll::Ready(Ok(())) => {
                 self.state = State::Idle;
-                match unsafe {
-                    pcap_c::pcap_dispatch(
-                        handle_ptr,
-                        1,
-                        handle_packet_ethernet,
-                        ((&mut res) as *mut Result<(std::time::SystemTime, Data), CaptureError>)
-                            as *mut u8,
-                    )
-                } {
-                    1 => {
-                        return Poll::Ready(Some(res));
-                    }
-                    _ => {
-                        return Poll::Ready(Some(Err(CaptureError::CouldNotCaptureAfterFdReady)));
-                    }
+                match self.dispatch() {
+                    n if n > 0 => Poll::Ready(self.queue.pop_front()),
+                    //This case should not be reached. It indicates that pcap signaled the program that data is available but none has been found.
+                    _ => Poll::Ready(Some(Err(CaptureError::CouldNotCaptureAfterFdReady))),
                 }
             }
-            Poll::Ready(Err(_)) => {
+            Poll::Ready(Err(e)) => {
                 self.state = State::Idle;
-                return Poll::Ready(Some(Err(CaptureError::CouldNotReadSelectableFd)));
+                Poll::Ready(Some(Err(CaptureError::CouldNotReadSelectableFd(e))))
             }
             Poll::Pending => {
                 self.state = State::WaitingForFd;
-                return Poll::Pending;
+                Poll::Pending
             }
         }
     }
 }
 
+#[cfg(feature = "libpcap")]
 impl Drop for CaptureHandle {
     /// Used to automatically close the interface when the handle is no longer used.
     fn drop(&mut self) {
@@ -379,19 +822,395 @@ impl Drop for CaptureHandle {
     }
 }
 
-/// The handle is used to parse data recieved from libpcap and return the sanitized result.
+#[cfg(feature = "libpcap")]
+impl Builder {
+    pub fn mode(mut self, mode: CaptureMode) -> Self {
+        self.mode = mode;
+        self
+    }
+    pub fn snaplen(mut self, snaplen: i32) -> Self {
+        self.snaplen = snaplen;
+        self
+    }
+    pub fn timeout_ms(mut self, timeout_ms: i32) -> Self {
+        self.timeout_ms = timeout_ms;
+        self
+    }
+    /// Sets the size of the kernel buffer, in bytes. Larger buffers drop fewer packets under load.
+    pub fn buffer_size(mut self, buffer_size: i32) -> Self {
+        self.buffer_size = Some(buffer_size);
+        self
+    }
+    /// Delivers packets as soon as they arrive, instead of waiting for the buffer to fill or for the timeout to expire.
+    pub fn immediate_mode(mut self, immediate: bool) -> Self {
+        self.immediate = immediate;
+        self
+    }
+    pub fn tstamp_precision(mut self, precision: TimestampPrecision) -> Self {
+        self.precision = precision;
+        self
+    }
+    pub fn tstamp_type(mut self, tstamp_type: TimestampType) -> Self {
+        self.tstamp_type = Some(tstamp_type);
+        self
+    }
+
+    /// Sets the maximal number of packets that are read from libpcap whenever the handle is ready.
+    pub fn batch_size(mut self, batch_size: i32) -> Self {
+        self.batch_size = std::cmp::max(batch_size, 1);
+        self
+    }
+
+    /// Makes the handle return frames exactly as they were captured, instead of parsing them.
+    pub fn raw(mut self, raw: bool) -> Self {
+        self.raw = raw;
+        self
+    }
+
+    /// Creates the handle, applies the configuration and activates it.
+    pub fn activate(self) -> Result<CaptureHandle, Error> {
+        let mut err_buf = [0 as libc::c_char; pcap_c::PCAP_ERRBUF_SIZE as usize];
+        let dev_name = match std::ffi::CString::new(self.dev.as_str()) {
+            Ok(d) => d,
+            Err(_) => {
+                return Err(
+                    Error::new(Operation::Create, "the device name contains a NUL byte")
+                        .with_context(self.dev.as_str()),
+                );
+            }
+        };
+        unsafe {
+            let x = match pcap_c::pcap_create(dev_name.as_ptr(), err_buf.as_mut_ptr()).as_mut() {
+                Some(x) => x,
+                None => {
+                    return Err(Error::from_err_buf(Operation::Create, &err_buf)
+                        .with_context(self.dev.as_str()));
+                }
+            };
+            // These settings can only fail if the handle has already been activated, which it has not, so their results are not checked.
+            pcap_c::pcap_set_snaplen(x, self.snaplen);
+            pcap_c::pcap_set_promisc(
+                x,
+                match self.mode {
+                    CaptureMode::Promisc => 1,
+                    CaptureMode::NonPromisc => 0,
+                },
+            );
+            pcap_c::pcap_set_timeout(x, self.timeout_ms);
+            pcap_c::pcap_set_immediate_mode(x, self.immediate as libc::c_int);
+            if let Some(size) = self.buffer_size {
+                pcap_c::pcap_set_buffer_size(x, size);
+            }
+            // The timestamp type can also be refused (or accepted with a warning) by the device.
+            let mut tstamp_warning = None;
+            if let Some(t) = self.tstamp_type {
+                let status = pcap_c::pcap_set_tstamp_type(
+                    x,
+                    (match t {
+                        TimestampType::Host => pcap_c::PCAP_TSTAMP_HOST,
+                        TimestampType::HostLowPrec => pcap_c::PCAP_TSTAMP_HOST_LOWPREC,
+                        TimestampType::HostHiPrec => pcap_c::PCAP_TSTAMP_HOST_HIPREC,
+                        TimestampType::Adapter => pcap_c::PCAP_TSTAMP_ADAPTER,
+                        TimestampType::AdapterUnsynced => pcap_c::PCAP_TSTAMP_ADAPTER_UNSYNCED,
+                    }) as libc::c_int,
+                );
+                if status < 0 {
+                    let err = Error::from_status(Operation::Configure, status)
+                        .with_context(self.dev.as_str());
+                    pcap_c::pcap_close(x);
+                    return Err(err);
+                } else if status > 0 {
+                    tstamp_warning = Some(Error::from_status(Operation::Configure, status));
+                }
+            }
+            let status = pcap_c::pcap_set_tstamp_precision(
+                x,
+                (match self.precision {
+                    TimestampPrecision::Micro => pcap_c::PCAP_TSTAMP_PRECISION_MICRO,
+                    TimestampPrecision::Nano => pcap_c::PCAP_TSTAMP_PRECISION_NANO,
+                }) as libc::c_int,
+            );
+            if status != 0 {
+                let err = Error::from_status(Operation::Configure, status)
+                    .with_context(self.dev.as_str());
+                pcap_c::pcap_close(x);
+                return Err(err);
+            }
+
+            let status = pcap_c::pcap_activate(x);
+            if status < 0 {
+                // Generic errors are described by pcap_geterr, while specific errors are described by their status.
+                let err = match status {
+                    pcap_c::PCAP_ERROR => Error::from_handle(Operation::Activate, x, status),
+                    _ => Error::from_status(Operation::Activate, status),
+                }
+                .with_context(self.dev.as_str());
+                pcap_c::pcap_close(x);
+                return Err(err);
+            }
+            // Warnings (for example, an unsupported timestamp type) do not prevent the handle from capturing, so they are kept for the caller.
+            let warning = match status {
+                0 => None,
+                s if s == pcap_c::PCAP_WARNING as libc::c_int => {
+                    Some(Error::from_handle(Operation::Activate, x, status))
+                }
+                _ => Some(Error::from_status(Operation::Activate, status)),
+            }
+            .or(tstamp_warning)
+            .map(|w| w.with_context(self.dev.as_str()));
+
+            if pcap_c::pcap_setnonblock(x, 1, err_buf.as_mut_ptr()) == pcap_c::PCAP_ERROR {
+                pcap_c::pcap_close(x);
+                return Err(Error::from_err_buf(Operation::SetNonBlocking, &err_buf)
+                    .with_context(self.dev.as_str()));
+            }
+            let selectable_fd = match pcap_c::pcap_get_selectable_fd(x) {
+                -1 => Err(Error::new(
+                    Operation::GetSelectableFd,
+                    "the device does not support selecting",
+                )),
+                fd => match tokio::io::unix::AsyncFd::new(fd) {
+                    Err(e) => Err(Error::new(Operation::GetSelectableFd, &e.to_string())),
+                    Ok(s) => Ok(s),
+                },
+            };
+            match selectable_fd {
+                Ok(fd) => Ok(CaptureHandle {
+                    handle: std::sync::atomic::AtomicPtr::new(x),
+                    state: State::Idle,
+                    savefile: None,
+                    mode: self.mode,
+                    snaplen: self.snaplen,
+                    timeout_ms: self.timeout_ms,
+                    frame_type_code: pcap_c::pcap_datalink(x),
+                    precision: match pcap_c::pcap_get_tstamp_precision(x) as u32 {
+                        pcap_c::PCAP_TSTAMP_PRECISION_NANO => TimestampPrecision::Nano,
+                        _ => TimestampPrecision::Micro,
+                    },
+                    device: Some(self.dev),
+                    batch_size: self.batch_size,
+                    raw: self.raw,
+                    queue: std::collections::VecDeque::new(),
+                    selectable_fd: Some(fd),
+                    warning,
+                }),
+                Err(e) => {
+                    pcap_c::pcap_close(x);
+                    Err(e.with_context(self.dev.as_str()))
+                }
+            }
+        }
+    }
+}
+
+#[cfg(feature = "libpcap")]
+impl Filter {
+    /// Compiles an optimized filter using the given handle, which is only used to determine the link type and the snaplen.
+    unsafe fn compile_with(
+        handle_ptr: *mut pcap_c::pcap_t,
+        code: &str,
+        netmask: Option<pcap_c::bpf_u_int32>,
+    ) -> Result<Filter, Error> {
+        // The code must outlive the call to pcap_compile, and is freed afterwards.
+        let c_code = match std::ffi::CString::new(code) {
+            Ok(n) => n,
+            Err(_) => {
+                return Err(
+                    Error::new(Operation::CompileFilter, "the filter contains a NUL byte")
+                        .with_context(code),
+                );
+            }
+        };
+        let mut prog: pcap_c::bpf_program = std::mem::zeroed();
+        match pcap_c::pcap_compile(
+            handle_ptr,
+            &mut prog as *mut pcap_c::bpf_program,
+            c_code.as_ptr(),
+            1,
+            match netmask {
+                None => pcap_c::PCAP_NETMASK_UNKNOWN,
+                Some(n) => n,
+            },
+        ) {
+            0 => Ok(Filter { bpf_prog: prog }),
+            status => Err(
+                Error::from_handle(Operation::CompileFilter, handle_ptr, status).with_context(code),
+            ),
+        }
+    }
+
+    /// Compiles a filter for frames of the given link type, without opening a device. Both DLT_ and LINKTYPE_ values are accepted.
+    pub fn compile(
+        link_type: i32,
+        snaplen: i32,
+        code: &str,
+        netmask: Option<pcap_c::bpf_u_int32>,
+    ) -> Result<Filter, Error> {
+        unsafe {
+            // pcap_open_dead expects a DLT_ value.
+            let link_type = match link_type {
+                pcap_c::LINKTYPE_RAW => pcap_c::DLT_RAW,
+                t => t,
+            };
+            let dead = match pcap_c::pcap_open_dead(link_type, snaplen).as_mut() {
+                Some(d) => d,
+                None => {
+                    return Err(Error::new(
+                        Operation::Create,
+                        "could not open a handle for compilation",
+                    ));
+                }
+            };
+            let res = Filter::compile_with(dead, code, netmask);
+            // The compiled program does not depend on the handle.
+            pcap_c::pcap_close(dead);
+            res
+        }
+    }
+
+    /// The compiled program, which can also be attached to a socket.
+    pub fn program(&self) -> &pcap_c::bpf_program {
+        &self.bpf_prog
+    }
+
+    /// Checks whether a frame matches the filter. `original_len` is the length of the frame on the wire, which may be larger than the buffer.
+    pub fn matches_buffer(&self, raw: &[u8], original_len: u32) -> bool {
+        unsafe {
+            let mut header: pcap_c::pcap_pkthdr = std::mem::zeroed();
+            header.caplen = raw.len() as pcap_c::bpf_u_int32;
+            header.len = original_len;
+            pcap_c::pcap_offline_filter(&self.bpf_prog, &header, raw.as_ptr()) != 0
+        }
+    }
+
+    /// Checks whether data matches the filter. Parsed data is written to a buffer first.
+    #[allow(dead_code)]
+    pub fn matches(&self, data: &Data) -> bool {
+        match data {
+            Data::Raw(r) => self.matches_buffer(&r.bytes, r.original_len),
+            d => {
+                let raw = d.into_buffer((0, 0));
+                self.matches_buffer(&raw, raw.len() as u32)
+            }
+        }
+    }
+}
+
+#[cfg(feature = "libpcap")]
+impl Drop for Filter {
+    fn drop(&mut self) {
+        unsafe {
+            pcap_c::pcap_freecode(&mut self.bpf_prog);
+        }
+    }
+}
+
+#[cfg(not(feature = "libpcap"))]
+impl Filter {
+    /// Always fails, since filters can only be compiled by libpcap.
+    pub fn compile(
+        _link_type: i32,
+        _snaplen: i32,
+        code: &str,
+        _netmask: Option<pcap_c::bpf_u_int32>,
+    ) -> Result<Filter, Error> {
+        Err(Error::new(
+            Operation::CompileFilter,
+            "filters can not be compiled without libpcap",
+        )
+        .with_context(code))
+    }
+
+    pub fn program(&self) -> &pcap_c::bpf_program {
+        match *self {}
+    }
+
+    pub fn matches_buffer(&self, _raw: &[u8], _original_len: u32) -> bool {
+        match *self {}
+    }
+}
+
+#[cfg(feature = "libpcap")]
+impl Savefile {
+    /// Creates a savefile at `path` that matches the link type and snaplen of `handle`.
+    pub fn create(handle: &mut CaptureHandle, path: &str) -> Result<Savefile, Error> {
+        let file_name = match std::ffi::CString::new(path) {
+            Ok(f) => f,
+            Err(_) => {
+                return Err(Error::new(
+                    Operation::OpenSavefile,
+                    "the file name contains a NUL byte",
+                )
+                .with_context(path));
+            }
+        };
+        let handle_ptr: *mut pcap_c::pcap_t = *handle.handle.get_mut();
+        unsafe {
+            match pcap_c::pcap_dump_open(handle_ptr, file_name.as_ptr()).as_mut() {
+                None => {
+                    Err(
+                        Error::from_handle(Operation::OpenSavefile, handle_ptr, pcap_c::PCAP_ERROR)
+                            .with_context(path),
+                    )
+                }
+                Some(d) => Ok(Savefile {
+                    dumper: std::sync::atomic::AtomicPtr::new(d),
+                }),
+            }
+        }
+    }
+
+    /// The number of bytes that have been written to the file so far.
+    pub fn size(&mut self) -> Option<u64> {
+        match unsafe { pcap_c::pcap_dump_ftell(*self.dumper.get_mut()) } {
+            -1 => None,
+            n => Some(n as u64),
+        }
+    }
+
+    /// Writes any buffered packets to the file.
+    pub fn flush(&mut self) -> Result<(), Error> {
+        match unsafe { pcap_c::pcap_dump_flush(*self.dumper.get_mut()) } {
+            0 => Ok(()),
+            // libpcap does not describe the error, but it is left in errno by the underlying write.
+            code => Err(Error {
+                operation: Operation::FlushSavefile,
+                code: Some(code),
+                message: std::io::Error::last_os_error().to_string(),
+                context: None,
+            }),
+        }
+    }
+}
+
+#[cfg(feature = "libpcap")]
+impl Drop for Savefile {
+    /// Flushes and closes the file once it is no longer used.
+    fn drop(&mut self) {
+        unsafe {
+            pcap_c::pcap_dump_close(*self.dumper.get_mut());
+        }
+    }
+}
+
+/// The handle is used to parse data recieved from libpcap (according to the handle's link type) and return the sanitized result.
 /// The parsing is done immediately instead of copying the packet first, and so there is no need to copy the data and and parse it later.
+#[cfg(feature = "libpcap")]
 #[no_mangle]
-unsafe extern "C" fn handle_packet_ethernet(
+unsafe extern "C" fn handle_packet(
     user: *mut u8,
     raw: *const pcap_c::pcap_pkthdr,
     data: *const u8,
 ) {
     // The calling method placed the output into the `user` parameter.
-    let out = (user as *mut Result<(std::time::SystemTime, Data), CaptureError>)
+    let dispatch = (user as *mut Dispatch)
         .as_mut()
         .expect("Got NULL pointer for output!");
-    *out = match raw.as_ref() {
+    // The packet is recorded before it is parsed, so that packets that can not be parsed are kept as well.
+    if !dispatch.dumper.is_null() {
+        pcap_c::pcap_dump(dispatch.dumper as *mut u8, raw, data);
+    }
+    let res = match raw.as_ref() {
         None => {
             panic!("Got NULL from libpcap!");
         }
@@ -399,15 +1218,126 @@ unsafe extern "C" fn handle_packet_ethernet(
             let dur = std::time::UNIX_EPOCH
                 + std::time::Duration::new(
                     packet.ts.tv_sec as u64,
-                    (packet.ts.tv_usec * 1000) as u32,
+                    // Handles with nanosecond precision store nanoseconds in the microseconds field.
+                    match dispatch.precision {
+                        TimestampPrecision::Micro => (packet.ts.tv_usec * 1000) as u32,
+                        TimestampPrecision::Nano => packet.ts.tv_usec as u32,
+                    },
                 );
-            match linklayer::ethernet::Frame::from_raw_slice(std::slice::from_raw_parts(
-                data,
-                packet.caplen as usize,
-            )) {
-                Err(e) => Err(CaptureError::LinkLayer(linklayer::Error::Ethernet(e))),
-                Ok(f) => Ok((dur, Data::LinkLayer(linklayer::Data::Ethernet(f)))),
+            let slice = std::slice::from_raw_parts(data, packet.caplen as usize);
+            match dispatch.raw {
+                true => Ok((
+                    dur,
+                    Data::Raw(RawFrame {
+                        link_type: dispatch.link_type,
+                        caplen: packet.caplen,
+                        original_len: packet.len,
+                        bytes: Box::from(slice),
+                    }),
+                )),
+                false => Data::from_link_type(dispatch.link_type, slice).map(|d| (dur, d)),
             }
         }
     };
+    dispatch.out.push_back(res);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// An IPv4 datagram from 10.0.0.1 to 10.0.0.2, which carries 4 bytes of an unknown protocol.
+    const DATAGRAM: [u8; 24] = [
+        0x45, 0, 0, 24, 0, 1, 0x40, 0, 64, 0xFD, 0xAB, 0xCD, 10, 0, 0, 1, 10, 0, 0, 2, 1, 2, 3, 4,
+    ];
+
+    /// Parses a frame of a link type, and checks that it is written back as it was.
+    fn parse(link_type: i32, header: &[u8]) -> linklayer::Data {
+        let mut buf = header.to_vec();
+        buf.extend_from_slice(&DATAGRAM);
+        let data = Data::from_link_type(link_type, &buf).unwrap();
+        assert_eq!(data.into_buffer((0, 0)), buf);
+        match data {
+            Data::LinkLayer(d) => d,
+            d => panic!("{:?}", d),
+        }
+    }
+
+    #[test]
+    fn ethernet() {
+        let header = [
+            0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 2, 0, 0, 0, 0, 1, 0x08, 0x00,
+        ];
+        assert!(matches!(
+            parse(pcap_c::DLT_EN10MB, &header),
+            linklayer::Data::Ethernet(_)
+        ));
+    }
+
+    #[test]
+    fn linux_cooked() {
+        let header = [0, 0, 0, 1, 0, 6, 2, 0, 0, 0, 0, 1, 0, 0, 0x08, 0x00];
+        assert!(matches!(
+            parse(pcap_c::DLT_LINUX_SLL, &header),
+            linklayer::Data::LinuxSll(_)
+        ));
+        let header = [
+            0x08, 0x00, 0, 0, 0, 0, 0, 1, 0, 1, 0, 6, 2, 0, 0, 0, 0, 1, 0, 0,
+        ];
+        assert!(matches!(
+            parse(pcap_c::DLT_LINUX_SLL2, &header),
+            linklayer::Data::LinuxSll2(_)
+        ));
+    }
+
+    #[test]
+    fn raw_ip() {
+        // DLT_RAW differs between platforms, so LINKTYPE_RAW is accepted as well.
+        for link_type in [
+            pcap_c::DLT_RAW,
+            pcap_c::LINKTYPE_RAW,
+            pcap_c::DLT_IPV4,
+            pcap_c::DLT_IPV6,
+        ]
+        .iter()
+        {
+            assert!(matches!(parse(*link_type, &[]), linklayer::Data::RawIp(_)));
+        }
+    }
+
+    #[test]
+    fn loopback() {
+        match parse(pcap_c::DLT_NULL, &[2, 0, 0, 0]) {
+            linklayer::Data::Null(f) => assert!(!f.big_endian),
+            d => panic!("{:?}", d),
+        }
+        match parse(pcap_c::DLT_LOOP, &[0, 0, 0, 2]) {
+            linklayer::Data::Null(f) => assert!(f.big_endian),
+            d => panic!("{:?}", d),
+        }
+    }
+
+    #[test]
+    fn ppp() {
+        assert!(matches!(
+            parse(pcap_c::DLT_PPP, &[0xFF, 0x03, 0x00, 0x21]),
+            linklayer::Data::Ppp(_)
+        ));
+    }
+
+    #[test]
+    fn unknown_link_types() {
+        match parse(147, &[1, 2]) {
+            linklayer::Data::Unknown(147, d) => assert_eq!(d.len(), 2 + DATAGRAM.len()),
+            d => panic!("{:?}", d),
+        }
+    }
+
+    #[test]
+    fn errors_name_the_link_layer() {
+        match Data::from_link_type(pcap_c::DLT_LINUX_SLL, &[0; 4]) {
+            Err(CaptureError::LinkLayer(linklayer::Error::LinuxSll(_))) => {}
+            r => panic!("{:?}", r.map(|_| ())),
+        }
+    }
 }
@@ -1,48 +1,154 @@
 use super::pcap;
+use super::PacketInterface;
 
 use tokio_stream::{Stream, StreamExt};
 
+/// A packet that has been captured by the wrapped handle, or the error that occured while capturing it.
+type Captured = Result<(std::time::SystemTime, pcap::Data), pcap::CaptureError>;
+
 /// Represents an action that a cloud can perform on a handle.
 enum CloudAction {
     /// Indicates that new data has been recieved.
-    Forward(Result<(std::time::SystemTime, pcap::Data), pcap::CaptureError>),
+    Forward(Captured),
     /// Indicates that the cloud recieved data to inject.
     Inject(pcap::Data),
+    /// Indicates that the handle's statistics should be reported.
+    Report,
+}
+
+/// The interval at which clouds report the statistics of their handles, if they have been asked to.
+pub const STATS_REPORT_PERIOD: std::time::Duration = std::time::Duration::from_secs(10);
+
+/// The time for which injected packets are remembered.
+const INJECTION_MEMORY: std::time::Duration = std::time::Duration::from_secs(1);
+/// The maximal number of injected packets that are remembered.
+const INJECTION_CACHE_SIZE: usize = 1024;
+
+/// How a cloud recognizes the packets that it has injected itself once they are captured again.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LoopProtection {
+    /// Restricts the handle to incoming packets, falling back to fingerprints if the handle does not support it.
+    /// This hides every packet that the host sends on the interface, not only the ones that the cloud injects.
+    IncomingOnly,
+    /// Remembers the fingerprints of the injected packets, and skips each of them once when it is captured.
+    /// The host's own outgoing packets are still forwarded.
+    Fingerprints,
+}
+
+/// Remembers the fingerprints of recently injected packets, so that they are not forwarded once they are captured again.
+/// Every injection is remembered separately, so a packet that is injected twice is skipped twice and then forwarded again.
+struct InjectionCache {
+    recent: std::collections::VecDeque<(std::time::Instant, u64)>,
+}
+
+impl InjectionCache {
+    fn new() -> Self {
+        InjectionCache {
+            recent: std::collections::VecDeque::new(),
+        }
+    }
+
+    fn fingerprint(raw: &[u8]) -> u64 {
+        use std::hash::{Hash, Hasher};
+        let mut hasher = std::collections::hash_map::DefaultHasher::new();
+        raw.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    /// Forgets packets that have been injected too long ago.
+    fn expire(&mut self) {
+        while let Some(&(t, _)) = self.recent.front() {
+            if t.elapsed() < INJECTION_MEMORY && self.recent.len() <= INJECTION_CACHE_SIZE {
+                break;
+            }
+            self.recent.pop_front();
+        }
+    }
+
+    fn remember(&mut self, raw: &[u8]) {
+        self.recent
+            .push_back((std::time::Instant::now(), Self::fingerprint(raw)));
+        self.expire();
+    }
+
+    /// Checks whether a captured packet has been injected recently. A matching injection is forgotten, so that it only matches a single capture.
+    fn take(&mut self, raw: &[u8]) -> bool {
+        self.expire();
+        let fp = Self::fingerprint(raw);
+        match self.recent.iter().position(|&(_, f)| f == fp) {
+            Some(i) => {
+                self.recent.remove(i);
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+/// Periodically prints the statistics of a handle.
+struct StatsReport {
+    /// The name that identifies the handle in the report.
+    label: String,
+    interval: tokio::time::Interval,
 }
 
-pub struct Cloud<S: Stream<Item = pcap::Data>> {
-    wrapped_handle: pcap::CaptureHandle,
+/// Any packet interface can be wrapped, such as a libpcap handle or an AF_PACKET ring.
+pub struct Cloud<S: Stream<Item = pcap::Data>, I: PacketInterface> {
+    wrapped_handle: I,
     injection_receiver: S,
-    packet_output:
-        tokio::sync::mpsc::Sender<Result<(std::time::SystemTime, pcap::Data), pcap::CaptureError>>,
+    packet_output: tokio::sync::mpsc::Sender<Captured>,
+    /// Statistics are only reported if this has been set.
+    report: Option<StatsReport>,
+    /// Recently injected packets, if loop protection relies on fingerprints.
+    injected: Option<InjectionCache>,
 }
 
-impl<S: Stream<Item = pcap::Data> + Unpin> Cloud<S> {
-    pub fn new(
-        handle: pcap::CaptureHandle,
-        injections: S,
-    ) -> (
-        Cloud<S>,
-        super::mpsc::Receiver<Result<(std::time::SystemTime, pcap::Data), pcap::CaptureError>>,
-    ) {
-        let (incoming_snd, incoming_rcv) = super::mpsc::Receiver::<
-            Result<(std::time::SystemTime, pcap::Data), pcap::CaptureError>,
-        >::new_bounded(1000);
+impl<S: Stream<Item = pcap::Data> + Unpin, I: PacketInterface> Cloud<S, I> {
+    pub fn new(handle: I, injections: S) -> (Cloud<S, I>, super::mpsc::Receiver<Captured>) {
+        let (incoming_snd, incoming_rcv) = super::mpsc::Receiver::<Captured>::new_bounded(1000);
         (
             Cloud {
                 wrapped_handle: handle,
                 injection_receiver: injections,
                 packet_output: incoming_snd,
+                report: None,
+                injected: None,
             },
             incoming_rcv,
         )
     }
 
+    /// Makes the cloud report the statistics of its handle periodically and once it is dropped.
+    pub fn with_stats_report(mut self, label: &str, period: std::time::Duration) -> Self {
+        self.report = Some(StatsReport {
+            label: String::from(label),
+            interval: tokio::time::interval_at(tokio::time::Instant::now() + period, period),
+        });
+        self
+    }
+
+    /// Prevents the cloud from forwarding the packets that it has injected itself.
+    /// `LoopProtection::IncomingOnly` also hides the host's own outgoing traffic, so it should only be used on interfaces where that traffic is of no interest.
+    pub fn with_loop_protection(mut self, protection: LoopProtection) -> Self {
+        let incoming_only = protection == LoopProtection::IncomingOnly
+            && self
+                .wrapped_handle
+                .set_direction(pcap::Direction::In)
+                .is_ok();
+        if !incoming_only {
+            self.injected = Some(InjectionCache::new());
+        }
+        self
+    }
+
     async fn get_next(&mut self) -> Option<CloudAction> {
+        let report = &mut self.report;
+        let reporting = report.is_some();
         tokio::select! {
             biased;
-            p = self.wrapped_handle.next() => Some(CloudAction::Forward(p)),
+            Some(p) = self.wrapped_handle.next() => Some(CloudAction::Forward(p)),
             Some(i) = self.injection_receiver.next() => Some(CloudAction::Inject(i)),
+            _ = async { if let Some(r) = report { r.interval.tick().await; } }, if reporting => Some(CloudAction::Report),
             else => None
         }
     }
@@ -52,14 +158,108 @@ impl<S: Stream<Item = pcap::Data> + Unpin> Cloud<S> {
         while let Some(a) = self.get_next().await {
             match a {
                 CloudAction::Forward(p) => {
-                    match self.packet_output.send(p).await {
-                        _ => {}
-                    };
+                    if let (Some(cache), Ok((_, d))) = (self.injected.as_mut(), &p) {
+                        if cache.take(&d.into_buffer((0, 0))) {
+                            continue;
+                        }
+                    }
+                    // Packets are dropped once nobody listens to the cloud.
+                    let _ = self.packet_output.send(p).await;
                 }
-                CloudAction::Inject(i) => match self.wrapped_handle.inject(i) {
-                    _ => {}
+                CloudAction::Inject(i) => match self.injected.as_mut() {
+                    Some(cache) => {
+                        let raw = i.into_buffer((0, 0));
+                        cache.remember(&raw);
+                        let _ = self.wrapped_handle.inject_buffer(&raw);
+                    }
+                    None => {
+                        let _ = self.wrapped_handle.inject(i);
+                    }
                 },
+                CloudAction::Report => self.report(),
             }
         }
     }
 }
+
+impl<S: Stream<Item = pcap::Data>, I: PacketInterface> Cloud<S, I> {
+    /// Prints the statistics of the handle.
+    fn report(&mut self) {
+        if let Some(r) = &self.report {
+            match self.wrapped_handle.stats() {
+                Ok(s) => eprintln!("[{}] {}", r.label, s),
+                Err(e) => eprintln!("[{}] {}", r.label, e),
+            }
+        }
+    }
+}
+
+/// The final statistics are reported when the cloud stops, including when it is cancelled.
+impl<S: Stream<Item = pcap::Data>, I: PacketInterface> Drop for Cloud<S, I> {
+    fn drop(&mut self) {
+        self.report();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn injections_match_once() {
+        let mut cache = InjectionCache::new();
+        cache.remember(&[1, 2, 3]);
+        cache.remember(&[1, 2, 3]);
+        cache.remember(&[4, 5]);
+        assert!(!cache.take(&[6]));
+        assert!(cache.take(&[1, 2, 3]));
+        assert!(cache.take(&[1, 2, 3]));
+        assert!(!cache.take(&[1, 2, 3]));
+        assert!(cache.take(&[4, 5]));
+        assert!(!cache.take(&[4, 5]));
+    }
+
+    #[test]
+    fn injections_are_bounded() {
+        let mut cache = InjectionCache::new();
+        for i in 0..INJECTION_CACHE_SIZE as u32 + 1 {
+            cache.remember(&i.to_be_bytes());
+        }
+        assert_eq!(cache.recent.len(), INJECTION_CACHE_SIZE);
+        assert!(!cache.take(&0u32.to_be_bytes()));
+        assert!(cache.take(&1u32.to_be_bytes()));
+    }
+
+    /// LINKTYPE_USER0, whose frames are kept as they are.
+    const USER0: i32 = 147;
+
+    #[tokio::test]
+    async fn fingerprints_skip_each_injection_once() {
+        let (port, mut peer) = super::super::memory::wire(USER0, ("a", "b"));
+        let (injections, injected) = tokio::sync::mpsc::unbounded_channel();
+        let (cloud, mut captured) = Cloud::new(
+            port,
+            tokio_stream::wrappers::UnboundedReceiverStream::new(injected),
+        );
+        let cloud = cloud.with_loop_protection(LoopProtection::Fingerprints);
+        tokio::spawn(cloud.run());
+
+        let packet = pcap::RawFrame {
+            link_type: USER0,
+            caplen: 4,
+            original_len: 4,
+            bytes: Box::new([1, 2, 3, 4]),
+        };
+        injections.send(pcap::Data::Raw(packet.clone())).unwrap();
+        // The wire does not echo injected frames, so the peer plays the part of a capture that sees them.
+        let echoed = peer.next().await.unwrap().unwrap().1;
+        peer.inject(echoed).unwrap();
+        peer.inject_buffer(&packet.bytes).unwrap();
+        peer.inject_buffer(&[5, 6, 7, 8]).unwrap();
+
+        let first = captured.next().await.unwrap().unwrap().1;
+        assert_eq!(first.into_buffer((0, 0)), packet.bytes.to_vec());
+        let second = captured.next().await.unwrap().unwrap().1;
+        assert_eq!(second.into_buffer((0, 0)), vec![5, 6, 7, 8]);
+    }
+}
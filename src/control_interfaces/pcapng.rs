@@ -0,0 +1,627 @@
+use super::pcap;
+
+use std::convert::TryInto;
+use std::io::{Read, Write};
+
+/// The block type of a Section Header Block. The value is a palindrome so that it can be read before the byte order is known.
+const SECTION_HEADER_BLOCK: u32 = 0x0A0D0D0A;
+const INTERFACE_DESCRIPTION_BLOCK: u32 = 0x00000001;
+/// The obsolete packet block, which is still produced by some older tools.
+const PACKET_BLOCK: u32 = 0x00000002;
+const SIMPLE_PACKET_BLOCK: u32 = 0x00000003;
+const ENHANCED_PACKET_BLOCK: u32 = 0x00000006;
+const BYTE_ORDER_MAGIC: u32 = 0x1A2B3C4D;
+
+const OPT_ENDOFOPT: u16 = 0;
+const OPT_COMMENT: u16 = 1;
+const IF_NAME: u16 = 2;
+const IF_DESCRIPTION: u16 = 3;
+const IF_TSRESOL: u16 = 9;
+
+/// The timestamp resolution used by the writer (10^-9, nanoseconds).
+const WRITER_TSRESOL: u8 = 9;
+
+/// Describes an interface that packets were captured on. Every packet refers to an interface by its index in the section.
+#[derive(Debug, Clone)]
+pub struct Interface {
+    /// The LINKTYPE_ value that describes the frames captured on the interface.
+    pub link_type: u16,
+    pub snaplen: u32,
+    pub name: Option<String>,
+    pub description: Option<String>,
+    /// The resolution of the interface's timestamps, encoded as in the `if_tsresol` option.
+    tsresol: u8,
+}
+
+/// A single packet in a pcapng file.
+#[derive(Debug)]
+pub struct Packet {
+    /// The index of the interface that the packet was captured on.
+    pub interface_id: u32,
+    pub timestamp: std::time::SystemTime,
+    /// The length of the packet on the wire, which may be larger than the captured data.
+    pub original_len: u32,
+    pub data: Box<[u8]>,
+    pub comment: Option<String>,
+}
+
+#[derive(Debug)]
+pub enum Error {
+    Io(std::io::Error),
+    /// The file does not start with a Section Header Block.
+    NotPcapng,
+    InvalidBlockLength,
+    InvalidByteOrder,
+    /// A packet refers to an interface that has not been described.
+    UnknownInterface(u32),
+    /// An interface uses a timestamp resolution (given in its `if_tsresol` encoding) that does not fit in 64 bits.
+    UnsupportedResolution(u8),
+    /// A packet could not be parsed according to the link type of its interface.
+    Parse(pcap::CaptureError),
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Error::Io(e) => write!(f, "{}", e),
+            Error::NotPcapng => write!(f, "not a pcapng file"),
+            Error::InvalidBlockLength => write!(f, "invalid block length"),
+            Error::InvalidByteOrder => write!(f, "invalid byte-order magic"),
+            Error::UnknownInterface(id) => write!(f, "packet refers to unknown interface {}", id),
+            Error::UnsupportedResolution(r) => {
+                write!(f, "unsupported timestamp resolution 0x{:02x}", r)
+            }
+            Error::Parse(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io(e) => Some(e),
+            Error::Parse(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl std::convert::From<std::io::Error> for Error {
+    fn from(thing: std::io::Error) -> Error {
+        Error::Io(thing)
+    }
+}
+
+/// Data that can be written to a capture file as a raw frame.
+pub trait Recordable {
+    fn record_bytes(&self) -> Vec<u8>;
+    /// The length of the packet on the wire, if it differs from the length of the recorded bytes.
+    fn original_len(&self) -> Option<u32> {
+        None
+    }
+}
+
+impl Recordable for pcap::Data {
+    fn record_bytes(&self) -> Vec<u8> {
+        self.into_buffer((0, 0))
+    }
+    fn original_len(&self) -> Option<u32> {
+        match self {
+            pcap::Data::Raw(r) => Some(r.original_len),
+            _ => None,
+        }
+    }
+}
+
+impl Interface {
+    pub fn new(link_type: u16, snaplen: u32, name: Option<&str>) -> Interface {
+        Interface {
+            link_type,
+            snaplen,
+            name: name.map(String::from),
+            description: None,
+            tsresol: WRITER_TSRESOL,
+        }
+    }
+
+    /// Describes the interface that a capture handle listens on.
+    pub fn of_handle<I: super::PacketInterface>(handle: &I, name: Option<&str>) -> Interface {
+        Interface::new(handle.link_type() as u16, handle.snaplen() as u32, name)
+    }
+
+    /// Whether the number of units in a second fits in 64 bits, so that timestamps can be converted.
+    fn is_supported_resolution(tsresol: u8) -> bool {
+        match tsresol & 0x80 {
+            0 => tsresol <= 19,
+            _ => tsresol & 0x7F <= 63,
+        }
+    }
+
+    /// The number of timestamp units in a second.
+    fn units_per_second(&self) -> u64 {
+        match self.tsresol & 0x80 {
+            0 => 10u64.pow((self.tsresol & 0x7F) as u32),
+            _ => 1u64 << (self.tsresol & 0x7F),
+        }
+    }
+}
+
+/// Appends an option (padded to 32 bits) to a block body.
+fn push_option(body: &mut Vec<u8>, code: u16, value: &[u8]) {
+    body.extend_from_slice(&code.to_le_bytes());
+    body.extend_from_slice(&(value.len() as u16).to_le_bytes());
+    body.extend_from_slice(value);
+    body.resize((body.len() + 3) & !3, 0);
+}
+
+/// Writes pcapng files. Blocks are written in little-endian byte order.
+pub struct Writer<W: Write> {
+    out: W,
+    /// The interfaces that have been described in the current section.
+    interfaces: Vec<Interface>,
+}
+
+impl Writer<std::io::BufWriter<std::fs::File>> {
+    /// Creates a new pcapng file at the given path.
+    pub fn create(path: &str) -> Result<Self, Error> {
+        Writer::new(std::io::BufWriter::new(std::fs::File::create(path)?))
+    }
+}
+
+impl<W: Write> Writer<W> {
+    /// Constructs the writer and writes the Section Header Block.
+    pub fn new(out: W) -> Result<Self, Error> {
+        let mut w = Writer {
+            out,
+            interfaces: Vec::new(),
+        };
+        let mut body = Vec::with_capacity(16);
+        body.extend_from_slice(&BYTE_ORDER_MAGIC.to_le_bytes());
+        body.extend_from_slice(&1u16.to_le_bytes());
+        body.extend_from_slice(&0u16.to_le_bytes());
+        // The section length is unknown, as the file is written as a stream.
+        body.extend_from_slice(&(-1i64).to_le_bytes());
+        w.write_block(SECTION_HEADER_BLOCK, &body)?;
+        Ok(w)
+    }
+
+    fn write_block(&mut self, block_type: u32, body: &[u8]) -> Result<(), Error> {
+        let padded = (body.len() + 3) & !3;
+        let total_len = (padded + 12) as u32;
+        self.out.write_all(&block_type.to_le_bytes())?;
+        self.out.write_all(&total_len.to_le_bytes())?;
+        self.out.write_all(body)?;
+        self.out.write_all(&[0u8; 3][..padded - body.len()])?;
+        self.out.write_all(&total_len.to_le_bytes())?;
+        Ok(())
+    }
+
+    /// Writes an Interface Description Block and returns the id that packets captured on the interface should use.
+    pub fn add_interface(&mut self, interface: &Interface) -> Result<u32, Error> {
+        let mut body = Vec::with_capacity(32);
+        body.extend_from_slice(&interface.link_type.to_le_bytes());
+        body.extend_from_slice(&0u16.to_le_bytes());
+        body.extend_from_slice(&interface.snaplen.to_le_bytes());
+        if let Some(ref name) = interface.name {
+            push_option(&mut body, IF_NAME, name.as_bytes());
+        }
+        if let Some(ref description) = interface.description {
+            push_option(&mut body, IF_DESCRIPTION, description.as_bytes());
+        }
+        push_option(&mut body, IF_TSRESOL, &[WRITER_TSRESOL]);
+        push_option(&mut body, OPT_ENDOFOPT, &[]);
+        self.write_block(INTERFACE_DESCRIPTION_BLOCK, &body)?;
+
+        self.interfaces.push(Interface {
+            tsresol: WRITER_TSRESOL,
+            ..interface.clone()
+        });
+        Ok((self.interfaces.len() - 1) as u32)
+    }
+
+    /// Writes an Enhanced Packet Block.
+    pub fn write_packet(&mut self, packet: &Packet) -> Result<(), Error> {
+        let interface = match self.interfaces.get(packet.interface_id as usize) {
+            Some(i) => i,
+            None => {
+                return Err(Error::UnknownInterface(packet.interface_id));
+            }
+        };
+        let since_epoch = packet
+            .timestamp
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap_or_default();
+        let ts = since_epoch.as_secs() * interface.units_per_second()
+            + (since_epoch.subsec_nanos() as u64) * interface.units_per_second() / 1_000_000_000;
+
+        let mut body = Vec::with_capacity(32 + packet.data.len());
+        body.extend_from_slice(&packet.interface_id.to_le_bytes());
+        body.extend_from_slice(&((ts >> 32) as u32).to_le_bytes());
+        body.extend_from_slice(&(ts as u32).to_le_bytes());
+        body.extend_from_slice(&(packet.data.len() as u32).to_le_bytes());
+        body.extend_from_slice(&packet.original_len.to_le_bytes());
+        body.extend_from_slice(&packet.data);
+        body.resize((body.len() + 3) & !3, 0);
+        if let Some(ref comment) = packet.comment {
+            push_option(&mut body, OPT_COMMENT, comment.as_bytes());
+            push_option(&mut body, OPT_ENDOFOPT, &[]);
+        }
+        self.write_block(ENHANCED_PACKET_BLOCK, &body)
+    }
+
+    /// Serializes data (for example, the output of a capture handle) and writes it as a packet of the given interface.
+    pub fn write_data<D: Recordable>(
+        &mut self,
+        interface_id: u32,
+        timestamp: std::time::SystemTime,
+        data: &D,
+        comment: Option<&str>,
+    ) -> Result<(), Error> {
+        let bytes = data.record_bytes();
+        self.write_packet(&Packet {
+            interface_id,
+            timestamp,
+            original_len: data.original_len().unwrap_or(bytes.len() as u32),
+            data: bytes.into_boxed_slice(),
+            comment: comment.map(String::from),
+        })
+    }
+
+    pub fn flush(&mut self) -> Result<(), Error> {
+        Ok(self.out.flush()?)
+    }
+}
+
+/// Reads packets from pcapng files. Files that contain several sections (possibly with different byte orders) are supported.
+pub struct Reader<R: Read> {
+    input: R,
+    big_endian: bool,
+    /// The interfaces that have been described in the current section.
+    interfaces: Vec<Interface>,
+}
+
+impl Reader<std::io::BufReader<std::fs::File>> {
+    /// Opens the pcapng file at the given path.
+    pub fn open(path: &str) -> Result<Self, Error> {
+        Reader::new(std::io::BufReader::new(std::fs::File::open(path)?))
+    }
+}
+
+impl<R: Read> Reader<R> {
+    /// Constructs the reader and reads the first Section Header Block.
+    pub fn new(input: R) -> Result<Self, Error> {
+        let mut r = Reader {
+            input,
+            big_endian: false,
+            interfaces: Vec::new(),
+        };
+        match r.read_block()? {
+            Some((SECTION_HEADER_BLOCK, _)) => Ok(r),
+            _ => Err(Error::NotPcapng),
+        }
+    }
+
+    /// The interfaces that have been described so far in the current section.
+    pub fn interfaces(&self) -> &[Interface] {
+        &self.interfaces
+    }
+
+    /// Parses a packet according to the link type of the interface it was captured on.
+    pub fn parse(&self, packet: &Packet) -> Result<pcap::Data, Error> {
+        match self.interfaces.get(packet.interface_id as usize) {
+            Some(i) => {
+                pcap::Data::from_link_type(i.link_type as i32, &packet.data).map_err(Error::Parse)
+            }
+            None => Err(Error::UnknownInterface(packet.interface_id)),
+        }
+    }
+
+    fn u16_at(&self, buf: &[u8], at: usize) -> u16 {
+        let b: [u8; 2] = buf[at..at + 2].try_into().unwrap();
+        match self.big_endian {
+            true => u16::from_be_bytes(b),
+            false => u16::from_le_bytes(b),
+        }
+    }
+
+    fn u32_at(&self, buf: &[u8], at: usize) -> u32 {
+        let b: [u8; 4] = buf[at..at + 4].try_into().unwrap();
+        match self.big_endian {
+            true => u32::from_be_bytes(b),
+            false => u32::from_le_bytes(b),
+        }
+    }
+
+    /// Reads the next block and returns its type and body. Section Header and Interface Description Blocks are also applied to the reader's state.
+    fn read_block(&mut self) -> Result<Option<(u32, Vec<u8>)>, Error> {
+        let mut header = [0u8; 8];
+        match self.input.read_exact(&mut header[..4]) {
+            Ok(_) => {}
+            Err(ref e) if e.kind() == std::io::ErrorKind::UnexpectedEof => {
+                return Ok(None);
+            }
+            Err(e) => {
+                return Err(Error::Io(e));
+            }
+        }
+        self.input.read_exact(&mut header[4..])?;
+
+        let mut magic = [0u8; 4];
+        let block_type = self.u32_at(&header, 0);
+        if block_type == SECTION_HEADER_BLOCK {
+            // The byte order of a section is only known once its magic number has been read.
+            self.input.read_exact(&mut magic)?;
+            self.big_endian = match magic {
+                [0x1A, 0x2B, 0x3C, 0x4D] => true,
+                [0x4D, 0x3C, 0x2B, 0x1A] => false,
+                _ => {
+                    return Err(Error::InvalidByteOrder);
+                }
+            };
+        }
+        let total_len = self.u32_at(&header, 4) as usize;
+        if total_len < 12 || total_len & 3 != 0 {
+            return Err(Error::InvalidBlockLength);
+        }
+
+        let mut body = vec![0u8; total_len - 8];
+        match block_type {
+            SECTION_HEADER_BLOCK => {
+                if total_len < 28 {
+                    return Err(Error::InvalidBlockLength);
+                }
+                body[..4].copy_from_slice(&magic);
+                self.input.read_exact(&mut body[4..])?;
+                self.interfaces.clear();
+            }
+            _ => {
+                self.input.read_exact(&mut body)?;
+            }
+        }
+        // The trailing copy of the length is not a part of the body.
+        body.truncate(total_len - 12);
+
+        if block_type == INTERFACE_DESCRIPTION_BLOCK {
+            if body.len() < 8 {
+                return Err(Error::InvalidBlockLength);
+            }
+            let mut interface = Interface {
+                link_type: self.u16_at(&body, 0),
+                snaplen: self.u32_at(&body, 4),
+                name: None,
+                description: None,
+                // Timestamps are in microseconds unless stated otherwise.
+                tsresol: 6,
+            };
+            for (code, value) in self.options(&body[8..]) {
+                match code {
+                    IF_NAME => interface.name = Some(String::from_utf8_lossy(value).into_owned()),
+                    IF_DESCRIPTION => {
+                        interface.description = Some(String::from_utf8_lossy(value).into_owned())
+                    }
+                    IF_TSRESOL if !value.is_empty() => {
+                        if !Interface::is_supported_resolution(value[0]) {
+                            return Err(Error::UnsupportedResolution(value[0]));
+                        }
+                        interface.tsresol = value[0];
+                    }
+                    _ => {}
+                }
+            }
+            self.interfaces.push(interface);
+        }
+        Ok(Some((block_type, body)))
+    }
+
+    /// Splits the options area of a block into (code, value) pairs.
+    fn options<'a>(&self, mut raw: &'a [u8]) -> Vec<(u16, &'a [u8])> {
+        let mut out = Vec::new();
+        while raw.len() >= 4 {
+            let code = self.u16_at(raw, 0);
+            let len = self.u16_at(raw, 2) as usize;
+            if code == OPT_ENDOFOPT || raw.len() < 4 + len {
+                break;
+            }
+            out.push((code, &raw[4..4 + len]));
+            raw = &raw[(4 + len + 3) & !3..];
+        }
+        out
+    }
+
+    fn timestamp(
+        &self,
+        interface_id: u32,
+        high: u32,
+        low: u32,
+    ) -> Result<std::time::SystemTime, Error> {
+        let interface = match self.interfaces.get(interface_id as usize) {
+            Some(i) => i,
+            None => {
+                return Err(Error::UnknownInterface(interface_id));
+            }
+        };
+        let ts = ((high as u64) << 32) | (low as u64);
+        let units = interface.units_per_second();
+        Ok(std::time::UNIX_EPOCH
+            + std::time::Duration::new(
+                ts / units,
+                ((ts % units) as u128 * 1_000_000_000 / units as u128) as u32,
+            ))
+    }
+
+    /// Reads the next packet, skipping blocks that do not contain packets. Returns None at the end of the file.
+    pub fn next_packet(&mut self) -> Result<Option<Packet>, Error> {
+        loop {
+            let (block_type, body) = match self.read_block()? {
+                Some(b) => b,
+                None => {
+                    return Ok(None);
+                }
+            };
+            match block_type {
+                ENHANCED_PACKET_BLOCK | PACKET_BLOCK => {
+                    if body.len() < 20 {
+                        return Err(Error::InvalidBlockLength);
+                    }
+                    let interface_id = match block_type {
+                        PACKET_BLOCK => self.u16_at(&body, 0) as u32,
+                        _ => self.u32_at(&body, 0),
+                    };
+                    let caplen = self.u32_at(&body, 12) as usize;
+                    let data_end = 20 + caplen;
+                    if body.len() < data_end {
+                        return Err(Error::InvalidBlockLength);
+                    }
+                    let comment = match body.get((data_end + 3) & !3..) {
+                        Some(opts) => self
+                            .options(opts)
+                            .into_iter()
+                            .find(|(code, _)| *code == OPT_COMMENT)
+                            .map(|(_, value)| String::from_utf8_lossy(value).into_owned()),
+                        None => None,
+                    };
+                    return Ok(Some(Packet {
+                        interface_id,
+                        timestamp: self.timestamp(
+                            interface_id,
+                            self.u32_at(&body, 4),
+                            self.u32_at(&body, 8),
+                        )?,
+                        original_len: self.u32_at(&body, 16),
+                        data: body[20..data_end].into(),
+                        comment,
+                    }));
+                }
+                SIMPLE_PACKET_BLOCK => {
+                    if body.len() < 4 {
+                        return Err(Error::InvalidBlockLength);
+                    }
+                    let original_len = self.u32_at(&body, 0);
+                    // Simple packets are always captured on the first interface, and are truncated to its snaplen.
+                    let snaplen = match self.interfaces.first() {
+                        Some(i) if i.snaplen != 0 => i.snaplen,
+                        Some(_) => u32::MAX,
+                        None => {
+                            return Err(Error::UnknownInterface(0));
+                        }
+                    };
+                    let caplen = std::cmp::min(
+                        std::cmp::min(original_len, snaplen) as usize,
+                        body.len() - 4,
+                    );
+                    return Ok(Some(Packet {
+                        interface_id: 0,
+                        // Simple packets do not carry a timestamp.
+                        timestamp: std::time::UNIX_EPOCH,
+                        original_len,
+                        data: body[4..4 + caplen].into(),
+                        comment: None,
+                    }));
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+impl<R: Read> Iterator for Reader<R> {
+    type Item = Result<Packet, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_packet().transpose()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn packet(interface_id: u32, nanos: u64, data: &[u8], comment: Option<&str>) -> Packet {
+        Packet {
+            interface_id,
+            timestamp: std::time::UNIX_EPOCH + std::time::Duration::from_nanos(nanos),
+            original_len: data.len() as u32 + 4,
+            data: data.into(),
+            comment: comment.map(String::from),
+        }
+    }
+
+    #[test]
+    fn round_trip() {
+        let mut writer = Writer::new(Vec::new()).unwrap();
+        let mut eth = Interface::new(1, 65535, Some("eth0"));
+        eth.description = Some(String::from("uplink"));
+        assert_eq!(writer.add_interface(&eth).unwrap(), 0);
+        assert_eq!(
+            writer
+                .add_interface(&Interface::new(101, 1500, Some("tun0")))
+                .unwrap(),
+            1
+        );
+        let written = [
+            packet(
+                0,
+                1_600_000_000_123_456_789,
+                &[1, 2, 3, 4, 5],
+                Some("first"),
+            ),
+            packet(1, 1_600_000_001_000_000_001, &[6, 7, 8], None),
+            packet(0, 1_600_000_002_000_000_000, &[], Some("an empty one")),
+        ];
+        for p in written.iter() {
+            writer.write_packet(p).unwrap();
+        }
+        assert!(writer.write_packet(&packet(2, 0, &[], None)).is_err());
+        writer.flush().unwrap();
+
+        let mut reader = Reader::new(&writer.out[..]).unwrap();
+        let mut read = Vec::new();
+        while let Some(p) = reader.next_packet().unwrap() {
+            read.push(p);
+        }
+        let interfaces = reader.interfaces();
+        assert_eq!(interfaces.len(), 2);
+        assert_eq!(interfaces[0].link_type, 1);
+        assert_eq!(interfaces[0].snaplen, 65535);
+        assert_eq!(interfaces[0].name.as_deref(), Some("eth0"));
+        assert_eq!(interfaces[0].description.as_deref(), Some("uplink"));
+        assert_eq!(interfaces[1].link_type, 101);
+        assert_eq!(interfaces[1].name.as_deref(), Some("tun0"));
+
+        assert_eq!(read.len(), written.len());
+        for (r, w) in read.iter().zip(written.iter()) {
+            assert_eq!(r.interface_id, w.interface_id);
+            assert_eq!(r.timestamp, w.timestamp);
+            assert_eq!(r.original_len, w.original_len);
+            assert_eq!(r.data, w.data);
+            assert_eq!(r.comment, w.comment);
+        }
+    }
+
+    /// Builds a little-endian file with a single interface, whose options are given.
+    fn file_with_interface_options(options: &[u8]) -> Vec<u8> {
+        let mut writer = Writer::new(Vec::new()).unwrap();
+        let mut body = vec![1, 0, 0, 0, 0, 0, 0, 0];
+        body.extend_from_slice(options);
+        writer
+            .write_block(INTERFACE_DESCRIPTION_BLOCK, &body)
+            .unwrap();
+        writer.out
+    }
+
+    #[test]
+    fn rejects_unsupported_resolutions() {
+        for tsresol in [20u8, 0xFF, 0x80 | 64].iter() {
+            let file = file_with_interface_options(&[9, 0, 1, 0, *tsresol, 0, 0, 0]);
+            let mut reader = Reader::new(&file[..]).unwrap();
+            match reader.next_packet() {
+                Err(Error::UnsupportedResolution(r)) => assert_eq!(r, *tsresol),
+                r => panic!("resolution 0x{:02x} gave {:?}", tsresol, r),
+            }
+        }
+        for tsresol in [19u8, 0x80 | 63].iter() {
+            let file = file_with_interface_options(&[9, 0, 1, 0, *tsresol, 0, 0, 0]);
+            assert!(Reader::new(&file[..]).unwrap().next_packet().is_ok());
+        }
+    }
+}
@@ -5,6 +5,8 @@ use crate::protocols::{linklayer, networklayer, sessionlayer};
 pub struct Metadata {
     pub mac_src: [u8; linklayer::ethernet::HW_ADDR_LEN],
     pub mac_dst: [u8; linklayer::ethernet::HW_ADDR_LEN],
+    /// The VLAN tags of the frame, outermost first, so that rebuilt frames stay on the same VLAN.
+    pub tags: Vec<linklayer::ethernet::VlanTag>,
     pub ip_src: [u8; 4],
     pub ip_dst: [u8; 4],
     pub mf: bool,
@@ -22,6 +24,7 @@ impl Metadata {
             linklayer::Data::Ethernet(linklayer::ethernet::Frame {
                 dst: mac_dst,
                 src: mac_src,
+                tags,
                 next:
                     networklayer::Data::Ip(networklayer::ip::Packet::V4(networklayer::ip::v4::Packet {
                         src: ip_src,
@@ -41,6 +44,37 @@ impl Metadata {
                 Metadata {
                     mac_dst,
                     mac_src,
+                    tags,
+                    ip_src,
+                    ip_dst,
+                    id,
+                    dscp,
+                    ecn,
+                    mf,
+                    fragment_offset,
+                    ttl,
+                },
+            )),
+            // Packets from tun devices have no link-layer header, so their addresses are left empty.
+            linklayer::Data::RawIp(networklayer::ip::Packet::V4(
+                networklayer::ip::v4::Packet {
+                    src: ip_src,
+                    dst: ip_dst,
+                    id,
+                    dscp,
+                    ecn,
+                    mf,
+                    fragment_offset,
+                    next: session,
+                    ttl,
+                    ..
+                },
+            )) => Some((
+                session,
+                Metadata {
+                    mac_dst: [0; linklayer::ethernet::HW_ADDR_LEN],
+                    mac_src: [0; linklayer::ethernet::HW_ADDR_LEN],
+                    tags: Vec::new(),
                     ip_src,
                     ip_dst,
                     id,
@@ -60,6 +94,7 @@ impl Metadata {
         linklayer::Data::Ethernet(linklayer::ethernet::Frame {
             dst: self.mac_dst,
             src: self.mac_src,
+            tags: self.tags.clone(),
             next: networklayer::Data::Ip(networklayer::ip::Packet::V4(
                 networklayer::ip::v4::Packet::from_metadata(
                     self.ip_src,
@@ -1,7 +1,8 @@
 use std::future;
 
-use crate::control_interfaces;
-use crate::protocols::{networklayer, sessionlayer};
+use crate::control_interfaces::{self, neighbors};
+use crate::pcap_c;
+use crate::protocols::{linklayer, networklayer, sessionlayer};
 
 use tokio_stream::StreamExt;
 
@@ -11,9 +12,9 @@ use serde::Deserialize;
 pub struct Icmp {
     /// The segment that was encoded in the tunnel.
     packet: sessionlayer::tcp::Segment,
-    /// The ICMP action that the packet contained.
-    action: sessionlayer::icmp::Action,
-    /// Data from the rest-of-header field of the packet. This field is used to indicate the destination of TCP segments.
+    /// Whether the packet was an echo request (rather than an echo response).
+    request: bool,
+    /// The identifier and sequence number of the echo. These fields are used to indicate the destination of TCP segments.
     original_dst: [u8; 4],
     /// Metadata about the ICMP packet.
     metadata: control_interfaces::stack::ipv4::Metadata,
@@ -33,12 +34,24 @@ impl super::Tunnelable<control_interfaces::pcap::Data> for Icmp {
             control_interfaces::pcap::Data::LinkLayer(l) => {
                 match control_interfaces::stack::ipv4::Metadata::extract(l) {
                     Some((sessionlayer::Data::Icmp(p), m)) => {
+                        let (request, id, seq) = match p.action {
+                            sessionlayer::icmp::Action::EchoRequest { id, seq } => (true, id, seq),
+                            sessionlayer::icmp::Action::EchoResponse { id, seq } => {
+                                (false, id, seq)
+                            }
+                            _ => return None,
+                        };
                         match sessionlayer::tcp::Segment::from_buffer(&p.data.as_ref()) {
                             Ok(s) => Some(Icmp {
                                 packet: s,
                                 metadata: m,
-                                action: p.action,
-                                original_dst: p.rest,
+                                request,
+                                original_dst: [
+                                    (id >> 8) as u8,
+                                    id as u8,
+                                    (seq >> 8) as u8,
+                                    seq as u8,
+                                ],
                             }),
                             Err(_) => None,
                         }
@@ -46,6 +59,11 @@ impl super::Tunnelable<control_interfaces::pcap::Data> for Icmp {
                     _ => None,
                 }
             }
+            // Raw frames are parsed on demand.
+            control_interfaces::pcap::Data::Raw(r) => match r.parse() {
+                Ok(d) => Self::extract(d),
+                Err(_) => None,
+            },
         }
     }
     fn embellish(self) -> control_interfaces::pcap::Data {
@@ -70,10 +88,14 @@ impl super::Tunnelable<control_interfaces::pcap::Data> for Icmp {
                 })
                 .1,
         );
+        let id = ((self.original_dst[0] as u16) << 8) | (self.original_dst[1] as u16);
+        let seq = ((self.original_dst[2] as u16) << 8) | (self.original_dst[3] as u16);
         let mut out = sessionlayer::icmp::Packet {
-            action: self.action,
+            action: match self.request {
+                true => sessionlayer::icmp::Action::EchoRequest { id, seq },
+                false => sessionlayer::icmp::Action::EchoResponse { id, seq },
+            },
             checksum: 0,
-            rest: self.original_dst,
             data: out_data.into_boxed_slice(),
         };
         out.calc_checksum();
@@ -96,6 +118,11 @@ impl super::Tunnelable<control_interfaces::pcap::Data> for Tcp {
                     _ => None,
                 }
             }
+            // Raw frames are parsed on demand.
+            control_interfaces::pcap::Data::Raw(r) => match r.parse() {
+                Ok(d) => Self::extract(d),
+                Err(_) => None,
+            },
         }
     }
     fn embellish(self) -> control_interfaces::pcap::Data {
@@ -112,9 +139,14 @@ pub struct EndpointIdentity {
     /// The IPv4 address of the machine.
     pub ip: [u8; 4],
     /// The MAC address used to route data to the machine.
+    /// It can be left out of the configuration, in which case it is resolved when the tunnel is opened.
+    #[serde(default)]
     pub mac: [u8; 6],
 }
 
+/// The MAC address of an identity whose address has been left out of the configuration.
+const UNRESOLVED: [u8; 6] = [0; 6];
+
 /// A proxy's role in the tunnel's operation.
 #[derive(Deserialize)]
 pub enum Role {
@@ -123,7 +155,12 @@ pub enum Role {
     Aware(EndpointIdentity),
     /// The proxy interacts with the outside world.
     /// In this case, we will store the route that the proxy will use as it's default gateway.
-    Unaware { gateway: [u8; 6] },
+    /// The gateway's MAC address can be left out if its IPv4 address is given, and is then resolved when the tunnel is opened.
+    Unaware {
+        #[serde(default)]
+        gateway: [u8; 6],
+        gateway_ip: Option<[u8; 4]>,
+    },
 }
 
 #[derive(Deserialize)]
@@ -136,18 +173,25 @@ pub struct Tunnel {
     pub meta: EndpointIdentity,
     /// The proxy's role.
     pub my_role: Role,
+    /// The VLAN tags of the latest frames from each side, which are put on the frames that are sent to that side.
+    #[serde(skip)]
+    clear_tags: Vec<linklayer::ethernet::VlanTag>,
+    #[serde(skip)]
+    meta_tags: Vec<linklayer::ethernet::VlanTag>,
 }
 
 impl super::Tunnel<Tcp, Icmp> for Tunnel {
     type Base = control_interfaces::pcap::Data;
     fn decode(&mut self, inp: Icmp) -> Tcp {
+        self.clear_tags = inp.metadata.tags;
         Tcp {
             metadata: control_interfaces::stack::ipv4::Metadata {
                 mac_src: self.meta.mac.clone(),
                 mac_dst: match &self.my_role {
                     &Role::Aware(ref e) => e.mac.clone(),
-                    &Role::Unaware { gateway } => gateway.clone(),
+                    &Role::Unaware { gateway, .. } => gateway.clone(),
                 },
+                tags: self.meta_tags.clone(),
                 ip_src: match &self.my_role {
                     &Role::Aware(_) => inp.original_dst.clone(),
                     &Role::Unaware { .. } => self.meta.ip.clone(),
@@ -170,11 +214,13 @@ impl super::Tunnel<Tcp, Icmp> for Tunnel {
         }
     }
     fn encode(&mut self, inp: Tcp) -> Icmp {
+        self.meta_tags = inp.metadata.tags;
         Icmp {
             packet: inp.segment,
             metadata: control_interfaces::stack::ipv4::Metadata {
                 mac_src: self.clear.mac.clone(),
                 mac_dst: self.other_proxy.mac.clone(),
+                tags: self.clear_tags.clone(),
                 ip_src: self.clear.ip.clone(),
                 ip_dst: self.other_proxy.ip.clone(),
 		//Fragmentation is not currently supported.
@@ -187,9 +233,9 @@ impl super::Tunnel<Tcp, Icmp> for Tunnel {
                 ttl: inp.metadata.ttl,
                 ecn: networklayer::ip::Ecn::NonEcnCapable,
             },
-            action: match &self.my_role {
-                &Role::Aware(_) => sessionlayer::icmp::Action::EchoRequest,
-                &Role::Unaware { .. } => sessionlayer::icmp::Action::EchoResponse,
+            request: match &self.my_role {
+                &Role::Aware(_) => true,
+                &Role::Unaware { .. } => false,
             },
             original_dst: match &self.my_role {
                 &Role::Aware(_) => inp.metadata.ip_dst,
@@ -201,35 +247,238 @@ impl super::Tunnel<Tcp, Icmp> for Tunnel {
 
 #[derive(std::fmt::Debug)]
 pub enum InterfaceError {
-    OpenClear,
-    OpenInt,
-    ApplyFilter,
+    #[cfg(feature = "libpcap")]
+    OpenClear(control_interfaces::pcap::Error),
+    #[cfg(feature = "libpcap")]
+    OpenInt(control_interfaces::pcap::Error),
+    #[cfg(feature = "libpcap")]
+    OpenDevice(control_interfaces::tuntap::Error),
+    ApplyFilter(control_interfaces::pcap::Error),
+    OpenCapture(control_interfaces::pcapng::Error),
+    /// The MAC address of the named interface is needed, but could not be found.
+    NoAddress(String),
+    /// The gateway's MAC address has been left out, and there is no IPv4 address to resolve it from.
+    NoGateway,
+    Resolve(neighbors::Error),
+}
+
+impl std::fmt::Display for InterfaceError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            #[cfg(feature = "libpcap")]
+            InterfaceError::OpenClear(e) => write!(f, "could not open the tunnel interface: {}", e),
+            #[cfg(feature = "libpcap")]
+            InterfaceError::OpenInt(e) => write!(f, "could not open the local interface: {}", e),
+            #[cfg(feature = "libpcap")]
+            InterfaceError::OpenDevice(e) => write!(f, "could not open the local device: {}", e),
+            InterfaceError::ApplyFilter(e) => write!(f, "could not filter tunnel traffic: {}", e),
+            InterfaceError::OpenCapture(e) => write!(f, "could not open capture file: {}", e),
+            InterfaceError::NoAddress(name) => {
+                write!(f, "could not find the MAC address of {}", name)
+            }
+            InterfaceError::NoGateway => write!(
+                f,
+                "the gateway needs either a MAC address or an IPv4 address"
+            ),
+            InterfaceError::Resolve(e) => write!(f, "could not resolve a MAC address: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for InterfaceError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            #[cfg(feature = "libpcap")]
+            InterfaceError::OpenClear(e) => Some(e),
+            #[cfg(feature = "libpcap")]
+            InterfaceError::OpenInt(e) => Some(e),
+            #[cfg(feature = "libpcap")]
+            InterfaceError::OpenDevice(e) => Some(e),
+            InterfaceError::ApplyFilter(e) => Some(e),
+            InterfaceError::OpenCapture(e) => Some(e),
+            InterfaceError::Resolve(e) => Some(e),
+            _ => None,
+        }
+    }
 }
 
+/// The size of the kernel buffer of both capture handles, large enough to hold bursts of tunneled traffic.
+#[cfg(feature = "libpcap")]
+const CAPTURE_BUFFER_SIZE: i32 = 8 * 1024 * 1024;
+
 impl Tunnel {
     /// Goes through the process of opening interfaces in the manner required by the tunnel.
-    pub fn open_with(
+    /// If a capture path is given, the packets injected to both interfaces are written to a single pcapng file.
+    /// The warnings that libpcap reported while activating the handles are returned along with the runner.
+    #[cfg(feature = "libpcap")]
+    pub async fn open_with(
         self,
         clear_iface: &str,
         int_iface: &str,
+        capture_path: Option<&str>,
+    ) -> Result<(impl future::Future, Vec<control_interfaces::pcap::Error>), InterfaceError> {
+        let mut plain_handle = Tunnel::open_tunnel_interface(clear_iface)?;
+
+        let mut meta_handle = match control_interfaces::pcap::CaptureHandle::builder(int_iface)
+            .buffer_size(CAPTURE_BUFFER_SIZE)
+            .immediate_mode(true)
+            .activate()
+        {
+            Ok(t) => t,
+            Err(e) => return Err(InterfaceError::OpenInt(e)),
+        };
+
+        let warnings = plain_handle
+            .take_warning()
+            .into_iter()
+            .chain(meta_handle.take_warning())
+            .collect();
+        let runner = self
+            .open_on(plain_handle, meta_handle, capture_path)
+            .await?;
+        Ok((runner, warnings))
+    }
+
+    /// Like `open_with`, but the local side is a TUN or TAP device that is created (or attached to) and brought up.
+    /// Local traffic can then be routed into the tunnel, instead of being sniffed off a real interface.
+    #[cfg(feature = "libpcap")]
+    pub async fn open_with_device(
+        self,
+        clear_iface: &str,
+        device: &str,
+        kind: control_interfaces::tuntap::Kind,
+        capture_path: Option<&str>,
+    ) -> Result<(impl future::Future, Vec<control_interfaces::pcap::Error>), InterfaceError> {
+        let mut plain_handle = Tunnel::open_tunnel_interface(clear_iface)?;
+
+        let meta_device = match control_interfaces::tuntap::Device::open(device, kind)
+            .and_then(|d| d.bring_up().map(|_| d))
+        {
+            Ok(t) => t,
+            Err(e) => return Err(InterfaceError::OpenDevice(e)),
+        };
+
+        let warnings = plain_handle.take_warning().into_iter().collect();
+        let runner = self
+            .open_on(plain_handle, meta_device, capture_path)
+            .await?;
+        Ok((runner, warnings))
+    }
+
+    #[cfg(feature = "libpcap")]
+    fn open_tunnel_interface(
+        clear_iface: &str,
+    ) -> Result<control_interfaces::pcap::CaptureHandle, InterfaceError> {
+        match control_interfaces::pcap::CaptureHandle::builder(clear_iface)
+            .buffer_size(CAPTURE_BUFFER_SIZE)
+            .immediate_mode(true)
+            .activate()
+        {
+            Ok(t) => Ok(t),
+            Err(e) => Err(InterfaceError::OpenClear(e)),
+        }
+    }
+
+    /// The MAC address of an interface that has been opened, as reported by libpcap.
+    #[cfg(feature = "libpcap")]
+    fn own_mac<I: control_interfaces::PacketInterface>(
+        handle: &I,
+    ) -> Result<[u8; 6], InterfaceError> {
+        let name = handle.name().unwrap_or("");
+        match control_interfaces::devices::find(name) {
+            Ok(Some(d)) => d
+                .mac()
+                .ok_or_else(|| InterfaceError::NoAddress(String::from(name))),
+            _ => Err(InterfaceError::NoAddress(String::from(name))),
+        }
+    }
+
+    /// Devices can only be listed through libpcap, so the addresses of the proxy must be configured without it.
+    #[cfg(not(feature = "libpcap"))]
+    fn own_mac<I: control_interfaces::PacketInterface>(
+        handle: &I,
+    ) -> Result<[u8; 6], InterfaceError> {
+        Err(InterfaceError::NoAddress(String::from(
+            handle.name().unwrap_or(""),
+        )))
+    }
+
+    /// Fills in the MAC addresses that have been left out of the configuration.
+    /// This is done before the interfaces are filtered, so that the ARP replies can still be seen.
+    async fn resolve_addresses<
+        P: control_interfaces::PacketInterface,
+        M: control_interfaces::PacketInterface,
+    >(
+        &mut self,
+        plain_handle: &mut P,
+        meta_handle: &mut M,
+    ) -> Result<(), InterfaceError> {
+        if self.clear.mac == UNRESOLVED {
+            self.clear.mac = Tunnel::own_mac(plain_handle)?;
+        }
+        if self.other_proxy.mac == UNRESOLVED {
+            let neighbors = neighbors::Table::new(neighbors::Host {
+                mac: self.clear.mac,
+                ip: self.clear.ip,
+            });
+            self.other_proxy.mac = match neighbors
+                .resolve_on(plain_handle, self.other_proxy.ip)
+                .await
+            {
+                Ok(mac) => mac,
+                Err(e) => return Err(InterfaceError::Resolve(e)),
+            };
+        }
+
+        // TUN devices carry bare IP packets, so there are no MAC addresses to resolve on them.
+        if meta_handle.link_type() != pcap_c::DLT_EN10MB {
+            return Ok(());
+        }
+        if self.meta.mac == UNRESOLVED {
+            self.meta.mac = Tunnel::own_mac(meta_handle)?;
+        }
+        let neighbors = neighbors::Table::new(neighbors::Host {
+            mac: self.meta.mac,
+            ip: self.meta.ip,
+        });
+        let (mac, ip) = match self.my_role {
+            Role::Aware(ref mut e) if e.mac == UNRESOLVED => (&mut e.mac, e.ip),
+            Role::Unaware {
+                ref mut gateway,
+                gateway_ip,
+            } if *gateway == UNRESOLVED => match gateway_ip {
+                Some(ip) => (gateway, ip),
+                None => return Err(InterfaceError::NoGateway),
+            },
+            _ => return Ok(()),
+        };
+        *mac = match neighbors.resolve_on(meta_handle, ip).await {
+            Ok(mac) => mac,
+            Err(e) => return Err(InterfaceError::Resolve(e)),
+        };
+        Ok(())
+    }
+
+    /// Runs the tunnel on interfaces that have already been opened (for example, the ends of an in-memory wire).
+    /// `plain_handle` carries the tunneled ICMP traffic, and `meta_handle` carries the TCP traffic.
+    /// The MAC addresses that have been left out of the configuration are resolved first.
+    pub async fn open_on<
+        P: control_interfaces::PacketInterface,
+        M: control_interfaces::PacketInterface,
+    >(
+        mut self,
+        mut plain_handle: P,
+        mut meta_handle: M,
+        capture_path: Option<&str>,
     ) -> Result<impl future::Future, InterfaceError> {
+        self.resolve_addresses(&mut plain_handle, &mut meta_handle)
+            .await?;
+
         let (inject_snd, inject_rcv) =
             control_interfaces::mpsc::Receiver::<control_interfaces::pcap::Data>::new_bounded(100);
         let (aux_snd, aux_rcv) =
             control_interfaces::mpsc::Receiver::<control_interfaces::pcap::Data>::new_bounded(100);
-
-        let mut plain_handle = match control_interfaces::pcap::CaptureHandle::open_live(
-            clear_iface,
-            control_interfaces::pcap::CaptureMode::Promisc,
-            100,
-            65535,
-        ) {
-            Ok(t) => t,
-            Err(_) => {
-                return Err(InterfaceError::OpenClear);
-            }
-        };
-        match plain_handle.with_filter(
+        match plain_handle.set_filter(
             format!(
                 "ip proto \\icmp and dst net {}.{}.{}.{} and src net {}.{}.{}.{} and icmp[icmptype] = {}",
                 self.clear.ip[0],
@@ -243,26 +492,14 @@ impl Tunnel {
 		match &self.my_role {&Role::Aware(_) => "icmp-echoreply", &Role::Unaware{..} => "icmp-echo"}
             )
             .as_str(),
-            None,
         ) {
             Ok(_) => {}
-            Err(_) => {
-                eprintln!("Could not compile or apply filter!");
-                return Err(InterfaceError::ApplyFilter);
+            Err(e) => {
+                return Err(InterfaceError::ApplyFilter(e));
             }
         }
 
-        let mut meta_handle = match control_interfaces::pcap::CaptureHandle::open_live(
-            int_iface,
-            control_interfaces::pcap::CaptureMode::Promisc,
-            100,
-            65535,
-        ) {
-            Ok(t) => t,
-            Err(_) => return Err(InterfaceError::OpenInt),
-        };
-
-        match meta_handle.with_filter(
+        match meta_handle.set_filter(
             match self.my_role {
                 Role::Unaware { .. } => format!(
                     "ip proto \\tcp and dst net {}.{}.{}.{}",
@@ -274,19 +511,56 @@ impl Tunnel {
                 ),
             }
             .as_str(),
-            None,
         ) {
             Ok(_) => {}
-            Err(_) => {
-                eprintln!("Could not compile or apply filter");
-                return Err(InterfaceError::ApplyFilter);
+            Err(e) => {
+                return Err(InterfaceError::ApplyFilter(e));
             }
         };
 
+        // The proxy's plain side is the interface that carries TCP, and its meta side is the interface that carries ICMP.
+        let capture = match capture_path {
+            Some(path) => match control_interfaces::pcapng::Writer::create(path).and_then(|w| {
+                crate::agents::proxy::Capture::new(
+                    w,
+                    &control_interfaces::pcapng::Interface::of_handle(
+                        &meta_handle,
+                        meta_handle.name(),
+                    ),
+                    &control_interfaces::pcapng::Interface::of_handle(
+                        &plain_handle,
+                        plain_handle.name(),
+                    ),
+                )
+            }) {
+                Ok(c) => Some(c),
+                Err(e) => {
+                    return Err(InterfaceError::OpenCapture(e));
+                }
+            },
+            None => None,
+        };
+
+        let plain_name = String::from(plain_handle.name().unwrap_or("tunnel"));
+        let meta_name = String::from(meta_handle.name().unwrap_or("local"));
         let (main_cloud, packets) =
             control_interfaces::pcap_cloud::Cloud::new(plain_handle, inject_rcv);
+        let main_cloud = main_cloud
+            .with_stats_report(
+                plain_name.as_str(),
+                control_interfaces::pcap_cloud::STATS_REPORT_PERIOD,
+            )
+            // Only the other proxy's messages are captured on the tunnel interface, so the host's own outgoing traffic can be hidden.
+            .with_loop_protection(control_interfaces::pcap_cloud::LoopProtection::IncomingOnly);
         let (clear_cloud, cleartext) =
             control_interfaces::pcap_cloud::Cloud::new(meta_handle, aux_rcv);
+        let clear_cloud = clear_cloud
+            .with_stats_report(
+                meta_name.as_str(),
+                control_interfaces::pcap_cloud::STATS_REPORT_PERIOD,
+            )
+            // The host's own connections may have to be tunneled, so its outgoing traffic must still be captured.
+            .with_loop_protection(control_interfaces::pcap_cloud::LoopProtection::Fingerprints);
 
         let mut proxy = crate::agents::proxy::new(
             cleartext.filter_map(|r| match r {
@@ -300,7 +574,135 @@ impl Tunnel {
             aux_snd,
             inject_snd,
         );
+        if let Some(c) = capture {
+            proxy = proxy.with_capture(c);
+        }
 
         Ok(async move { tokio::join!(main_cloud.run(), clear_cloud.run(), proxy.run(self)) })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::super::Tunnelable;
+    use super::*;
+    use crate::control_interfaces::{memory, PacketInterface};
+
+    const CLIENT: ([u8; 6], [u8; 4]) = ([2, 0, 0, 0, 0, 1], [10, 0, 0, 1]);
+    const META: ([u8; 6], [u8; 4]) = ([2, 0, 0, 0, 0, 2], [10, 0, 0, 254]);
+    const CLEAR: ([u8; 6], [u8; 4]) = ([2, 0, 0, 0, 1, 1], [192, 168, 0, 1]);
+    const OTHER_PROXY: ([u8; 6], [u8; 4]) = ([2, 0, 0, 0, 1, 2], [192, 168, 0, 2]);
+    const SERVER_IP: [u8; 4] = [93, 184, 216, 34];
+
+    fn identity((mac, ip): ([u8; 6], [u8; 4])) -> EndpointIdentity {
+        EndpointIdentity { ip, mac }
+    }
+
+    fn metadata(
+        (mac_src, ip_src): ([u8; 6], [u8; 4]),
+        (mac_dst, ip_dst): ([u8; 6], [u8; 4]),
+    ) -> control_interfaces::stack::ipv4::Metadata {
+        control_interfaces::stack::ipv4::Metadata {
+            mac_src,
+            mac_dst,
+            tags: Vec::new(),
+            ip_src,
+            ip_dst,
+            mf: false,
+            fragment_offset: networklayer::ip::v4::FragmentOffset::Arbitrary(0),
+            id: 0x1234,
+            ttl: 61,
+            dscp: 0,
+            ecn: networklayer::ip::Ecn::NonEcnCapable,
+        }
+    }
+
+    /// A segment from port 40000 to port 80 with the given flags and payload.
+    fn segment(flags: u8, payload: &[u8]) -> sessionlayer::tcp::Segment {
+        let mut raw = vec![
+            0x9c, 0x40, 0x00, 0x50, 0, 0, 0, 1, 0, 0, 0, 0, 0x50, flags, 0xff, 0xff, 0, 0, 0, 0,
+        ];
+        raw.extend_from_slice(payload);
+        sessionlayer::tcp::Segment::from_buffer(&raw).unwrap()
+    }
+
+    /// The segment as it is written out, without its checksum (which depends on the IPv4 addresses).
+    fn segment_bytes(segment: &sessionlayer::tcp::Segment) -> Vec<u8> {
+        let mut raw = segment.into_buffer((0, 0), 0);
+        raw[16] = 0;
+        raw[17] = 0;
+        raw
+    }
+
+    // The tunnel filters the traffic of its interfaces, and filters are compiled by libpcap.
+    #[cfg(feature = "libpcap")]
+    #[tokio::test]
+    async fn tcp_through_icmp_and_back() {
+        let (tunnel_side, mut other_proxy) =
+            memory::wire(pcap_c::DLT_EN10MB, ("tunnel", "other-proxy"));
+        let (local_side, mut client) = memory::wire(pcap_c::DLT_EN10MB, ("local", "client"));
+        let tunnel = Tunnel {
+            other_proxy: identity(OTHER_PROXY),
+            clear: identity(CLEAR),
+            meta: identity(META),
+            my_role: Role::Aware(identity(CLIENT)),
+            clear_tags: Vec::new(),
+            meta_tags: Vec::new(),
+        };
+        let runner = match tunnel.open_on(tunnel_side, local_side, None).await {
+            Ok(r) => r,
+            Err(e) => panic!("{}", e),
+        };
+
+        let exchange = async {
+            // The client's segment leaves through the tunnel as an echo request to the other proxy.
+            let syn = segment(0x02, &[]);
+            let syn_bytes = segment_bytes(&syn);
+            client
+                .inject(
+                    Tcp {
+                        segment: syn,
+                        metadata: metadata(CLIENT, (META.0, SERVER_IP)),
+                    }
+                    .embellish(),
+                )
+                .unwrap();
+            let icmp = Icmp::extract(other_proxy.next().await.unwrap().unwrap().1).unwrap();
+            assert!(icmp.request);
+            assert_eq!(icmp.original_dst, SERVER_IP);
+            assert_eq!(icmp.metadata.mac_src, CLEAR.0);
+            assert_eq!(icmp.metadata.mac_dst, OTHER_PROXY.0);
+            assert_eq!(icmp.metadata.ip_src, CLEAR.1);
+            assert_eq!(icmp.metadata.ip_dst, OTHER_PROXY.1);
+            assert_eq!(icmp.metadata.id, 0x1234);
+            assert_eq!(icmp.metadata.ttl, 61);
+            assert_eq!(segment_bytes(&icmp.packet), syn_bytes);
+
+            // The other proxy's echo reply reaches the client as a segment from the server.
+            let syn_ack = segment(0x12, b"hello");
+            let syn_ack_bytes = segment_bytes(&syn_ack);
+            other_proxy
+                .inject(
+                    Icmp {
+                        packet: syn_ack,
+                        request: false,
+                        original_dst: SERVER_IP,
+                        metadata: metadata(OTHER_PROXY, CLEAR),
+                    }
+                    .embellish(),
+                )
+                .unwrap();
+            let tcp = Tcp::extract(client.next().await.unwrap().unwrap().1).unwrap();
+            assert_eq!(tcp.metadata.mac_src, META.0);
+            assert_eq!(tcp.metadata.mac_dst, CLIENT.0);
+            assert_eq!(tcp.metadata.ip_src, SERVER_IP);
+            assert_eq!(tcp.metadata.ip_dst, CLIENT.1);
+            assert_eq!(segment_bytes(&tcp.segment), syn_ack_bytes);
+        };
+
+        tokio::select! {
+            _ = runner => panic!("the tunnel stopped"),
+            _ = exchange => {}
+        }
+    }
+}
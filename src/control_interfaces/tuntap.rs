@@ -0,0 +1,287 @@
+use super::pcap;
+use crate::pcap_c;
+use crate::protocols::linklayer;
+
+use std::io::{Read, Write};
+use std::os::unix::fs::OpenOptionsExt;
+use std::os::unix::io::AsRawFd;
+use std::pin::Pin;
+use std::task::Poll;
+use tokio_stream::Stream;
+
+/// The kind of frames that a virtual device carries.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Kind {
+    /// A TUN device carries raw IP packets.
+    Tun,
+    /// A TAP device carries Ethernet frames.
+    Tap,
+}
+
+#[derive(Debug)]
+pub enum Error {
+    /// /dev/net/tun could not be opened.
+    Open(std::io::Error),
+    /// The device could not be created or attached to.
+    Attach(std::io::Error),
+    /// The device could not be brought up.
+    BringUp(std::io::Error),
+    /// The device could not be registered with the runtime.
+    Register(std::io::Error),
+    /// The name of the device is too long or contains a null byte.
+    InvalidName(String),
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Error::Open(e) => write!(f, "could not open {}: {}", CLONE_DEVICE, e),
+            Error::Attach(e) => write!(f, "could not attach to the device: {}", e),
+            Error::BringUp(e) => write!(f, "could not bring the device up: {}", e),
+            Error::Register(e) => write!(f, "could not wait for the device: {}", e),
+            Error::InvalidName(n) => write!(f, "invalid device name {:?}", n),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Open(e) => Some(e),
+            Error::Attach(e) => Some(e),
+            Error::BringUp(e) => Some(e),
+            Error::Register(e) => Some(e),
+            Error::InvalidName(_) => None,
+        }
+    }
+}
+
+/// The device that TUN and TAP devices are created through.
+const CLONE_DEVICE: &str = "/dev/net/tun";
+
+/// _IOW('T', 202, int), as defined in linux/if_tun.h.
+const TUNSETIFF: libc::c_ulong = 0x400454ca;
+
+/// The largest frame that is read from a device. Frames are never truncated, so this is also the snaplen.
+const FRAME_BUFFER_SIZE: usize = 65536;
+
+/// The part of struct ifreq that is used to create devices and to set their flags.
+#[repr(C)]
+struct IfReq {
+    name: [libc::c_char; libc::IFNAMSIZ],
+    flags: libc::c_short,
+    /// The rest of the union in struct ifreq, which is never used here.
+    _pad: [u8; 22],
+}
+
+impl IfReq {
+    fn new(name: &str) -> Result<IfReq, Error> {
+        // The name must leave room for the terminating null byte.
+        if name.len() >= libc::IFNAMSIZ || name.bytes().any(|b| b == 0) {
+            return Err(Error::InvalidName(String::from(name)));
+        }
+        let mut req = IfReq {
+            name: [0; libc::IFNAMSIZ],
+            flags: 0,
+            _pad: [0; 22],
+        };
+        for (d, s) in req.name.iter_mut().zip(name.bytes()) {
+            *d = s as libc::c_char;
+        }
+        Ok(req)
+    }
+}
+
+/// A TUN or TAP device. Frames that the kernel routes to the device are captured, and injected frames are handed to the kernel as if they had arrived on it.
+pub struct Device {
+    name: String,
+    kind: Kind,
+    file: tokio::io::unix::AsyncFd<std::fs::File>,
+    /// Frames that do not match the filter are not captured.
+    filter: Option<pcap::Filter>,
+    /// The number of frames that have been captured. The kernel drops frames when they are not read fast enough, but does not report it.
+    received: u32,
+    buffer: Box<[u8]>,
+}
+
+impl Device {
+    /// Creates a device, or attaches to an existing one with the same name and kind.
+    /// An empty name lets the kernel choose one (e.g. tun0).
+    pub fn open(name: &str, kind: Kind) -> Result<Device, Error> {
+        let file = match std::fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .custom_flags(libc::O_NONBLOCK)
+            .open(CLONE_DEVICE)
+        {
+            Ok(f) => f,
+            Err(e) => return Err(Error::Open(e)),
+        };
+        let mut req = IfReq::new(name)?;
+        // Without IFF_NO_PI, every frame would be preceded by a 4-byte header.
+        req.flags = (libc::IFF_NO_PI
+            | match kind {
+                Kind::Tun => libc::IFF_TUN,
+                Kind::Tap => libc::IFF_TAP,
+            }) as libc::c_short;
+        if unsafe { libc::ioctl(file.as_raw_fd(), TUNSETIFF, &mut req) } < 0 {
+            return Err(Error::Attach(std::io::Error::last_os_error()));
+        }
+        // The kernel writes back the name of the device, which may have been chosen by it.
+        let name = unsafe { std::ffi::CStr::from_ptr(req.name.as_ptr()) }
+            .to_string_lossy()
+            .into_owned();
+        Ok(Device {
+            name,
+            kind,
+            file: match tokio::io::unix::AsyncFd::new(file) {
+                Ok(f) => f,
+                Err(e) => return Err(Error::Register(e)),
+            },
+            filter: None,
+            received: 0,
+            buffer: vec![0; FRAME_BUFFER_SIZE].into_boxed_slice(),
+        })
+    }
+
+    /// Sets the IFF_UP flag of the device, as `ip link set <name> up` would. Addresses and routes are left to the user.
+    pub fn bring_up(&self) -> Result<(), Error> {
+        let mut req = IfReq::new(self.name.as_str())?;
+        unsafe {
+            let sock = libc::socket(libc::AF_INET, libc::SOCK_DGRAM, 0);
+            if sock < 0 {
+                return Err(Error::BringUp(std::io::Error::last_os_error()));
+            }
+            let result = if libc::ioctl(sock, libc::SIOCGIFFLAGS, &mut req) < 0 {
+                Err(Error::BringUp(std::io::Error::last_os_error()))
+            } else {
+                req.flags |= libc::IFF_UP as libc::c_short;
+                match libc::ioctl(sock, libc::SIOCSIFFLAGS, &mut req) < 0 {
+                    true => Err(Error::BringUp(std::io::Error::last_os_error())),
+                    false => Ok(()),
+                }
+            };
+            libc::close(sock);
+            result
+        }
+    }
+
+    fn inject_error(&self, e: std::io::Error) -> pcap::Error {
+        pcap::Error::new(pcap::Operation::Inject, &e.to_string()).with_context(self.name.as_str())
+    }
+}
+
+impl Stream for Device {
+    type Item = Result<(std::time::SystemTime, pcap::Data), pcap::CaptureError>;
+
+    /// Waits for the kernel to route a frame to the device.
+    fn poll_next(
+        mut self: Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+    ) -> Poll<Option<Self::Item>> {
+        let this = &mut *self;
+        loop {
+            let mut ready = match this.file.poll_read_ready(cx) {
+                Poll::Ready(Ok(r)) => r,
+                Poll::Ready(Err(e)) => {
+                    return Poll::Ready(Some(Err(pcap::CaptureError::CouldNotRead(e))))
+                }
+                Poll::Pending => return Poll::Pending,
+            };
+            let len = match this.file.get_ref().read(&mut this.buffer) {
+                Ok(0) => return Poll::Ready(None),
+                Ok(n) => n,
+                Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => {
+                    // Every queued frame has been read, so the next one has to be waited for.
+                    ready.clear_ready();
+                    continue;
+                }
+                Err(e) => return Poll::Ready(Some(Err(pcap::CaptureError::CouldNotRead(e)))),
+            };
+            let raw = &this.buffer[..len];
+            if let Some(ref f) = this.filter {
+                if !f.matches_buffer(raw, len as u32) {
+                    continue;
+                }
+            }
+            this.received += 1;
+            return Poll::Ready(Some(
+                pcap::Data::from_link_type(
+                    match this.kind {
+                        Kind::Tun => pcap_c::LINKTYPE_RAW,
+                        Kind::Tap => pcap_c::DLT_EN10MB,
+                    },
+                    raw,
+                )
+                .map(|d| (std::time::SystemTime::now(), d)),
+            ));
+        }
+    }
+}
+
+impl super::PacketInterface for Device {
+    fn name(&self) -> Option<&str> {
+        Some(self.name.as_str())
+    }
+    fn link_type(&self) -> i32 {
+        match self.kind {
+            Kind::Tun => pcap_c::LINKTYPE_RAW,
+            Kind::Tap => pcap_c::DLT_EN10MB,
+        }
+    }
+    fn snaplen(&self) -> i32 {
+        FRAME_BUFFER_SIZE as i32
+    }
+    /// Ethernet frames that are injected into a TUN device lose their link-layer header, so that agents that build Ethernet frames can use either kind of device.
+    fn inject(&mut self, data: pcap::Data) -> Result<(), pcap::Error> {
+        match (self.kind, data) {
+            (
+                Kind::Tun,
+                pcap::Data::LinkLayer(linklayer::Data::Ethernet(linklayer::ethernet::Frame {
+                    next,
+                    ..
+                })),
+            ) => self.inject_buffer(&next.into_buffer((0, 0))),
+            (_, d) => self.inject_buffer(&d.into_buffer((0, 0))),
+        }
+    }
+    fn inject_buffer(&mut self, raw: &[u8]) -> Result<(), pcap::Error> {
+        // The kernel takes a whole frame in each write, or rejects it (e.g. when its queue is full).
+        match self.file.get_ref().write(raw) {
+            Ok(n) if n == raw.len() => Ok(()),
+            Ok(n) => Err(pcap::Error::new(
+                pcap::Operation::Inject,
+                &format!("only {} of {} bytes were written", n, raw.len()),
+            )
+            .with_context(self.name.as_str())),
+            Err(e) => Err(self.inject_error(e)),
+        }
+    }
+    fn set_filter(&mut self, code: &str) -> Result<(), pcap::Error> {
+        self.filter = Some(pcap::Filter::compile(
+            self.link_type(),
+            self.snaplen(),
+            code,
+            None,
+        )?);
+        Ok(())
+    }
+    /// Injected frames are handed to the kernel rather than routed to the device, so they are never captured regardless of the direction.
+    fn set_direction(&mut self, direction: pcap::Direction) -> Result<(), pcap::Error> {
+        match direction {
+            pcap::Direction::In | pcap::Direction::InOut => Ok(()),
+            pcap::Direction::Out => Err(pcap::Error::new(
+                pcap::Operation::SetDirection,
+                "devices can not capture the frames that are injected into them",
+            )
+            .with_context(self.name.as_str())),
+        }
+    }
+    fn stats(&mut self) -> Result<pcap::Stats, pcap::Error> {
+        Ok(pcap::Stats {
+            received: self.received,
+            dropped: 0,
+            if_dropped: 0,
+        })
+    }
+}
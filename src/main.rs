@@ -1,3 +1,6 @@
+// Without libpcap, the agents that need it are left out, along with much of the code that only they use.
+#![cfg_attr(not(feature = "libpcap"), allow(dead_code, unused_imports))]
+
 use std::env;
 
 /// Agents are functions that make decisions and are run by the main function.
@@ -16,6 +19,8 @@ use std::fs::File;
 use std::io::BufReader;
 use std::path::Path;
 
+use control_interfaces::PacketInterface;
+
 #[tokio::main]
 async fn main() {
     let args: Vec<String> = env::args().collect();
@@ -25,6 +30,24 @@ async fn main() {
         return;
     }
 
+    // The agent restores the caches of its targets once ctrl-C is pressed, which takes a while, so it is stopped rather than dropped.
+    #[cfg(feature = "libpcap")]
+    if args[1] == "arp-mitm" {
+        let agent = tokio::select! {
+            a = arp_mitm(&args) => a,
+            _ = tokio::signal::ctrl_c() => None,
+        };
+        if let Some(mut agent) = agent {
+            tokio::select! {
+                _ = agent.run() => {},
+                _ = tokio::signal::ctrl_c() => {}
+            }
+            println!("Cleaning up...");
+            agent.stop().await;
+        }
+        return;
+    }
+
     // The method runs some agent, and stops it's execution once ctrl-C is pressed.
     tokio::select! {
     biased;
@@ -38,10 +61,11 @@ async fn main() {
 async fn handle_args(args: Vec<String>) {
     match args[1].as_str() {
         // A TCP-over-ICMP tunnel.
+        #[cfg(feature = "libpcap")]
         "icmp-tcp" => {
             if args.len() < 5 {
                 eprintln!(
-            "Usage: <filename> icmp-tcp <tunnel_interface> <local_interface> <configuration_file.json>"
+            "Usage: <filename> icmp-tcp <tunnel_interface> <local_interface|tun:<name>|tap:<name>> <configuration_file.json> {{<capture.pcapng>}}"
 		);
                 return;
             }
@@ -64,62 +88,393 @@ async fn handle_args(args: Vec<String>) {
                     }
                 };
 
-            let proxy_runner = match tun.open_with(args[2].as_str(), args[3].as_str()) {
-                Ok(t) => t,
-                Err(e) => {
-                    eprintln!("Could not open interfaces: {:?}", e);
-                    return;
-                }
+            // The local side can be a virtual device that local traffic is routed into.
+            let device = match args[3].split_once(':') {
+                Some(("tun", name)) => Some((name, control_interfaces::tuntap::Kind::Tun)),
+                Some(("tap", name)) => Some((name, control_interfaces::tuntap::Kind::Tap)),
+                _ => None,
             };
+            let capture_path = args.get(5).map(|p| p.as_str());
 
-            proxy_runner.await;
+            // The runners have different types, so each one is awaited on its own.
+            let result = match device {
+                Some((name, kind)) => {
+                    match tun
+                        .open_with_device(args[2].as_str(), name, kind, capture_path)
+                        .await
+                    {
+                        Ok((t, warnings)) => {
+                            print_warnings(warnings);
+                            t.await;
+                            Ok(())
+                        }
+                        Err(e) => Err(e),
+                    }
+                }
+                None => match tun
+                    .open_with(args[2].as_str(), args[3].as_str(), capture_path)
+                    .await
+                {
+                    Ok((t, warnings)) => {
+                        print_warnings(warnings);
+                        t.await;
+                        Ok(())
+                    }
+                    Err(e) => Err(e),
+                },
+            };
+            if let Err(e) = result {
+                eprintln!("Could not open interfaces: {}", e);
+            }
         }
         // A packet sniffer.
         "sniff" => {
-            if args.len() < 3 {
-                eprintln!("Usage: <filename> sniff <interface> {{<bpf-filter>}}")
+            if args.len() < 3 || ((args[2] == "--read" || args[2] == "--ring") && args.len() < 4) {
+                eprintln!("Usage: <filename> sniff <interface> {{<bpf-filter>}}");
+                eprintln!(
+                    "       <filename> sniff --read <file.pcap|file.pcapng> {{<bpf-filter>}}"
+                );
+                eprintln!("       <filename> sniff --ring <interface> {{<bpf-filter>}} {{--block-size <bytes>}} {{--blocks <count>}} {{--timeout <ms>}} {{--no-promisc}}");
+                return;
             }
 
-            let mut handle = match control_interfaces::pcap::CaptureHandle::open_live(
-                args[2].as_str(),
-                control_interfaces::pcap::CaptureMode::Promisc,
-                100,
-                65535,
-            ) {
-                Ok(t) => t,
-                Err(e) => {
-                    eprintln!("Could not open interface: {}", e);
-                    return;
+            // pcapng files are read natively, so that every packet keeps its interface and comment.
+            if args[2] == "--read" && args[3].ends_with(".pcapng") {
+                let filter = args.get(4).cloned();
+                let mut reader = match control_interfaces::pcapng::Reader::open(args[3].as_str()) {
+                    Ok(r) => r,
+                    Err(e) => {
+                        eprintln!("Could not open file: {}", e);
+                        return;
+                    }
+                };
+                // Interfaces may have different link types, so the filter is compiled separately for each of them.
+                let mut filters: Vec<Option<control_interfaces::pcap::Filter>> = Vec::new();
+                let packets = std::iter::from_fn(move || loop {
+                    let packet = match reader.next_packet() {
+                        Ok(Some(p)) => p,
+                        Ok(None) => return None,
+                        Err(e) => {
+                            eprintln!("Could not read packet: {}", e);
+                            return None;
+                        }
+                    };
+                    if let Some(ref code) = filter {
+                        let id = packet.interface_id as usize;
+                        if filters.len() <= id {
+                            filters.resize_with(id + 1, || None);
+                        }
+                        if filters[id].is_none() {
+                            let iface = &reader.interfaces()[id];
+                            match control_interfaces::pcap::Filter::compile(
+                                iface.link_type as i32,
+                                // A snaplen of 0 indicates that packets are not truncated.
+                                match iface.snaplen {
+                                    0 => 262144,
+                                    n => n as i32,
+                                },
+                                code.as_str(),
+                                None,
+                            ) {
+                                Ok(f) => filters[id] = Some(f),
+                                Err(e) => {
+                                    eprintln!("{}", e);
+                                    return None;
+                                }
+                            }
+                        }
+                        if let Some(ref f) = filters[id] {
+                            if !f.matches_buffer(&packet.data, packet.original_len) {
+                                continue;
+                            }
+                        }
+                    }
+                    if let Ok(data) = reader.parse(&packet) {
+                        return Some(format!(
+                            "[{}] {}{}",
+                            match reader.interfaces()[packet.interface_id as usize].name {
+                                Some(ref n) => n.clone(),
+                                None => packet.interface_id.to_string(),
+                            },
+                            control_interfaces::pcap::PrintableData {
+                                style: control_interfaces::pcap::PrintStyle::Normal,
+                                data: &data,
+                            },
+                            match packet.comment {
+                                Some(ref c) => format!(" # {}", c),
+                                None => String::new(),
+                            }
+                        ));
+                    }
+                });
+                agents::dumper::Agent::new(tokio_stream::iter(packets))
+                    .run()
+                    .await;
+                return;
+            }
+
+            // Live devices can also be captured from a memory-mapped ring, without going through libpcap.
+            if args[2] == "--ring" {
+                let mut filter: Option<&String> = None;
+                let mut builder =
+                    control_interfaces::af_packet::RingHandle::builder(args[3].as_str()).raw(true);
+                let mut rest = args[4..].iter();
+                while let Some(arg) = rest.next() {
+                    match arg.as_str() {
+                        "--block-size" => match rest.next().map(|n| n.parse::<u32>()) {
+                            Some(Ok(n)) => builder = builder.block_size(n),
+                            _ => {
+                                eprintln!("--block-size expects a number of bytes!");
+                                return;
+                            }
+                        },
+                        "--blocks" => match rest.next().map(|n| n.parse::<u32>()) {
+                            Some(Ok(n)) => builder = builder.block_count(n),
+                            _ => {
+                                eprintln!("--blocks expects a number of blocks!");
+                                return;
+                            }
+                        },
+                        "--timeout" => match rest.next().map(|n| n.parse::<u32>()) {
+                            Some(Ok(n)) => builder = builder.timeout_ms(n),
+                            _ => {
+                                eprintln!("--timeout expects a number of milliseconds!");
+                                return;
+                            }
+                        },
+                        "--no-promisc" => {
+                            builder =
+                                builder.mode(control_interfaces::pcap::CaptureMode::NonPromisc)
+                        }
+                        _ if filter.is_none() => filter = Some(arg),
+                        x => {
+                            eprintln!("Unexpected argument {}", x);
+                            return;
+                        }
+                    }
                 }
-            };
 
-            if args.len() >= 4 {
-                match handle.with_filter(args[3].as_str(), None) {
-                    Ok(_) => {}
-                    Err(control_interfaces::pcap::FilterErr::CouldNotApply) => {
-                        eprintln!("Could not apply filter!");
+                let mut handle = match builder.open() {
+                    Ok(t) => t,
+                    Err(e) => {
+                        eprintln!("Could not open ring: {}", e);
                         return;
                     }
-                    Err(control_interfaces::pcap::FilterErr::InvalidFilter) => {
-                        eprintln!("Incorrect filter syntax!");
+                };
+                if let Some(filter) = filter {
+                    if let Err(e) = handle.set_filter(filter.as_str()) {
+                        eprintln!("{}", e);
                         return;
                     }
                 }
+                sniff_on(handle, Some(args[3].as_str())).await;
+                return;
             }
 
-            let dumper = agents::dumper::Agent::new(tokio_stream::StreamExt::filter_map(
-                handle,
-                |x| match x {
-                    Err(_) => None,
-                    Ok(p) => Some(control_interfaces::pcap::PrintableDataOwned {
-                        style: control_interfaces::pcap::PrintStyle::Normal,
-                        data: p.1,
-                    }),
-                },
-            ))
-            .run();
+            #[cfg(feature = "libpcap")]
+            sniff_with_libpcap(&args).await;
+            #[cfg(not(feature = "libpcap"))]
+            eprintln!("Only rings (--ring) and pcapng files can be sniffed without libpcap!");
+        }
+        // Lists the devices that can be captured on.
+        #[cfg(feature = "libpcap")]
+        "interfaces" => match control_interfaces::devices::find_all() {
+            Ok(devices) => {
+                for d in devices.iter() {
+                    println!("{}", d);
+                }
+            }
+            Err(e) => eprintln!("{}", e),
+        },
+        // Replays a savefile into an interface.
+        #[cfg(feature = "libpcap")]
+        "replay" => {
+            if args.len() < 4 {
+                eprintln!(
+                    "Usage: <filename> replay <interface> <input.pcap> {{--speed <multiplier>}} {{--top-speed}} {{--loop {{<count>}}}} {{--mac-src <mac>}} {{--mac-dst <mac>}} {{--ip-src <ip>}} {{--ip-dst <ip>}}"
+                );
+                return;
+            }
 
-            dumper.await;
+            let mut timing = agents::replay::Timing::Original { speed: 1.0 };
+            let mut loops = Some(1);
+            let mut rewrite = agents::replay::Rewrite::default();
+            let mut rest = args[4..].iter().peekable();
+            while let Some(arg) = rest.next() {
+                match arg.as_str() {
+                    "--speed" => match rest.next().map(|n| n.parse::<f64>()) {
+                        Some(Ok(n)) if n > 0.0 && n.is_finite() => {
+                            timing = agents::replay::Timing::Original { speed: n }
+                        }
+                        _ => {
+                            eprintln!("--speed expects a positive multiplier!");
+                            return;
+                        }
+                    },
+                    "--top-speed" => timing = agents::replay::Timing::TopSpeed,
+                    // Without a count, the file is replayed until the agent is stopped.
+                    "--loop" => match rest.peek().map(|n| n.parse::<u64>()) {
+                        Some(Ok(n)) => {
+                            loops = Some(n);
+                            rest.next();
+                        }
+                        _ => loops = None,
+                    },
+                    "--mac-src" | "--mac-dst" => {
+                        match rest
+                            .next()
+                            .and_then(|m| protocols::linklayer::ethernet::parse_addr(m))
+                        {
+                            Some(m) if arg == "--mac-src" => rewrite.mac_src = Some(m),
+                            Some(m) => rewrite.mac_dst = Some(m),
+                            None => {
+                                eprintln!("{} expects a MAC address!", arg);
+                                return;
+                            }
+                        }
+                    }
+                    "--ip-src" | "--ip-dst" => {
+                        match rest.next().map(|i| i.parse::<std::net::Ipv4Addr>()) {
+                            Some(Ok(i)) if arg == "--ip-src" => rewrite.ip_src = Some(i.octets()),
+                            Some(Ok(i)) => rewrite.ip_dst = Some(i.octets()),
+                            _ => {
+                                eprintln!("{} expects an IPv4 address!", arg);
+                                return;
+                            }
+                        }
+                    }
+                    x => {
+                        eprintln!("Unexpected argument {}", x);
+                        return;
+                    }
+                }
+            }
+
+            let mut handle =
+                match control_interfaces::pcap::CaptureHandle::builder(args[2].as_str())
+                    .raw(true)
+                    .activate()
+                {
+                    Ok(t) => t,
+                    Err(e) => {
+                        eprintln!("{}", e);
+                        return;
+                    }
+                };
+            print_warnings(handle.take_warning());
+
+            agents::replay::Agent::new(handle, args[3].as_str())
+                .with_timing(timing)
+                .with_loops(loops)
+                .with_rewrite(rewrite)
+                .run()
+                .await;
+        }
+        // A packet recorder.
+        #[cfg(feature = "libpcap")]
+        "record" => {
+            if args.len() < 4 {
+                eprintln!(
+                    "Usage: <filename> record <interface> <output.pcap> {{<bpf-filter>}} {{--max-size <bytes>}} {{--max-seconds <seconds>}} {{--buffer-size <bytes>}} {{--immediate}} {{--nano}} {{--batch-size <packets>}} {{--tstamp-type <host|adapter|adapter-unsynced>}}"
+                );
+                return;
+            }
+
+            let mut filter: Option<&String> = None;
+            let mut rotation = agents::record::Rotation::default();
+            // Packets are only written to the savefile, so there is no need to parse them.
+            let mut builder =
+                control_interfaces::pcap::CaptureHandle::builder(args[2].as_str()).raw(true);
+            let mut rest = args[4..].iter();
+            while let Some(arg) = rest.next() {
+                match arg.as_str() {
+                    "--max-size" => match rest.next().map(|n| n.parse::<u64>()) {
+                        Some(Ok(n)) => rotation.max_bytes = Some(n),
+                        _ => {
+                            eprintln!("--max-size expects a number of bytes!");
+                            return;
+                        }
+                    },
+                    "--max-seconds" => match rest.next().map(|n| n.parse::<u64>()) {
+                        Some(Ok(n)) => {
+                            rotation.max_duration = Some(std::time::Duration::from_secs(n))
+                        }
+                        _ => {
+                            eprintln!("--max-seconds expects a number of seconds!");
+                            return;
+                        }
+                    },
+                    "--buffer-size" => match rest.next().map(|n| n.parse::<i32>()) {
+                        Some(Ok(n)) => builder = builder.buffer_size(n),
+                        _ => {
+                            eprintln!("--buffer-size expects a number of bytes!");
+                            return;
+                        }
+                    },
+                    "--immediate" => builder = builder.immediate_mode(true),
+                    "--batch-size" => match rest.next().map(|n| n.parse::<i32>()) {
+                        Some(Ok(n)) => builder = builder.batch_size(n),
+                        _ => {
+                            eprintln!("--batch-size expects a number of packets!");
+                            return;
+                        }
+                    },
+                    "--nano" => {
+                        builder = builder
+                            .tstamp_precision(control_interfaces::pcap::TimestampPrecision::Nano)
+                    }
+                    "--tstamp-type" => match rest.next().map(|t| t.as_str()) {
+                        Some("host") => {
+                            builder =
+                                builder.tstamp_type(control_interfaces::pcap::TimestampType::Host)
+                        }
+                        Some("adapter") => {
+                            builder = builder
+                                .tstamp_type(control_interfaces::pcap::TimestampType::Adapter)
+                        }
+                        Some("adapter-unsynced") => {
+                            builder = builder.tstamp_type(
+                                control_interfaces::pcap::TimestampType::AdapterUnsynced,
+                            )
+                        }
+                        _ => {
+                            eprintln!("--tstamp-type expects host, adapter or adapter-unsynced!");
+                            return;
+                        }
+                    },
+                    _ if filter.is_none() => filter = Some(arg),
+                    x => {
+                        eprintln!("Unexpected argument {}", x);
+                        return;
+                    }
+                }
+            }
+
+            let mut handle = match builder.activate() {
+                Ok(t) => t,
+                Err(e) => {
+                    eprintln!("{}", e);
+                    return;
+                }
+            };
+            print_warnings(handle.take_warning());
+
+            if let Some(filter) = filter {
+                if let Err(e) = handle.with_filter(filter.as_str(), None) {
+                    eprintln!("{}", e);
+                    return;
+                }
+            }
+
+            let recorder = match agents::record::Agent::new(handle, args[3].as_str(), rotation) {
+                Ok(r) => r,
+                Err(e) => {
+                    eprintln!("{}", e);
+                    return;
+                }
+            };
+
+            recorder.run().await;
         }
         // These are the only agents currently supported.
         x => {
@@ -128,3 +483,157 @@ async fn handle_args(args: Vec<String>) {
         }
     }
 }
+
+/// Sets up an agent that intercepts the traffic between two hosts, for authorized tests.
+#[cfg(feature = "libpcap")]
+async fn arp_mitm(
+    args: &[String],
+) -> Option<agents::arp_mitm::Agent<control_interfaces::pcap::CaptureHandle>> {
+    if args.len() < 5 {
+        eprintln!("Usage: <filename> arp-mitm <interface> <target-ip> <target-ip>");
+        eprintln!("       IPv4 forwarding should be disabled while the agent runs (sysctl net.ipv4.ip_forward=0).");
+        return None;
+    }
+
+    let targets = match (
+        args[3].parse::<std::net::Ipv4Addr>(),
+        args[4].parse::<std::net::Ipv4Addr>(),
+    ) {
+        (Ok(a), Ok(b)) => (a.octets(), b.octets()),
+        _ => {
+            eprintln!("Targets must be IPv4 addresses!");
+            return None;
+        }
+    };
+
+    // Forged replies carry the addresses of the interface itself.
+    let device = match control_interfaces::devices::find(args[2].as_str()) {
+        Ok(Some(d)) => d,
+        Ok(None) => {
+            eprintln!("No such interface {}", args[2]);
+            return None;
+        }
+        Err(e) => {
+            eprintln!("{}", e);
+            return None;
+        }
+    };
+    let me = match device.host() {
+        Some(h) => h,
+        None => {
+            eprintln!("{} needs both a MAC address and an IPv4 address", args[2]);
+            return None;
+        }
+    };
+
+    let mut handle = match control_interfaces::pcap::CaptureHandle::builder(args[2].as_str())
+        .raw(true)
+        .immediate_mode(true)
+        .activate()
+    {
+        Ok(t) => t,
+        Err(e) => {
+            eprintln!("{}", e);
+            return None;
+        }
+    };
+    print_warnings(handle.take_warning());
+
+    match agents::arp_mitm::Agent::new(handle, me, targets).await {
+        Ok(a) => Some(a),
+        Err(e) => {
+            eprintln!("{}", e);
+            None
+        }
+    }
+}
+
+/// Sniffs a live device or a savefile through libpcap.
+#[cfg(feature = "libpcap")]
+async fn sniff_with_libpcap(args: &[String]) {
+    // Recorded traffic is read from a file instead of a live interface.
+    // Frames are parsed after they are captured, so that the ones that can not be parsed are still shown.
+    let (opened, filter) = match args[2].as_str() {
+        "--read" => (
+            control_interfaces::pcap::CaptureHandle::open_offline(args[3].as_str()).map(|mut h| {
+                h.set_raw(true);
+                h
+            }),
+            args.get(4),
+        ),
+        dev => (
+            control_interfaces::pcap::CaptureHandle::builder(dev)
+                .raw(true)
+                .activate(),
+            args.get(3),
+        ),
+    };
+
+    let mut handle = match opened {
+        Ok(t) => t,
+        Err(e) => {
+            eprintln!("{}", e);
+            return;
+        }
+    };
+    print_warnings(handle.take_warning());
+
+    if let Some(filter) = filter {
+        if let Err(e) = handle.with_filter(filter.as_str(), None) {
+            eprintln!("{}", e);
+            return;
+        }
+    }
+
+    // Live captures report their statistics, which savefiles do not have.
+    sniff_on(
+        handle,
+        match args[2].as_str() {
+            "--read" => None,
+            dev => Some(dev),
+        },
+    )
+    .await;
+}
+
+/// Prints the packets that are captured on an interface. Frames are parsed after they are captured, so that the ones that can not be parsed are still shown.
+/// If a label is given, the statistics of the interface are reported under it.
+async fn sniff_on<I: control_interfaces::PacketInterface>(handle: I, report_label: Option<&str>) {
+    let (cloud, packets) =
+        control_interfaces::pcap_cloud::Cloud::new(handle, tokio_stream::empty());
+    let cloud = match report_label {
+        None => cloud,
+        Some(label) => {
+            cloud.with_stats_report(label, control_interfaces::pcap_cloud::STATS_REPORT_PERIOD)
+        }
+    };
+
+    let dumper =
+        agents::dumper::Agent::new(tokio_stream::StreamExt::filter_map(packets, |x| match x {
+            Err(e) => {
+                eprintln!("{}", e);
+                None
+            }
+            Ok(p) => Some(control_interfaces::pcap::PrintableDataOwned {
+                style: control_interfaces::pcap::PrintStyle::Normal,
+                data: match p.1 {
+                    control_interfaces::pcap::Data::Raw(r) => match r.parse() {
+                        Ok(d) => d,
+                        Err(_) => control_interfaces::pcap::Data::Raw(r),
+                    },
+                    d => d,
+                },
+            }),
+        }))
+        .run();
+
+    tokio::join!(cloud.run(), dumper);
+}
+
+/// Prints the warnings that libpcap reported while activating handles. They do not prevent the handles from capturing.
+#[cfg(feature = "libpcap")]
+fn print_warnings<W: IntoIterator<Item = control_interfaces::pcap::Error>>(warnings: W) {
+    for w in warnings {
+        eprintln!("Warning: {}", w);
+    }
+}
@@ -15,6 +15,7 @@ pub const PCAP_ERRBUF_SIZE: u32 = 256;
 pub const PCAP_IF_LOOPBACK: u32 = 1;
 pub const PCAP_IF_UP: u32 = 2;
 pub const PCAP_IF_RUNNING: u32 = 4;
+pub const PCAP_IF_WIRELESS: u32 = 8;
 pub const PCAP_ERROR: i32 = -1;
 pub const PCAP_ERROR_BREAK: i32 = -2;
 pub const PCAP_ERROR_NOT_ACTIVATED: i32 = -3;
@@ -38,6 +39,16 @@ pub const PCAP_TSTAMP_ADAPTER: u32 = 3;
 pub const PCAP_TSTAMP_ADAPTER_UNSYNCED: u32 = 4;
 pub const PCAP_TSTAMP_PRECISION_MICRO: u32 = 0;
 pub const PCAP_TSTAMP_PRECISION_NANO: u32 = 1;
+pub const DLT_NULL: i32 = 0;
+pub const DLT_EN10MB: i32 = 1;
+pub const DLT_PPP: i32 = 9;
+pub const DLT_RAW: i32 = 12;
+pub const DLT_LOOP: i32 = 108;
+pub const DLT_LINUX_SLL: i32 = 113;
+pub const DLT_IPV4: i32 = 228;
+pub const DLT_IPV6: i32 = 229;
+pub const DLT_LINUX_SLL2: i32 = 276;
+pub const LINKTYPE_RAW: i32 = 101;
 pub type u_char = libc::c_uchar;
 pub type u_short = libc::c_ushort;
 pub type u_int = libc::c_uint;
@@ -132,9 +143,11 @@ pub struct pcap_addr {
 }
 pub type pcap_handler =
     unsafe extern "C" fn(arg1: *mut u_char, arg2: *const pcap_pkthdr, arg3: *const u_char);
+#[cfg(feature = "libpcap")]
 extern "C" {
     pub fn pcap_lookupdev(arg1: *mut libc::c_char) -> *mut libc::c_char;
 }
+#[cfg(feature = "libpcap")]
 extern "C" {
     pub fn pcap_lookupnet(
         arg1: *const libc::c_char,
@@ -143,61 +156,80 @@ extern "C" {
         arg4: *mut libc::c_char,
     ) -> libc::c_int;
 }
+#[cfg(feature = "libpcap")]
 extern "C" {
     pub fn pcap_create(arg1: *const libc::c_char, arg2: *mut libc::c_char) -> *mut pcap_t;
 }
+#[cfg(feature = "libpcap")]
 extern "C" {
     pub fn pcap_set_snaplen(arg1: *mut pcap_t, arg2: libc::c_int) -> libc::c_int;
 }
+#[cfg(feature = "libpcap")]
 extern "C" {
     pub fn pcap_set_promisc(arg1: *mut pcap_t, arg2: libc::c_int) -> libc::c_int;
 }
+#[cfg(feature = "libpcap")]
 extern "C" {
     pub fn pcap_can_set_rfmon(arg1: *mut pcap_t) -> libc::c_int;
 }
+#[cfg(feature = "libpcap")]
 extern "C" {
     pub fn pcap_set_rfmon(arg1: *mut pcap_t, arg2: libc::c_int) -> libc::c_int;
 }
+#[cfg(feature = "libpcap")]
 extern "C" {
     pub fn pcap_set_timeout(arg1: *mut pcap_t, arg2: libc::c_int) -> libc::c_int;
 }
+#[cfg(feature = "libpcap")]
 extern "C" {
     pub fn pcap_set_tstamp_type(arg1: *mut pcap_t, arg2: libc::c_int) -> libc::c_int;
 }
+#[cfg(feature = "libpcap")]
 extern "C" {
     pub fn pcap_set_immediate_mode(arg1: *mut pcap_t, arg2: libc::c_int) -> libc::c_int;
 }
+#[cfg(feature = "libpcap")]
 extern "C" {
     pub fn pcap_set_buffer_size(arg1: *mut pcap_t, arg2: libc::c_int) -> libc::c_int;
 }
+#[cfg(feature = "libpcap")]
 extern "C" {
     pub fn pcap_set_tstamp_precision(arg1: *mut pcap_t, arg2: libc::c_int) -> libc::c_int;
 }
+#[cfg(feature = "libpcap")]
 extern "C" {
     pub fn pcap_get_tstamp_precision(arg1: *mut pcap_t) -> libc::c_int;
 }
+#[cfg(feature = "libpcap")]
 extern "C" {
     pub fn pcap_activate(arg1: *mut pcap_t) -> libc::c_int;
 }
+#[cfg(feature = "libpcap")]
 extern "C" {
     pub fn pcap_apple_set_exthdr(p: *mut pcap_t, arg1: libc::c_int) -> libc::c_int;
 }
+#[cfg(feature = "libpcap")]
 extern "C" {
     pub fn pcap_list_tstamp_types(arg1: *mut pcap_t, arg2: *mut *mut libc::c_int) -> libc::c_int;
 }
+#[cfg(feature = "libpcap")]
 extern "C" {
     pub fn pcap_free_tstamp_types(arg1: *mut libc::c_int);
 }
+#[cfg(feature = "libpcap")]
 extern "C" {
     pub fn pcap_tstamp_type_name_to_val(arg1: *const libc::c_char) -> libc::c_int;
 }
+#[cfg(feature = "libpcap")]
 extern "C" {
     pub fn pcap_tstamp_type_val_to_name(arg1: libc::c_int) -> *const libc::c_char;
 }
+#[cfg(feature = "libpcap")]
 extern "C" {
     pub fn pcap_tstamp_type_val_to_description(arg1: libc::c_int) -> *const libc::c_char;
 }
 /*Changed arg5 type to const pointer instead of mut*/
+#[cfg(feature = "libpcap")]
 extern "C" {
     pub fn pcap_open_live(
         arg1: *const libc::c_char,
@@ -207,9 +239,11 @@ extern "C" {
         arg5: *const libc::c_char,
     ) -> *mut pcap_t;
 }
+#[cfg(feature = "libpcap")]
 extern "C" {
     pub fn pcap_open_dead(arg1: libc::c_int, arg2: libc::c_int) -> *mut pcap_t;
 }
+#[cfg(feature = "libpcap")]
 extern "C" {
     pub fn pcap_open_dead_with_tstamp_precision(
         arg1: libc::c_int,
@@ -217,6 +251,7 @@ extern "C" {
         arg3: u_int,
     ) -> *mut pcap_t;
 }
+#[cfg(feature = "libpcap")]
 extern "C" {
     pub fn pcap_open_offline_with_tstamp_precision(
         arg1: *const libc::c_char,
@@ -224,9 +259,11 @@ extern "C" {
         arg3: *mut libc::c_char,
     ) -> *mut pcap_t;
 }
+#[cfg(feature = "libpcap")]
 extern "C" {
     pub fn pcap_open_offline(arg1: *const libc::c_char, arg2: *mut libc::c_char) -> *mut pcap_t;
 }
+#[cfg(feature = "libpcap")]
 extern "C" {
     pub fn pcap_fopen_offline_with_tstamp_precision(
         arg1: *mut FILE,
@@ -234,13 +271,16 @@ extern "C" {
         arg3: *mut libc::c_char,
     ) -> *mut pcap_t;
 }
+#[cfg(feature = "libpcap")]
 extern "C" {
     pub fn pcap_fopen_offline(arg1: *mut FILE, arg2: *mut libc::c_char) -> *mut pcap_t;
 }
 /*Chnged arg1 to be const and not mut*/
+#[cfg(feature = "libpcap")]
 extern "C" {
     pub fn pcap_close(arg1: *const pcap_t);
 }
+#[cfg(feature = "libpcap")]
 extern "C" {
     pub fn pcap_loop(
         arg1: *mut pcap_t,
@@ -249,6 +289,7 @@ extern "C" {
         arg4: *mut u_char,
     ) -> libc::c_int;
 }
+#[cfg(feature = "libpcap")]
 extern "C" {
     pub fn pcap_dispatch(
         arg1: *mut pcap_t,
@@ -258,9 +299,11 @@ extern "C" {
     ) -> libc::c_int;
 }
 /*Changed arg1 to not be mutable*/
+#[cfg(feature = "libpcap")]
 extern "C" {
     pub fn pcap_next(arg1: *const pcap_t, arg2: *mut pcap_pkthdr) -> *const u_char;
 }
+#[cfg(feature = "libpcap")]
 extern "C" {
     pub fn pcap_next_ex(
         arg1: *mut pcap_t,
@@ -268,21 +311,27 @@ extern "C" {
         arg3: *mut *const u_char,
     ) -> libc::c_int;
 }
+#[cfg(feature = "libpcap")]
 extern "C" {
     pub fn pcap_breakloop(arg1: *mut pcap_t);
 }
+#[cfg(feature = "libpcap")]
 extern "C" {
     pub fn pcap_stats(arg1: *mut pcap_t, arg2: *mut pcap_stat) -> libc::c_int;
 }
+#[cfg(feature = "libpcap")]
 extern "C" {
     pub fn pcap_setfilter(arg1: *mut pcap_t, arg2: *const bpf_program) -> libc::c_int; //changed program to false
 }
+#[cfg(feature = "libpcap")]
 extern "C" {
     pub fn pcap_setdirection(arg1: *mut pcap_t, arg2: pcap_direction_t) -> libc::c_int;
 }
+#[cfg(feature = "libpcap")]
 extern "C" {
     pub fn pcap_getnonblock(arg1: *mut pcap_t, arg2: *mut libc::c_char) -> libc::c_int;
 }
+#[cfg(feature = "libpcap")]
 extern "C" {
     pub fn pcap_setnonblock(
         arg1: *mut pcap_t,
@@ -290,9 +339,11 @@ extern "C" {
         arg3: *mut libc::c_char,
     ) -> libc::c_int;
 }
+#[cfg(feature = "libpcap")]
 extern "C" {
     pub fn pcap_inject(arg1: *mut pcap_t, arg2: *const libc::c_void, arg3: usize) -> libc::c_int;
 }
+#[cfg(feature = "libpcap")]
 extern "C" {
     pub fn pcap_sendpacket(
         arg1: *mut pcap_t,
@@ -300,18 +351,23 @@ extern "C" {
         arg3: libc::c_int,
     ) -> libc::c_int;
 }
+#[cfg(feature = "libpcap")]
 extern "C" {
     pub fn pcap_statustostr(arg1: libc::c_int) -> *const libc::c_char;
 }
+#[cfg(feature = "libpcap")]
 extern "C" {
     pub fn pcap_strerror(arg1: libc::c_int) -> *const libc::c_char;
 }
+#[cfg(feature = "libpcap")]
 extern "C" {
     pub fn pcap_geterr(arg1: *mut pcap_t) -> *mut libc::c_char;
 }
+#[cfg(feature = "libpcap")]
 extern "C" {
     pub fn pcap_perror(arg1: *mut pcap_t, arg2: *const libc::c_char);
 }
+#[cfg(feature = "libpcap")]
 extern "C" {
     pub fn pcap_compile(
         arg1: *mut pcap_t,
@@ -321,6 +377,7 @@ extern "C" {
         arg5: bpf_u_int32,
     ) -> libc::c_int;
 }
+#[cfg(feature = "libpcap")]
 extern "C" {
     pub fn pcap_compile_nopcap(
         arg1: libc::c_int,
@@ -331,9 +388,11 @@ extern "C" {
         arg6: bpf_u_int32,
     ) -> libc::c_int;
 }
+#[cfg(feature = "libpcap")]
 extern "C" {
     pub fn pcap_freecode(arg1: *mut bpf_program);
 }
+#[cfg(feature = "libpcap")]
 extern "C" {
     pub fn pcap_offline_filter(
         arg1: *const bpf_program,
@@ -341,93 +400,121 @@ extern "C" {
         arg3: *const u_char,
     ) -> libc::c_int;
 }
+#[cfg(feature = "libpcap")]
 extern "C" {
     pub fn pcap_datalink(arg1: *const pcap_t) -> libc::c_int; //changed to const
 }
+#[cfg(feature = "libpcap")]
 extern "C" {
     pub fn pcap_datalink_ext(arg1: *mut pcap_t) -> libc::c_int;
 }
+#[cfg(feature = "libpcap")]
 extern "C" {
     pub fn pcap_list_datalinks(arg1: *mut pcap_t, arg2: *mut *mut libc::c_int) -> libc::c_int;
 }
+#[cfg(feature = "libpcap")]
 extern "C" {
     pub fn pcap_set_datalink(arg1: *mut pcap_t, arg2: libc::c_int) -> libc::c_int;
 }
+#[cfg(feature = "libpcap")]
 extern "C" {
     pub fn pcap_free_datalinks(arg1: *mut libc::c_int);
 }
+#[cfg(feature = "libpcap")]
 extern "C" {
     pub fn pcap_datalink_name_to_val(arg1: *const libc::c_char) -> libc::c_int;
 }
+#[cfg(feature = "libpcap")]
 extern "C" {
     pub fn pcap_datalink_val_to_name(arg1: libc::c_int) -> *const libc::c_char;
 }
+#[cfg(feature = "libpcap")]
 extern "C" {
     pub fn pcap_datalink_val_to_description(arg1: libc::c_int) -> *const libc::c_char;
 }
+#[cfg(feature = "libpcap")]
 extern "C" {
     pub fn pcap_snapshot(arg1: *mut pcap_t) -> libc::c_int;
 }
+#[cfg(feature = "libpcap")]
 extern "C" {
     pub fn pcap_is_swapped(arg1: *mut pcap_t) -> libc::c_int;
 }
+#[cfg(feature = "libpcap")]
 extern "C" {
     pub fn pcap_major_version(arg1: *mut pcap_t) -> libc::c_int;
 }
+#[cfg(feature = "libpcap")]
 extern "C" {
     pub fn pcap_minor_version(arg1: *mut pcap_t) -> libc::c_int;
 }
+#[cfg(feature = "libpcap")]
 extern "C" {
     pub fn pcap_file(arg1: *mut pcap_t) -> *mut FILE;
 }
+#[cfg(feature = "libpcap")]
 extern "C" {
     pub fn pcap_fileno(arg1: *mut pcap_t) -> libc::c_int;
 }
+#[cfg(feature = "libpcap")]
 extern "C" {
     pub fn pcap_dump_open(arg1: *mut pcap_t, arg2: *const libc::c_char) -> *mut pcap_dumper_t;
 }
+#[cfg(feature = "libpcap")]
 extern "C" {
     pub fn pcap_dump_fopen(arg1: *mut pcap_t, fp: *mut FILE) -> *mut pcap_dumper_t;
 }
+#[cfg(feature = "libpcap")]
 extern "C" {
     pub fn pcap_dump_open_append(
         arg1: *mut pcap_t,
         arg2: *const libc::c_char,
     ) -> *mut pcap_dumper_t;
 }
+#[cfg(feature = "libpcap")]
 extern "C" {
     pub fn pcap_dump_file(arg1: *mut pcap_dumper_t) -> *mut FILE;
 }
+#[cfg(feature = "libpcap")]
 extern "C" {
     pub fn pcap_dump_ftell(arg1: *mut pcap_dumper_t) -> libc::c_long;
 }
+#[cfg(feature = "libpcap")]
 extern "C" {
     pub fn pcap_dump_flush(arg1: *mut pcap_dumper_t) -> libc::c_int;
 }
+#[cfg(feature = "libpcap")]
 extern "C" {
     pub fn pcap_dump_close(arg1: *mut pcap_dumper_t);
 }
+#[cfg(feature = "libpcap")]
 extern "C" {
     pub fn pcap_dump(arg1: *mut u_char, arg2: *const pcap_pkthdr, arg3: *const u_char);
 }
+#[cfg(feature = "libpcap")]
 extern "C" {
     pub fn pcap_findalldevs(arg1: *mut *mut pcap_if_t, arg2: *mut libc::c_char) -> libc::c_int;
 }
+#[cfg(feature = "libpcap")]
 extern "C" {
     pub fn pcap_freealldevs(arg1: *mut pcap_if_t);
 }
+#[cfg(feature = "libpcap")]
 extern "C" {
     pub fn pcap_lib_version() -> *const libc::c_char;
 }
+#[cfg(feature = "libpcap")]
 extern "C" {
     pub fn pcap_get_selectable_fd(arg1: *mut pcap_t) -> libc::c_int;
 }
+#[cfg(feature = "libpcap")]
 extern "C" {
     pub fn pcap_get_selectable_fd_list(
         arg1: *mut pcap_t,
         arg2: *mut *mut libc::c_int,
     ) -> libc::c_int;
 }
+#[cfg(feature = "libpcap")]
 extern "C" {
     pub fn pcap_free_selectable_fd_list(arg1: *mut libc::c_int);
 }
@@ -1,11 +1,57 @@
 pub const HW_ADDR_LEN: usize = 6;
 
+/// Parses a MAC address written as six colon-separated hexadecimal bytes (e.g. 00:11:22:33:44:55).
+pub fn parse_addr(s: &str) -> Option<[u8; HW_ADDR_LEN]> {
+    let mut addr = [0u8; HW_ADDR_LEN];
+    let mut parts = s.split(':');
+    for b in addr.iter_mut() {
+        *b = u8::from_str_radix(parts.next()?, 16).ok()?;
+    }
+    match parts.next() {
+        Some(_) => None,
+        None => Some(addr),
+    }
+}
+
 use super::super::networklayer;
 
+/// The EtherType of IEEE 802.1Q (customer) tags.
+pub const TPID_8021Q: u16 = 0x8100;
+/// The EtherType of IEEE 802.1ad (service) tags, which are stacked in front of customer tags (QinQ).
+pub const TPID_8021AD: u16 = 0x88A8;
+/// The EtherType that service tags used before 802.1ad was standardized, which some switches still use.
+pub const TPID_QINQ_LEGACY: u16 = 0x9100;
+
+const VLAN_TAG_LEN: usize = 4;
+
+/// An IEEE 802.1Q VLAN tag.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct VlanTag {
+    /// The EtherType that introduces the tag.
+    pub tpid: u16,
+    /// The priority code point (0-7).
+    pub pcp: u8,
+    /// The drop eligible indicator.
+    pub dei: bool,
+    /// The VLAN identifier (0-4095).
+    pub vid: u16,
+}
+
+impl VlanTag {
+    fn is_tpid(ethertype: u16) -> bool {
+        matches!(ethertype, TPID_8021Q | TPID_8021AD | TPID_QINQ_LEGACY)
+    }
+    fn tci(&self) -> u16 {
+        ((self.pcp as u16 & 0x7) << 13) | ((self.dei as u16) << 12) | (self.vid & 0x0FFF)
+    }
+}
+
 #[derive(Debug)]
 pub struct Frame {
     pub dst: [u8; HW_ADDR_LEN],
     pub src: [u8; HW_ADDR_LEN],
+    /// The VLAN tags of the frame, outermost first. Untagged frames have none.
+    pub tags: Vec<VlanTag>,
     pub next: networklayer::Data,
 }
 
@@ -20,7 +66,31 @@ pub struct PrintableData<'a> {
 impl<'a> std::fmt::Display for PrintableData<'a> {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
         match self.style {
-            PrintStyle::Normal => write!(f, "({:02x}:{:02x}:{:02x}:{:02x}:{:02x}:{:02x}->{:02x}:{:02x}:{:02x}:{:02x}:{:02x}:{:02x}) {}", self.data.src[0], self.data.src[1], self.data.src[2], self.data.src[3], self.data.src[4], self.data.src[5], self.data.dst[0], self.data.dst[1], self.data.dst[2], self.data.dst[3], self.data.dst[4], self.data.dst[5], networklayer::PrintableData{style: networklayer::PrintStyle::Normal, data: &self.data.next})
+            PrintStyle::Normal => {
+                write!(f, "({:02x}:{:02x}:{:02x}:{:02x}:{:02x}:{:02x}->{:02x}:{:02x}:{:02x}:{:02x}:{:02x}:{:02x}) ", self.data.src[0], self.data.src[1], self.data.src[2], self.data.src[3], self.data.src[4], self.data.src[5], self.data.dst[0], self.data.dst[1], self.data.dst[2], self.data.dst[3], self.data.dst[4], self.data.dst[5])?;
+                for tag in self.data.tags.iter() {
+                    write!(
+                        f,
+                        "[{} {} PCP {}{}] ",
+                        if tag.tpid == TPID_8021Q {
+                            "VLAN"
+                        } else {
+                            "S-VLAN"
+                        },
+                        tag.vid,
+                        tag.pcp,
+                        if tag.dei { " DEI" } else { "" }
+                    )?;
+                }
+                write!(
+                    f,
+                    "{}",
+                    networklayer::PrintableData {
+                        style: networklayer::PrintStyle::Normal,
+                        data: &self.data.next
+                    }
+                )
+            }
         }
     }
 }
@@ -28,10 +98,27 @@ impl<'a> std::fmt::Display for PrintableData<'a> {
 #[derive(Debug)]
 pub enum Error {
     InvalidLength,
-    UnknownNetworkProtocol,
     NetworkError(networklayer::Error),
 }
 
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Error::InvalidLength => write!(f, "Ethernet: frame is too short"),
+            Error::NetworkError(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::NetworkError(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
 impl Frame {
     pub fn from_raw_slice(slc: &[u8]) -> Result<Frame, Error> {
         if slc.len() < 2 * HW_ADDR_LEN + 3 {
@@ -43,53 +130,195 @@ impl Frame {
             dst[i] = slc[i];
             src[i] = slc[i + HW_ADDR_LEN];
         }
-        match ((slc[2 * HW_ADDR_LEN] as u16) << 8) + (slc[2 * HW_ADDR_LEN + 1] as u16) {
-            //EtherType
-            0x0800 => Ok(Frame {
-                dst,
-                src,
-                next: networklayer::Data::Ip(
-                    match networklayer::ip::Packet::from_buffer(&slc[2 * HW_ADDR_LEN + 2..]) {
-                        Ok(x) => x,
-                        Err(e) => (return Err(Error::NetworkError(networklayer::Error::Ip(e)))),
-                    },
-                ),
-            }),
-            0x0806 => Ok(Frame {
+        //EtherType
+        let mut offset = 2 * HW_ADDR_LEN;
+        let mut ethertype = ((slc[offset] as u16) << 8) + (slc[offset + 1] as u16);
+        let mut tags = Vec::new();
+        while VlanTag::is_tpid(ethertype) {
+            if slc.len() < offset + VLAN_TAG_LEN + 3 {
+                return Err(Error::InvalidLength);
+            }
+            let tci = ((slc[offset + 2] as u16) << 8) + (slc[offset + 3] as u16);
+            tags.push(VlanTag {
+                tpid: ethertype,
+                pcp: (tci >> 13) as u8,
+                dei: tci & 0x1000 != 0,
+                vid: tci & 0x0FFF,
+            });
+            offset += VLAN_TAG_LEN;
+            ethertype = ((slc[offset] as u16) << 8) + (slc[offset + 1] as u16);
+        }
+        match networklayer::Data::from_ethertype(ethertype, &slc[offset + 2..]) {
+            Ok(next) => Ok(Frame {
                 dst,
                 src,
-                next: networklayer::Data::Arp(
-                    match networklayer::arp::Packet::from_buffer(&slc[2 * HW_ADDR_LEN + 2..]) {
-                        Ok(x) => x,
-                        Err(e) => {
-                            return Err(Error::NetworkError(networklayer::Error::Arp(e)));
-                        }
-                    },
-                ),
+                tags,
+                next,
             }),
-            _ => Err(Error::UnknownNetworkProtocol),
+            Err(e) => Err(Error::NetworkError(e)),
         }
     }
     pub fn into_buffer(&self, offset_needed: (usize, usize)) -> Vec<u8> {
+        let header_len = 2 * HW_ADDR_LEN + VLAN_TAG_LEN * self.tags.len() + 2;
         let mut vec: Vec<u8> = self
             .next
-            .into_buffer((offset_needed.0 + 2 * HW_ADDR_LEN + 2, offset_needed.1));
-        if vec.len() < offset_needed.0 + 2 * HW_ADDR_LEN + 2 + offset_needed.1 {
-            vec.resize(offset_needed.0 + 2 * HW_ADDR_LEN + 2 + offset_needed.1, 0);
+            .into_buffer((offset_needed.0 + header_len, offset_needed.1));
+        if vec.len() < offset_needed.0 + header_len + offset_needed.1 {
+            vec.resize(offset_needed.0 + header_len + offset_needed.1, 0);
         }
-        let slc: &mut [u8] = &mut vec[offset_needed.0..offset_needed.0 + 2 * HW_ADDR_LEN + 2];
+        let slc: &mut [u8] = &mut vec[offset_needed.0..offset_needed.0 + header_len];
         slc[..HW_ADDR_LEN].clone_from_slice(&self.dst);
         slc[HW_ADDR_LEN..2 * HW_ADDR_LEN].clone_from_slice(&self.src);
-        match self.next {
-            networklayer::Data::Ip(_) => {
-                slc[2 * HW_ADDR_LEN] = 0x08;
-                slc[2 * HW_ADDR_LEN + 1] = 0x00;
-            }
-            networklayer::Data::Arp(_) => {
-                slc[2 * HW_ADDR_LEN] = 0x08;
-                slc[2 * HW_ADDR_LEN + 1] = 0x06;
-            }
+        for (i, tag) in self.tags.iter().enumerate() {
+            let tag_slc = &mut slc[2 * HW_ADDR_LEN + VLAN_TAG_LEN * i..][..VLAN_TAG_LEN];
+            tag_slc[..2].copy_from_slice(&tag.tpid.to_be_bytes());
+            tag_slc[2..].copy_from_slice(&tag.tci().to_be_bytes());
         }
+        let ethertype = self.next.ethertype();
+        slc[header_len - 2] = (ethertype >> 8) as u8;
+        slc[header_len - 1] = ethertype as u8;
         vec
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DST: [u8; HW_ADDR_LEN] = [0xff; HW_ADDR_LEN];
+    const SRC: [u8; HW_ADDR_LEN] = [2, 0, 0, 0, 0, 1];
+
+    /// A frame with the given tags (as they appear on the wire), which carries an ARP request with the given operation.
+    fn buffer(tags: &[[u8; 4]], op: u8) -> Vec<u8> {
+        let mut buf = Vec::new();
+        buf.extend_from_slice(&DST);
+        buf.extend_from_slice(&SRC);
+        for tag in tags.iter() {
+            buf.extend_from_slice(tag);
+        }
+        match op {
+            3 | 4 => buf.extend_from_slice(&[0x80, 0x35]),
+            _ => buf.extend_from_slice(&[0x08, 0x06]),
+        }
+        buf.extend_from_slice(&[0, 1, 0x08, 0, 6, 4, 0, op]);
+        buf.extend_from_slice(&SRC);
+        buf.extend_from_slice(&[10, 0, 0, 1]);
+        buf.extend_from_slice(&[0; HW_ADDR_LEN]);
+        buf.extend_from_slice(&[10, 0, 0, 2]);
+        buf
+    }
+
+    /// Parses a frame and checks that it is written back as it was.
+    fn round_trip(buf: &[u8]) -> Frame {
+        let frame = Frame::from_raw_slice(buf).unwrap();
+        assert_eq!(frame.into_buffer((0, 0)), buf);
+        frame
+    }
+
+    fn show(frame: &Frame) -> String {
+        format!(
+            "{}",
+            PrintableData {
+                style: PrintStyle::Normal,
+                data: frame
+            }
+        )
+    }
+
+    #[test]
+    fn untagged() {
+        let frame = round_trip(&buffer(&[], 1));
+        assert_eq!((frame.dst, frame.src), (DST, SRC));
+        assert!(frame.tags.is_empty());
+        assert!(matches!(frame.next, networklayer::Data::Arp(_)));
+        assert!(show(&frame).starts_with("(02:00:00:00:00:01->ff:ff:ff:ff:ff:ff) "));
+    }
+
+    #[test]
+    fn rarp_has_its_own_ethertype() {
+        let frame = round_trip(&buffer(&[], 3));
+        match frame.next {
+            networklayer::Data::Arp(ref p) => assert!(p.action.is_rarp()),
+            ref d => panic!("{:?}", d),
+        }
+    }
+
+    #[test]
+    fn vlan_tags() {
+        // Priority 5, drop eligible, VLAN 100.
+        let frame = round_trip(&buffer(&[[0x81, 0x00, 0xB0, 0x64]], 1));
+        assert_eq!(
+            frame.tags,
+            vec![VlanTag {
+                tpid: TPID_8021Q,
+                pcp: 5,
+                dei: true,
+                vid: 100,
+            }]
+        );
+        assert!(show(&frame).contains(") [VLAN 100 PCP 5 DEI] "));
+
+        let frame = round_trip(&buffer(&[[0x81, 0x00, 0x0F, 0xFF]], 1));
+        assert_eq!(
+            (frame.tags[0].pcp, frame.tags[0].dei, frame.tags[0].vid),
+            (0, false, 4095)
+        );
+    }
+
+    #[test]
+    fn qinq() {
+        let frame = round_trip(&buffer(
+            &[[0x88, 0xA8, 0x00, 0x0A], [0x81, 0x00, 0x20, 0x64]],
+            1,
+        ));
+        let tags: Vec<(u16, u8, u16)> = frame.tags.iter().map(|t| (t.tpid, t.pcp, t.vid)).collect();
+        assert_eq!(tags, vec![(TPID_8021AD, 0, 10), (TPID_8021Q, 1, 100)]);
+        assert!(show(&frame).contains(") [S-VLAN 10 PCP 0] [VLAN 100 PCP 1] "));
+
+        // Switches that predate 802.1ad use another EtherType for service tags.
+        let frame = round_trip(&buffer(
+            &[[0x91, 0x00, 0x00, 0x0A], [0x81, 0x00, 0x00, 0x64]],
+            1,
+        ));
+        assert_eq!(frame.tags[0].tpid, TPID_QINQ_LEGACY);
+        assert_eq!(frame.tags.len(), 2);
+    }
+
+    #[test]
+    fn tags_are_written_outermost_first() {
+        let mut frame = Frame::from_raw_slice(&buffer(&[], 1)).unwrap();
+        frame.tags = vec![
+            VlanTag {
+                tpid: TPID_8021AD,
+                pcp: 7,
+                dei: false,
+                vid: 1,
+            },
+            VlanTag {
+                tpid: TPID_8021Q,
+                pcp: 0,
+                dei: false,
+                vid: 2,
+            },
+        ];
+        let buf = frame.into_buffer((1, 1));
+        assert_eq!(
+            &buf[13..23],
+            &[0x88, 0xA8, 0xE0, 0x01, 0x81, 0x00, 0x00, 0x02, 0x08, 0x06]
+        );
+        assert_eq!(buf.len(), 1 + 22 + 28 + 1);
+    }
+
+    #[test]
+    fn truncated_tags() {
+        let buf = buffer(&[[0x81, 0x00, 0x00, 0x64]], 1);
+        assert!(matches!(
+            Frame::from_raw_slice(&buf[..2 * HW_ADDR_LEN + 4 + 2]),
+            Err(Error::InvalidLength)
+        ));
+        assert!(matches!(
+            Frame::from_raw_slice(&buf[..2 * HW_ADDR_LEN + 2]),
+            Err(Error::InvalidLength)
+        ));
+    }
+}
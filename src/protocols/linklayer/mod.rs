@@ -1,15 +1,43 @@
 /// The Ethernet protocol.
 pub mod ethernet;
+/// The BSD loopback encapsulation.
+pub mod null;
+/// The Point-to-Point protocol.
+pub mod ppp;
+/// The Linux "cooked" capture encapsulation.
+pub mod sll;
+/// The second version of the Linux "cooked" capture encapsulation.
+pub mod sll2;
+
+use super::networklayer;
 
 #[derive(Debug)]
 pub enum Data {
     Ethernet(ethernet::Frame),
+    LinuxSll(sll::Frame),
+    LinuxSll2(sll2::Frame),
+    /// An IP packet without any link-layer header (as captured on tun devices).
+    RawIp(networklayer::ip::Packet),
+    Null(null::Frame),
+    Ppp(ppp::Frame),
+    /// A frame of a link type that is not supported, along with the link type.
+    Unknown(i32, Box<[u8]>),
 }
 
 impl Data {
     pub fn into_buffer(&self, offset_needed: (usize, usize)) -> Vec<u8> {
         match self {
             Data::Ethernet(f) => f.into_buffer(offset_needed),
+            Data::LinuxSll(f) => f.into_buffer(offset_needed),
+            Data::LinuxSll2(f) => f.into_buffer(offset_needed),
+            Data::RawIp(p) => p.into_buffer(offset_needed),
+            Data::Null(f) => f.into_buffer(offset_needed),
+            Data::Ppp(f) => f.into_buffer(offset_needed),
+            Data::Unknown(_, data) => {
+                let mut out = vec![0; offset_needed.0 + data.len() + offset_needed.1];
+                out[offset_needed.0..offset_needed.0 + data.len()].copy_from_slice(data);
+                out
+            }
         }
     }
 }
@@ -34,6 +62,53 @@ impl<'a> std::fmt::Display for PrintableData<'a> {
                         data: frame
                     }
                 ),
+                &Data::LinuxSll(ref frame) => write!(
+                    f,
+                    "SLL {}",
+                    sll::PrintableData {
+                        style: sll::PrintStyle::Normal,
+                        data: frame
+                    }
+                ),
+                &Data::LinuxSll2(ref frame) => write!(
+                    f,
+                    "SLL2 {}",
+                    sll2::PrintableData {
+                        style: sll2::PrintStyle::Normal,
+                        data: frame
+                    }
+                ),
+                &Data::RawIp(ref packet) => write!(
+                    f,
+                    "Raw IP{}",
+                    networklayer::ip::PrintableData {
+                        style: networklayer::ip::PrintStyle::Normal,
+                        data: packet
+                    }
+                ),
+                &Data::Null(ref frame) => write!(
+                    f,
+                    "Loopback {}",
+                    null::PrintableData {
+                        style: null::PrintStyle::Normal,
+                        data: frame
+                    }
+                ),
+                &Data::Ppp(ref frame) => write!(
+                    f,
+                    "PPP {}",
+                    ppp::PrintableData {
+                        style: ppp::PrintStyle::Normal,
+                        data: frame
+                    }
+                ),
+                &Data::Unknown(link_type, ref data) => {
+                    write!(f, "Unknown-{} |", link_type)?;
+                    for e in data.iter() {
+                        write!(f, " {:02X}", e)?;
+                    }
+                    Ok(())
+                }
             },
         }
     }
@@ -42,6 +117,37 @@ impl<'a> std::fmt::Display for PrintableData<'a> {
 #[derive(Debug)]
 pub enum Error {
     Ethernet(ethernet::Error),
+    LinuxSll(sll::Error),
+    LinuxSll2(sll2::Error),
+    RawIp(networklayer::ip::Error),
+    Null(null::Error),
+    Ppp(ppp::Error),
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Error::Ethernet(e) => write!(f, "{}", e),
+            Error::LinuxSll(e) => write!(f, "{}", e),
+            Error::LinuxSll2(e) => write!(f, "{}", e),
+            Error::RawIp(e) => write!(f, "{}", e),
+            Error::Null(e) => write!(f, "{}", e),
+            Error::Ppp(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Ethernet(e) => Some(e),
+            Error::LinuxSll(e) => Some(e),
+            Error::LinuxSll2(e) => Some(e),
+            Error::RawIp(e) => Some(e),
+            Error::Null(e) => Some(e),
+            Error::Ppp(e) => Some(e),
+        }
+    }
 }
 
 impl std::convert::From<ethernet::Error> for Error {
@@ -49,3 +155,27 @@ impl std::convert::From<ethernet::Error> for Error {
         Error::Ethernet(thing)
     }
 }
+
+impl std::convert::From<sll::Error> for Error {
+    fn from(thing: sll::Error) -> Error {
+        Error::LinuxSll(thing)
+    }
+}
+
+impl std::convert::From<sll2::Error> for Error {
+    fn from(thing: sll2::Error) -> Error {
+        Error::LinuxSll2(thing)
+    }
+}
+
+impl std::convert::From<null::Error> for Error {
+    fn from(thing: null::Error) -> Error {
+        Error::Null(thing)
+    }
+}
+
+impl std::convert::From<ppp::Error> for Error {
+    fn from(thing: ppp::Error) -> Error {
+        Error::Ppp(thing)
+    }
+}
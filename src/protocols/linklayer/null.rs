@@ -0,0 +1,190 @@
+use super::super::networklayer;
+
+/// The length of the BSD loopback header.
+pub const HEADER_LEN: usize = 4;
+
+/// A frame captured on a BSD loopback device. The header only contains the address family of the packet.
+#[derive(Debug)]
+pub struct Frame {
+    /// The AF_ value of the packet's protocol (on the machine that captured it).
+    pub family: u32,
+    /// Whether the family is stored in network byte order. Captures made with DLT_NULL use the byte order of the capturing machine.
+    pub big_endian: bool,
+    pub next: networklayer::Data,
+}
+
+pub enum PrintStyle {
+    Normal,
+}
+pub struct PrintableData<'a> {
+    pub style: PrintStyle,
+    pub data: &'a Frame,
+}
+
+impl<'a> std::fmt::Display for PrintableData<'a> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self.style {
+            PrintStyle::Normal => write!(
+                f,
+                "(AF-{}) {}",
+                self.data.family,
+                networklayer::PrintableData {
+                    style: networklayer::PrintStyle::Normal,
+                    data: &self.data.next
+                }
+            ),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum Error {
+    InvalidLength,
+    NetworkError(networklayer::Error),
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Error::InvalidLength => write!(f, "Null/Loopback: frame is too short"),
+            Error::NetworkError(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::NetworkError(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl Frame {
+    /// Parses a loopback frame. Frames captured with LINKTYPE_LOOP always use network byte order, while the byte order of DLT_NULL frames is detected.
+    pub fn from_raw_slice(slc: &[u8], network_order: bool) -> Result<Frame, Error> {
+        if slc.len() < HEADER_LEN {
+            return Err(Error::InvalidLength);
+        }
+        let raw = [slc[0], slc[1], slc[2], slc[3]];
+        // Address families are small, so a value that does not fit in 16 bits was written in the other byte order.
+        let big_endian = network_order || u32::from_le_bytes(raw) > 0xFFFF;
+        let family = match big_endian {
+            true => u32::from_be_bytes(raw),
+            false => u32::from_le_bytes(raw),
+        };
+        Ok(Frame {
+            family,
+            big_endian,
+            next: match family {
+                // AF_INET, and the values used for AF_INET6 by the different BSDs.
+                2 | 24 | 28 | 30 => match networklayer::ip::Packet::from_buffer(&slc[HEADER_LEN..])
+                {
+                    Ok(x) => networklayer::Data::Ip(x),
+                    Err(e) => {
+                        return Err(Error::NetworkError(networklayer::Error::Ip(e)));
+                    }
+                },
+                // Address families are small, so they fit in the protocol number of unknown data.
+                f => networklayer::Data::Unknown(f as u16, slc[HEADER_LEN..].into()),
+            },
+        })
+    }
+    pub fn into_buffer(&self, offset_needed: (usize, usize)) -> Vec<u8> {
+        let mut vec: Vec<u8> = self
+            .next
+            .into_buffer((offset_needed.0 + HEADER_LEN, offset_needed.1));
+        vec[offset_needed.0..offset_needed.0 + HEADER_LEN].copy_from_slice(
+            &match self.big_endian {
+                true => self.family.to_be_bytes(),
+                false => self.family.to_le_bytes(),
+            },
+        );
+        vec
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// An IPv4 datagram from 10.0.0.1 to 10.0.0.2, which carries 4 bytes of an unknown protocol.
+    const DATAGRAM: [u8; 24] = [
+        0x45, 0, 0, 24, 0, 1, 0x40, 0, 64, 0xFD, 0xAB, 0xCD, 10, 0, 0, 1, 10, 0, 0, 2, 1, 2, 3, 4,
+    ];
+
+    /// Parses a frame and checks that it is written back as it was.
+    fn round_trip(buf: &[u8], network_order: bool) -> Frame {
+        let frame = Frame::from_raw_slice(buf, network_order).unwrap();
+        assert_eq!(frame.into_buffer((0, 0)), buf);
+        frame
+    }
+
+    fn framed(header: [u8; 4]) -> Vec<u8> {
+        let mut buf = header.to_vec();
+        buf.extend_from_slice(&DATAGRAM);
+        buf
+    }
+
+    #[test]
+    fn little_endian() {
+        let frame = round_trip(&framed([2, 0, 0, 0]), false);
+        assert_eq!((frame.family, frame.big_endian), (2, false));
+        assert!(matches!(frame.next, networklayer::Data::Ip(_)));
+        let shown = format!(
+            "{}",
+            PrintableData {
+                style: PrintStyle::Normal,
+                data: &frame
+            }
+        );
+        assert!(shown.starts_with("(AF-2) "));
+    }
+
+    #[test]
+    fn big_endian() {
+        let frame = round_trip(&framed([0, 0, 0, 2]), false);
+        assert_eq!((frame.family, frame.big_endian), (2, true));
+        assert!(matches!(frame.next, networklayer::Data::Ip(_)));
+    }
+
+    #[test]
+    fn loop_frames_use_network_order() {
+        let frame = round_trip(&framed([0, 0, 0, 2]), true);
+        assert_eq!((frame.family, frame.big_endian), (2, true));
+        // The family would be 0x02000000 in network order, which is not an IP family.
+        let frame = round_trip(&framed([2, 0, 0, 0]), true);
+        assert_eq!((frame.family, frame.big_endian), (0x0200_0000, true));
+        assert!(matches!(frame.next, networklayer::Data::Unknown(_, _)));
+    }
+
+    #[test]
+    fn ipv6_families() {
+        let mut packet = vec![0x60, 0, 0, 0, 0, 0, 59, 64];
+        packet.extend_from_slice(&[0xfe; 16]);
+        packet.extend_from_slice(&[0xff; 16]);
+        for family in [24u32, 28, 30].iter() {
+            let mut buf = family.to_le_bytes().to_vec();
+            buf.extend_from_slice(&packet);
+            let frame = round_trip(&buf, false);
+            assert!(matches!(
+                frame.next,
+                networklayer::Data::Ip(networklayer::ip::Packet::V6(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn other_families_are_kept() {
+        let frame = round_trip(&[7, 0, 0, 0, 1, 2, 3], false);
+        match frame.next {
+            networklayer::Data::Unknown(7, ref d) => assert_eq!(&d[..], &[1, 2, 3]),
+            ref d => panic!("{:?}", d),
+        }
+        assert!(matches!(
+            Frame::from_raw_slice(&[2, 0, 0], false),
+            Err(Error::InvalidLength)
+        ));
+    }
+}
@@ -0,0 +1,193 @@
+use super::super::networklayer;
+
+/// A PPP frame (without HDLC framing).
+#[derive(Debug)]
+pub struct Frame {
+    /// Whether the frame starts with the (constant) address and control fields.
+    pub address_control: bool,
+    /// Whether the protocol field is compressed to a single byte (RFC 1661), which is only possible for protocols below 0x100.
+    pub compressed_protocol: bool,
+    /// The PPP protocol number of the payload.
+    pub protocol: u16,
+    pub next: networklayer::Data,
+}
+
+pub enum PrintStyle {
+    Normal,
+}
+pub struct PrintableData<'a> {
+    pub style: PrintStyle,
+    pub data: &'a Frame,
+}
+
+impl<'a> std::fmt::Display for PrintableData<'a> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self.style {
+            PrintStyle::Normal => write!(
+                f,
+                "{}",
+                networklayer::PrintableData {
+                    style: networklayer::PrintStyle::Normal,
+                    data: &self.data.next
+                }
+            ),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum Error {
+    InvalidLength,
+    NetworkError(networklayer::Error),
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Error::InvalidLength => write!(f, "PPP: frame is too short"),
+            Error::NetworkError(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::NetworkError(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl Frame {
+    pub fn from_raw_slice(slc: &[u8]) -> Result<Frame, Error> {
+        let address_control = slc.len() >= 2 && slc[0] == 0xFF && slc[1] == 0x03;
+        let slc = match address_control {
+            true => &slc[2..],
+            false => slc,
+        };
+        // Protocol numbers always have an even first byte and an odd second byte, so a field that starts with an odd byte has been compressed.
+        let compressed_protocol = !slc.is_empty() && slc[0] & 0x01 != 0;
+        let (protocol, payload) = match compressed_protocol {
+            true => (slc[0] as u16, &slc[1..]),
+            false if slc.len() >= 2 => (((slc[0] as u16) << 8) | (slc[1] as u16), &slc[2..]),
+            false => return Err(Error::InvalidLength),
+        };
+        Ok(Frame {
+            address_control,
+            compressed_protocol,
+            protocol,
+            next: match protocol {
+                // IPv4 and IPv6.
+                0x0021 | 0x0057 => match networklayer::ip::Packet::from_buffer(payload) {
+                    Ok(x) => networklayer::Data::Ip(x),
+                    Err(e) => {
+                        return Err(Error::NetworkError(networklayer::Error::Ip(e)));
+                    }
+                },
+                p => networklayer::Data::Unknown(p, payload.into()),
+            },
+        })
+    }
+    pub fn into_buffer(&self, offset_needed: (usize, usize)) -> Vec<u8> {
+        let protocol_len = match self.compressed_protocol {
+            true => 1,
+            false => 2,
+        };
+        let header_len = match self.address_control {
+            true => 2 + protocol_len,
+            false => protocol_len,
+        };
+        let mut vec: Vec<u8> = self
+            .next
+            .into_buffer((offset_needed.0 + header_len, offset_needed.1));
+        let slc: &mut [u8] = &mut vec[offset_needed.0..offset_needed.0 + header_len];
+        if self.address_control {
+            slc[0] = 0xFF;
+            slc[1] = 0x03;
+        }
+        if !self.compressed_protocol {
+            slc[header_len - 2] = (self.protocol >> 8) as u8;
+        }
+        slc[header_len - 1] = self.protocol as u8;
+        vec
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// An IPv4 datagram from 10.0.0.1 to 10.0.0.2, which carries 4 bytes of an unknown protocol.
+    const DATAGRAM: [u8; 24] = [
+        0x45, 0, 0, 24, 0, 1, 0x40, 0, 64, 0xFD, 0xAB, 0xCD, 10, 0, 0, 1, 10, 0, 0, 2, 1, 2, 3, 4,
+    ];
+
+    /// Parses a frame and checks that it is written back as it was.
+    fn round_trip(buf: &[u8]) -> Frame {
+        let frame = Frame::from_raw_slice(buf).unwrap();
+        assert_eq!(frame.into_buffer((0, 0)), buf);
+        frame
+    }
+
+    fn framed(header: &[u8]) -> Vec<u8> {
+        let mut buf = header.to_vec();
+        buf.extend_from_slice(&DATAGRAM);
+        buf
+    }
+
+    #[test]
+    fn address_and_control() {
+        let frame = round_trip(&framed(&[0xFF, 0x03, 0x00, 0x21]));
+        assert!(frame.address_control);
+        assert!(!frame.compressed_protocol);
+        assert_eq!(frame.protocol, 0x0021);
+        assert!(matches!(frame.next, networklayer::Data::Ip(_)));
+    }
+
+    #[test]
+    fn compressed_address_and_control() {
+        let frame = round_trip(&framed(&[0x00, 0x21]));
+        assert!(!frame.address_control);
+        assert_eq!(frame.protocol, 0x0021);
+        assert!(matches!(frame.next, networklayer::Data::Ip(_)));
+    }
+
+    #[test]
+    fn compressed_protocol() {
+        let frame = round_trip(&framed(&[0x21]));
+        assert!(frame.compressed_protocol);
+        assert_eq!(frame.protocol, 0x0021);
+        assert!(matches!(frame.next, networklayer::Data::Ip(_)));
+
+        let frame = round_trip(&framed(&[0xFF, 0x03, 0x21]));
+        assert!(frame.address_control && frame.compressed_protocol);
+        assert!(matches!(frame.next, networklayer::Data::Ip(_)));
+    }
+
+    #[test]
+    fn other_protocols_are_kept() {
+        // An LCP echo request.
+        let frame = round_trip(&[0xFF, 0x03, 0xC0, 0x21, 9, 1, 0, 8, 0, 0, 0, 0]);
+        match frame.next {
+            networklayer::Data::Unknown(0xC021, ref d) => assert_eq!(d.len(), 8),
+            ref d => panic!("{:?}", d),
+        }
+    }
+
+    #[test]
+    fn truncated_frames() {
+        assert!(matches!(
+            Frame::from_raw_slice(&[0xFF, 0x03]),
+            Err(Error::InvalidLength)
+        ));
+        assert!(matches!(
+            Frame::from_raw_slice(&[0x00]),
+            Err(Error::InvalidLength)
+        ));
+        assert!(matches!(
+            Frame::from_raw_slice(&[0x21, 0x45]),
+            Err(Error::NetworkError(_))
+        ));
+    }
+}
@@ -0,0 +1,195 @@
+use super::super::networklayer;
+
+/// The length of the Linux "cooked" capture header.
+pub const HEADER_LEN: usize = 16;
+
+/// A frame captured with the Linux "cooked" capture encapsulation (for example, on the `any` device).
+#[derive(Debug)]
+pub struct Frame {
+    /// Describes where the packet was going (0 - to us, 1 - broadcast, 2 - multicast, 3 - to someone else, 4 - sent by us).
+    pub packet_type: u16,
+    /// The ARPHRD_ type of the device that the packet was captured on.
+    pub hatype: u16,
+    /// The number of meaningful bytes in `addr`.
+    pub addr_len: u16,
+    /// The link-layer address of the sender.
+    pub addr: [u8; 8],
+    pub next: networklayer::Data,
+}
+
+pub enum PrintStyle {
+    Normal,
+}
+pub struct PrintableData<'a> {
+    pub style: PrintStyle,
+    pub data: &'a Frame,
+}
+
+/// Formats the direction of a packet, which is described in the same manner by SLL and SLL2.
+pub fn fmt_packet_type(f: &mut std::fmt::Formatter, packet_type: u16) -> std::fmt::Result {
+    match packet_type {
+        0 => write!(f, "IN"),
+        1 => write!(f, "BCAST"),
+        2 => write!(f, "MCAST"),
+        3 => write!(f, "OTHER"),
+        4 => write!(f, "OUT"),
+        t => write!(f, "TYPE-{}", t),
+    }
+}
+
+/// Formats a link-layer address of arbitrary length.
+pub fn fmt_addr(f: &mut std::fmt::Formatter, addr: &[u8]) -> std::fmt::Result {
+    for (i, b) in addr.iter().enumerate() {
+        if i != 0 {
+            write!(f, ":")?;
+        }
+        write!(f, "{:02x}", b)?;
+    }
+    Ok(())
+}
+
+impl<'a> std::fmt::Display for PrintableData<'a> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self.style {
+            PrintStyle::Normal => {
+                write!(f, "(")?;
+                fmt_packet_type(f, self.data.packet_type)?;
+                write!(f, ",")?;
+                fmt_addr(
+                    f,
+                    &self.data.addr[..std::cmp::min(self.data.addr_len as usize, 8)],
+                )?;
+                write!(
+                    f,
+                    ") {}",
+                    networklayer::PrintableData {
+                        style: networklayer::PrintStyle::Normal,
+                        data: &self.data.next
+                    }
+                )
+            }
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum Error {
+    InvalidLength,
+    NetworkError(networklayer::Error),
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Error::InvalidLength => write!(f, "Linux cooked: frame is too short"),
+            Error::NetworkError(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::NetworkError(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl Frame {
+    pub fn from_raw_slice(slc: &[u8]) -> Result<Frame, Error> {
+        if slc.len() < HEADER_LEN {
+            return Err(Error::InvalidLength);
+        }
+        let mut addr = [0; 8];
+        addr.copy_from_slice(&slc[6..14]);
+        Ok(Frame {
+            packet_type: ((slc[0] as u16) << 8) | (slc[1] as u16),
+            hatype: ((slc[2] as u16) << 8) | (slc[3] as u16),
+            addr_len: ((slc[4] as u16) << 8) | (slc[5] as u16),
+            addr,
+            next: match networklayer::Data::from_ethertype(
+                ((slc[14] as u16) << 8) | (slc[15] as u16),
+                &slc[HEADER_LEN..],
+            ) {
+                Ok(next) => next,
+                Err(e) => {
+                    return Err(Error::NetworkError(e));
+                }
+            },
+        })
+    }
+    pub fn into_buffer(&self, offset_needed: (usize, usize)) -> Vec<u8> {
+        let mut vec: Vec<u8> = self
+            .next
+            .into_buffer((offset_needed.0 + HEADER_LEN, offset_needed.1));
+        let slc: &mut [u8] = &mut vec[offset_needed.0..offset_needed.0 + HEADER_LEN];
+        let ethertype = self.next.ethertype();
+        slc[0] = (self.packet_type >> 8) as u8;
+        slc[1] = self.packet_type as u8;
+        slc[2] = (self.hatype >> 8) as u8;
+        slc[3] = self.hatype as u8;
+        slc[4] = (self.addr_len >> 8) as u8;
+        slc[5] = self.addr_len as u8;
+        slc[6..14].copy_from_slice(&self.addr);
+        slc[14] = (ethertype >> 8) as u8;
+        slc[15] = ethertype as u8;
+        vec
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// An IPv4 datagram from 10.0.0.1 to 10.0.0.2, which carries 4 bytes of an unknown protocol.
+    const DATAGRAM: [u8; 24] = [
+        0x45, 0, 0, 24, 0, 1, 0x40, 0, 64, 0xFD, 0xAB, 0xCD, 10, 0, 0, 1, 10, 0, 0, 2, 1, 2, 3, 4,
+    ];
+
+    /// A header of an outgoing frame from an Ethernet device, with the given EtherType.
+    fn header(ethertype: u16) -> Vec<u8> {
+        let mut buf = vec![0, 4, 0, 1, 0, 6, 2, 0, 0, 0, 0, 1, 0, 0];
+        buf.extend_from_slice(&ethertype.to_be_bytes());
+        buf
+    }
+
+    /// Parses a frame and checks that it is written back as it was.
+    fn round_trip(buf: &[u8]) -> Frame {
+        let frame = Frame::from_raw_slice(buf).unwrap();
+        assert_eq!(frame.into_buffer((0, 0)), buf);
+        frame
+    }
+
+    #[test]
+    fn fields() {
+        let mut buf = header(0x0800);
+        buf.extend_from_slice(&DATAGRAM);
+        let frame = round_trip(&buf);
+        assert_eq!((frame.packet_type, frame.hatype, frame.addr_len), (4, 1, 6));
+        assert_eq!(frame.addr, [2, 0, 0, 0, 0, 1, 0, 0]);
+        assert!(matches!(frame.next, networklayer::Data::Ip(_)));
+        let shown = format!(
+            "{}",
+            PrintableData {
+                style: PrintStyle::Normal,
+                data: &frame
+            }
+        );
+        assert!(shown.starts_with("(OUT,02:00:00:00:00:01) "));
+    }
+
+    #[test]
+    fn other_ethertypes_are_kept() {
+        let mut buf = header(0x88CC);
+        buf.extend_from_slice(&[1, 2, 3]);
+        match round_trip(&buf).next {
+            networklayer::Data::Unknown(0x88CC, ref d) => assert_eq!(&d[..], &[1, 2, 3]),
+            ref d => panic!("{:?}", d),
+        }
+        assert!(matches!(
+            Frame::from_raw_slice(&buf[..HEADER_LEN - 1]),
+            Err(Error::InvalidLength)
+        ));
+    }
+}
@@ -0,0 +1,184 @@
+use super::super::networklayer;
+
+/// The length of the second version of the Linux "cooked" capture header.
+pub const HEADER_LEN: usize = 20;
+
+/// A frame captured with the second version of the Linux "cooked" capture encapsulation, which also identifies the interface the packet was captured on.
+#[derive(Debug)]
+pub struct Frame {
+    /// The index of the interface that the packet was captured on.
+    pub if_index: u32,
+    /// The ARPHRD_ type of the device that the packet was captured on.
+    pub hatype: u16,
+    /// Describes where the packet was going, in the same manner as in SLL.
+    pub packet_type: u8,
+    /// The number of meaningful bytes in `addr`.
+    pub addr_len: u8,
+    /// The link-layer address of the sender.
+    pub addr: [u8; 8],
+    pub next: networklayer::Data,
+}
+
+pub enum PrintStyle {
+    Normal,
+}
+pub struct PrintableData<'a> {
+    pub style: PrintStyle,
+    pub data: &'a Frame,
+}
+
+impl<'a> std::fmt::Display for PrintableData<'a> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self.style {
+            PrintStyle::Normal => {
+                write!(f, "(IF-{},", self.data.if_index)?;
+                super::sll::fmt_packet_type(f, self.data.packet_type as u16)?;
+                write!(f, ",")?;
+                super::sll::fmt_addr(
+                    f,
+                    &self.data.addr[..std::cmp::min(self.data.addr_len as usize, 8)],
+                )?;
+                write!(
+                    f,
+                    ") {}",
+                    networklayer::PrintableData {
+                        style: networklayer::PrintStyle::Normal,
+                        data: &self.data.next
+                    }
+                )
+            }
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum Error {
+    InvalidLength,
+    NetworkError(networklayer::Error),
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Error::InvalidLength => write!(f, "Linux cooked v2: frame is too short"),
+            Error::NetworkError(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::NetworkError(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl Frame {
+    pub fn from_raw_slice(slc: &[u8]) -> Result<Frame, Error> {
+        if slc.len() < HEADER_LEN {
+            return Err(Error::InvalidLength);
+        }
+        let mut addr = [0; 8];
+        addr.copy_from_slice(&slc[12..20]);
+        Ok(Frame {
+            if_index: ((slc[4] as u32) << 24)
+                | ((slc[5] as u32) << 16)
+                | ((slc[6] as u32) << 8)
+                | (slc[7] as u32),
+            hatype: ((slc[8] as u16) << 8) | (slc[9] as u16),
+            packet_type: slc[10],
+            addr_len: slc[11],
+            addr,
+            next: match networklayer::Data::from_ethertype(
+                ((slc[0] as u16) << 8) | (slc[1] as u16),
+                &slc[HEADER_LEN..],
+            ) {
+                Ok(next) => next,
+                Err(e) => {
+                    return Err(Error::NetworkError(e));
+                }
+            },
+        })
+    }
+    pub fn into_buffer(&self, offset_needed: (usize, usize)) -> Vec<u8> {
+        let mut vec: Vec<u8> = self
+            .next
+            .into_buffer((offset_needed.0 + HEADER_LEN, offset_needed.1));
+        let slc: &mut [u8] = &mut vec[offset_needed.0..offset_needed.0 + HEADER_LEN];
+        let ethertype = self.next.ethertype();
+        slc[0] = (ethertype >> 8) as u8;
+        slc[1] = ethertype as u8;
+        // Reserved.
+        slc[2] = 0;
+        slc[3] = 0;
+        slc[4] = (self.if_index >> 24) as u8;
+        slc[5] = (self.if_index >> 16) as u8;
+        slc[6] = (self.if_index >> 8) as u8;
+        slc[7] = self.if_index as u8;
+        slc[8] = (self.hatype >> 8) as u8;
+        slc[9] = self.hatype as u8;
+        slc[10] = self.packet_type;
+        slc[11] = self.addr_len;
+        slc[12..20].copy_from_slice(&self.addr);
+        vec
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// An IPv4 datagram from 10.0.0.1 to 10.0.0.2, which carries 4 bytes of an unknown protocol.
+    const DATAGRAM: [u8; 24] = [
+        0x45, 0, 0, 24, 0, 1, 0x40, 0, 64, 0xFD, 0xAB, 0xCD, 10, 0, 0, 1, 10, 0, 0, 2, 1, 2, 3, 4,
+    ];
+
+    /// A header of an incoming frame on interface 0x01020304 (an Ethernet device), with the given EtherType.
+    fn header(ethertype: u16) -> Vec<u8> {
+        let mut buf = ethertype.to_be_bytes().to_vec();
+        buf.extend_from_slice(&[0, 0, 1, 2, 3, 4, 0, 1, 0, 6, 2, 0, 0, 0, 0, 1, 0, 0]);
+        buf
+    }
+
+    /// Parses a frame and checks that it is written back as it was.
+    fn round_trip(buf: &[u8]) -> Frame {
+        let frame = Frame::from_raw_slice(buf).unwrap();
+        assert_eq!(frame.into_buffer((0, 0)), buf);
+        frame
+    }
+
+    #[test]
+    fn fields() {
+        let mut buf = header(0x0800);
+        buf.extend_from_slice(&DATAGRAM);
+        let frame = round_trip(&buf);
+        assert_eq!(frame.if_index, 0x01020304);
+        assert_eq!((frame.hatype, frame.packet_type, frame.addr_len), (1, 0, 6));
+        assert_eq!(frame.addr, [2, 0, 0, 0, 0, 1, 0, 0]);
+        assert!(matches!(frame.next, networklayer::Data::Ip(_)));
+        let shown = format!(
+            "{}",
+            PrintableData {
+                style: PrintStyle::Normal,
+                data: &frame
+            }
+        );
+        assert!(shown.starts_with("(IF-16909060,IN,02:00:00:00:00:01) "));
+    }
+
+    #[test]
+    fn other_ethertypes_are_kept() {
+        let mut buf = header(0x88CC);
+        buf.extend_from_slice(&[1, 2, 3]);
+        match round_trip(&buf).next {
+            networklayer::Data::Unknown(0x88CC, ref d) => assert_eq!(&d[..], &[1, 2, 3]),
+            ref d => panic!("{:?}", d),
+        }
+        assert!(matches!(
+            Frame::from_raw_slice(&buf[..HEADER_LEN - 1]),
+            Err(Error::InvalidLength)
+        ));
+    }
+}
@@ -1,74 +1,165 @@
 use std::convert::TryInto;
 
+use super::super::linklayer::sll;
+
 pub enum PrintStyle {
     Normal,
 }
 
+/// A (sender, reciever) pair of addresses of a type that is not otherwise supported, which are kept as they are.
+/// Such pairs are only read from packets, so both of their addresses have the length that the packet gives.
+#[derive(Debug, Clone, PartialEq)]
+pub struct OtherPair {
+    kind: u16,
+    sender: Box<[u8]>,
+    reciever: Box<[u8]>,
+}
+
+impl OtherPair {
+    /// The hardware type or the protocol type of the addresses.
+    pub fn kind(&self) -> u16 {
+        self.kind
+    }
+    pub fn sender(&self) -> &[u8] {
+        &self.sender
+    }
+    pub fn reciever(&self) -> &[u8] {
+        &self.reciever
+    }
+}
+
 /// Contains a (sender, reciever) address pair for different data-link-layer protocols (for example, Ethernet).
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone)]
 pub enum LLAddressPair {
     Ethernet([u8; 6], [u8; 6]), //(Sender, reciever)
+    /// Addresses of any other hardware type (or of an unusual length).
+    Other(OtherPair),
 }
 
 impl LLAddressPair {
-    fn fmt_sender(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+    fn hw_type(&self) -> u16 {
         match self {
-            &LLAddressPair::Ethernet(s, _) => write!(
-                f,
-                "{:02x}:{:02x}:{:02x}:{:02x}:{:02x}:{:02x}",
-                s[0], s[1], s[2], s[3], s[4], s[5]
-            ),
+            LLAddressPair::Ethernet(_, _) => 1,
+            LLAddressPair::Other(p) => p.kind(),
         }
     }
-    fn fmt_reciever(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+    fn addresses(&self) -> (&[u8], &[u8]) {
         match self {
-            &LLAddressPair::Ethernet(_, s) => write!(
-                f,
-                "{:02x}:{:02x}:{:02x}:{:02x}:{:02x}:{:02x}",
-                s[0], s[1], s[2], s[3], s[4], s[5]
-            ),
+            LLAddressPair::Ethernet(s, r) => (s, r),
+            LLAddressPair::Other(p) => (p.sender(), p.reciever()),
         }
     }
+    fn fmt_sender(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        sll::fmt_addr(f, self.addresses().0)
+    }
+    fn fmt_reciever(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        sll::fmt_addr(f, self.addresses().1)
+    }
 }
 
 /// Contains a (Sender, Reciever) pair for network-layer protocols (for example, IPv4).
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone)]
 pub enum NLAddressPair {
     Ipv4([u8; 4], [u8; 4]),
-    Ipv6([u8; 8], [u8; 8]),
+    Ipv6([u8; 16], [u8; 16]),
+    /// Addresses of any other protocol type (or of an unusual length).
+    Other(OtherPair),
 }
 impl NLAddressPair {
-    fn fmt_sender(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+    fn protocol_type(&self) -> u16 {
         match self {
-            &NLAddressPair::Ipv4(s, _) => write!(f, "{}.{}.{}.{}", s[0], s[1], s[2], s[3]),
-            _ => write!(f, "unformatable"),
+            NLAddressPair::Ipv4(_, _) => 0x0800,
+            NLAddressPair::Ipv6(_, _) => 0x86DD,
+            NLAddressPair::Other(p) => p.kind(),
         }
     }
-    fn fmt_reciever(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+    fn addresses(&self) -> (&[u8], &[u8]) {
         match self {
-            &NLAddressPair::Ipv4(_, s) => write!(f, "{}.{}.{}.{}", s[0], s[1], s[2], s[3]),
-            _ => write!(f, "unformatable"),
+            NLAddressPair::Ipv4(s, r) => (s, r),
+            NLAddressPair::Ipv6(s, r) => (s, r),
+            NLAddressPair::Other(p) => (p.sender(), p.reciever()),
         }
     }
+    fn fmt_addr(f: &mut std::fmt::Formatter, addr: &[u8]) -> std::fmt::Result {
+        match addr.len() {
+            4 => write!(f, "{}.{}.{}.{}", addr[0], addr[1], addr[2], addr[3]),
+            16 => {
+                let addr: [u8; 16] = addr.try_into().unwrap();
+                write!(f, "{}", std::net::Ipv6Addr::from(addr))
+            }
+            _ => sll::fmt_addr(f, addr),
+        }
+    }
+    fn fmt_sender(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        NLAddressPair::fmt_addr(f, self.addresses().0)
+    }
+    fn fmt_reciever(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        NLAddressPair::fmt_addr(f, self.addresses().1)
+    }
 }
 
-#[derive(Debug)]
+/// The operation of an ARP packet.
+/// RARP (RFC 903) and Inverse ARP (RFC 2390) share the packet format of ARP.
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum Action {
     Request,
     Response,
+    /// Asks for the protocol address of the target's hardware address.
+    RarpRequest,
+    RarpResponse,
+    /// Asks for the protocol address of the target, whose hardware address is known (e.g. on Frame Relay).
+    InArpRequest,
+    InArpResponse,
+    /// An operation that is not supported. Its code is kept.
+    Other(u16),
+}
+
+impl Action {
+    fn from_code(code: u16) -> Action {
+        match code {
+            1 => Action::Request,
+            2 => Action::Response,
+            3 => Action::RarpRequest,
+            4 => Action::RarpResponse,
+            8 => Action::InArpRequest,
+            9 => Action::InArpResponse,
+            c => Action::Other(c),
+        }
+    }
+    fn code(self) -> u16 {
+        match self {
+            Action::Request => 1,
+            Action::Response => 2,
+            Action::RarpRequest => 3,
+            Action::RarpResponse => 4,
+            Action::InArpRequest => 8,
+            Action::InArpResponse => 9,
+            Action::Other(c) => c,
+        }
+    }
+    /// Whether the packet is carried with the RARP EtherType (0x8035) rather than the ARP one.
+    pub fn is_rarp(self) -> bool {
+        matches!(self, Action::RarpRequest | Action::RarpResponse)
+    }
 }
 
 #[derive(Debug)]
 pub enum Error {
     InvalidLength,
     InvalidLengthFields,
-    UnknownNetworkProtocol,
-    UnknownLinkProtocol,
-    CantParseNetworkAddress,
-    CantParseLinkAddress,
-    InvalidOperation,
 }
 
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Error::InvalidLength => write!(f, "ARP: packet is too short"),
+            Error::InvalidLengthFields => write!(f, "ARP: address lengths do not match the packet"),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
 #[derive(Debug)]
 pub struct Packet {
     pub hw_addr: LLAddressPair,
@@ -84,24 +175,27 @@ impl<'a> std::fmt::Display for PrintableData<'a> {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
         match self.style {
             PrintStyle::Normal => {
-                write!(
-                    f,
-                    "{} (",
-                    match self.data.action {
-                        Action::Request => "REQ",
-                        Action::Response => "REP",
-                    }
-                )?;
+                match self.data.action {
+                    Action::Request | Action::RarpRequest => write!(f, "REQ (")?,
+                    Action::Response | Action::RarpResponse => write!(f, "REP (")?,
+                    Action::InArpRequest => write!(f, "InREQ (")?,
+                    Action::InArpResponse => write!(f, "InREP (")?,
+                    Action::Other(c) => write!(f, "OP{} (", c)?,
+                };
                 self.data.hw_addr.fmt_sender(f)?;
                 write!(f, ",")?;
                 self.data.sw_addr.fmt_sender(f)?;
                 write!(f, "->")?;
+                // Requests leave out the address that they ask for.
                 match self.data.action {
                     Action::Request => write!(f, "?"),
-                    Action::Response => self.data.hw_addr.fmt_reciever(f),
+                    _ => self.data.hw_addr.fmt_reciever(f),
                 }?;
                 write!(f, ",")?;
-                self.data.sw_addr.fmt_reciever(f)?;
+                match self.data.action {
+                    Action::RarpRequest | Action::InArpRequest => write!(f, "?"),
+                    _ => self.data.sw_addr.fmt_reciever(f),
+                }?;
                 write!(f, ")")
             }
         }
@@ -110,124 +204,278 @@ impl<'a> std::fmt::Display for PrintableData<'a> {
 
 impl Packet {
     pub fn from_buffer(raw: &[u8]) -> Result<Packet, Error> {
-        let hw_addr: LLAddressPair;
-        let sw_addr: NLAddressPair;
-        if raw.len() < 6 {
+        if raw.len() < 8 {
             return Err(Error::InvalidLength);
         }
+        let hw_type = ((raw[0] as u16) << 8) + (raw[1] as u16);
+        let protocol_type = ((raw[2] as u16) << 8) + (raw[3] as u16);
         let hw_len = raw[4] as usize;
         let sw_len = raw[5] as usize;
         if raw.len() < 8 + 2 * hw_len + 2 * sw_len {
             return Err(Error::InvalidLengthFields);
         }
 
-        hw_addr = match ((raw[0] as u16) << 8) + (raw[1] as u16) {
-            1 => LLAddressPair::Ethernet(
-                match (&raw[8..8 + hw_len]).try_into() {
-                    Ok(x) => x,
-                    Err(_) => {
-                        return Err(Error::CantParseLinkAddress);
-                    }
-                },
-                match (&raw[8 + hw_len + sw_len..8 + 2 * hw_len + sw_len]).try_into() {
-                    Ok(x) => x,
-                    Err(_) => {
-                        return Err(Error::CantParseLinkAddress);
-                    }
-                },
+        let hw_sender = &raw[8..8 + hw_len];
+        let sw_sender = &raw[8 + hw_len..8 + hw_len + sw_len];
+        let hw_reciever = &raw[8 + hw_len + sw_len..8 + 2 * hw_len + sw_len];
+        let sw_reciever = &raw[8 + 2 * hw_len + sw_len..8 + 2 * hw_len + 2 * sw_len];
+
+        // The lengths are checked along with the types, so that the conversions can not fail.
+        let hw_addr = match (hw_type, hw_len) {
+            (1, 6) => LLAddressPair::Ethernet(
+                hw_sender.try_into().unwrap(),
+                hw_reciever.try_into().unwrap(),
             ),
-            _ => {
-                return Err(Error::UnknownLinkProtocol);
-            }
+            _ => LLAddressPair::Other(OtherPair {
+                kind: hw_type,
+                sender: hw_sender.into(),
+                reciever: hw_reciever.into(),
+            }),
         };
 
-        sw_addr = match ((raw[2] as u16) << 8) + (raw[3] as u16) {
-            0x0800 => NLAddressPair::Ipv4(
-                match (&raw[8 + hw_len..8 + hw_len + sw_len]).try_into() {
-                    Ok(x) => x,
-                    Err(_) => {
-                        return Err(Error::CantParseNetworkAddress);
-                    }
-                },
-                match (&raw[8 + 2 * hw_len + sw_len..8 + 2 * hw_len + 2 * sw_len]).try_into() {
-                    Ok(x) => x,
-                    Err(_) => {
-                        return Err(Error::CantParseNetworkAddress);
-                    }
-                },
+        let sw_addr = match (protocol_type, sw_len) {
+            (0x0800, 4) => NLAddressPair::Ipv4(
+                sw_sender.try_into().unwrap(),
+                sw_reciever.try_into().unwrap(),
             ),
-            0x86DD => NLAddressPair::Ipv6(
-                match (&raw[8 + hw_len..8 + hw_len + sw_len]).try_into() {
-                    Ok(x) => x,
-                    Err(_) => {
-                        return Err(Error::CantParseNetworkAddress);
-                    }
-                },
-                match (&raw[8 + 2 * hw_len + sw_len..8 + 2 * hw_len + 2 * sw_len]).try_into() {
-                    Ok(x) => x,
-                    Err(_) => {
-                        return Err(Error::CantParseNetworkAddress);
-                    }
-                },
+            (0x86DD, 16) => NLAddressPair::Ipv6(
+                sw_sender.try_into().unwrap(),
+                sw_reciever.try_into().unwrap(),
             ),
-            _ => {
-                return Err(Error::UnknownNetworkProtocol);
-            }
+            _ => NLAddressPair::Other(OtherPair {
+                kind: protocol_type,
+                sender: sw_sender.into(),
+                reciever: sw_reciever.into(),
+            }),
         };
-        return Ok(Packet {
+
+        Ok(Packet {
             hw_addr,
             sw_addr,
-            action: match ((raw[6] as u16) << 8) + (raw[7] as u16) {
-                1 => Action::Request,
-                2 => Action::Response,
-                _ => {
-                    return Err(Error::InvalidOperation);
-                }
-            },
-        });
+            action: Action::from_code(((raw[6] as u16) << 8) + (raw[7] as u16)),
+        })
     }
+    /// Writes the packet out. The addresses of each pair have the same length, since pairs of other types can only be parsed.
     pub fn into_buffer(&self, offset_needed: (usize, usize)) -> Vec<u8> {
-        let mut out = vec![0; offset_needed.0 + 28 + offset_needed.1];
-        let slc = &mut out[offset_needed.0..offset_needed.0 + 28];
-        slc[4] = match self.hw_addr {
-            LLAddressPair::Ethernet(_, _) => 6,
-        };
-        slc[5] = match self.sw_addr {
-            NLAddressPair::Ipv4(_, _) => 4,
-            NLAddressPair::Ipv6(_, _) => 8,
-        };
-        let sw_len = slc[5] as usize;
-        let hw_len = slc[4] as usize;
-
-        match self.hw_addr {
-            LLAddressPair::Ethernet(ref sender_addr, ref reciever_addr) => {
-                slc[0] = 0x00;
-                slc[1] = 0x01;
-                slc[8..8 + hw_len].copy_from_slice(sender_addr);
-                slc[8 + hw_len + sw_len..8 + sw_len + 2 * hw_len].copy_from_slice(reciever_addr);
+        let (hw_sender, hw_reciever) = self.hw_addr.addresses();
+        let (sw_sender, sw_reciever) = self.sw_addr.addresses();
+        let hw_len = hw_sender.len();
+        let sw_len = sw_sender.len();
+        let len = 8 + 2 * hw_len + 2 * sw_len;
+
+        let mut out = vec![0; offset_needed.0 + len + offset_needed.1];
+        let slc = &mut out[offset_needed.0..offset_needed.0 + len];
+        slc[0..2].copy_from_slice(&self.hw_addr.hw_type().to_be_bytes());
+        slc[2..4].copy_from_slice(&self.sw_addr.protocol_type().to_be_bytes());
+        slc[4] = hw_len as u8;
+        slc[5] = sw_len as u8;
+        slc[6..8].copy_from_slice(&self.action.code().to_be_bytes());
+        slc[8..8 + hw_len].copy_from_slice(hw_sender);
+        slc[8 + hw_len..8 + hw_len + sw_len].copy_from_slice(sw_sender);
+        slc[8 + hw_len + sw_len..8 + 2 * hw_len + sw_len].copy_from_slice(hw_reciever);
+        slc[8 + 2 * hw_len + sw_len..].copy_from_slice(sw_reciever);
+
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Parses a packet and checks that it is written back as it was.
+    fn round_trip(buf: &[u8]) -> Packet {
+        let packet = Packet::from_buffer(buf).unwrap();
+        assert_eq!(packet.into_buffer((0, 0)), buf);
+        packet
+    }
+
+    fn show(packet: &Packet) -> String {
+        format!(
+            "{}",
+            PrintableData {
+                style: PrintStyle::Normal,
+                data: packet
             }
-        };
+        )
+    }
+
+    /// A packet with the given types, operation and addresses.
+    fn buffer(
+        hw_type: u16,
+        protocol_type: u16,
+        op: u16,
+        hw: (&[u8], &[u8]),
+        sw: (&[u8], &[u8]),
+    ) -> Vec<u8> {
+        let mut buf = Vec::new();
+        buf.extend_from_slice(&hw_type.to_be_bytes());
+        buf.extend_from_slice(&protocol_type.to_be_bytes());
+        buf.push(hw.0.len() as u8);
+        buf.push(sw.0.len() as u8);
+        buf.extend_from_slice(&op.to_be_bytes());
+        buf.extend_from_slice(hw.0);
+        buf.extend_from_slice(sw.0);
+        buf.extend_from_slice(hw.1);
+        buf.extend_from_slice(sw.1);
+        buf
+    }
+
+    const MAC_A: [u8; 6] = [2, 0, 0, 0, 0, 1];
+    const MAC_B: [u8; 6] = [2, 0, 0, 0, 0, 2];
+
+    #[test]
+    fn ethernet_and_ipv4() {
+        let buf = buffer(
+            1,
+            0x0800,
+            1,
+            (&MAC_A, &[0; 6]),
+            (&[10, 0, 0, 1], &[10, 0, 0, 2]),
+        );
+        let request = round_trip(&buf);
+        match (&request.hw_addr, &request.sw_addr, request.action) {
+            (
+                LLAddressPair::Ethernet(MAC_A, [0, 0, 0, 0, 0, 0]),
+                NLAddressPair::Ipv4([10, 0, 0, 1], [10, 0, 0, 2]),
+                Action::Request,
+            ) => {}
+            p => panic!("{:?}", p),
+        }
+        assert!(!request.action.is_rarp());
+        assert_eq!(
+            show(&request),
+            "REQ (02:00:00:00:00:01,10.0.0.1->?,10.0.0.2)"
+        );
+
+        let buf = buffer(
+            1,
+            0x0800,
+            2,
+            (&MAC_B, &MAC_A),
+            (&[10, 0, 0, 2], &[10, 0, 0, 1]),
+        );
+        let reply = round_trip(&buf);
+        assert_eq!(reply.action, Action::Response);
+        assert_eq!(
+            show(&reply),
+            "REP (02:00:00:00:00:02,10.0.0.2->02:00:00:00:00:01,10.0.0.1)"
+        );
+    }
+
+    #[test]
+    fn padding_is_not_part_of_the_packet() {
+        let mut buf = buffer(
+            1,
+            0x0800,
+            1,
+            (&MAC_A, &[0; 6]),
+            (&[10, 0, 0, 1], &[10, 0, 0, 2]),
+        );
+        let len = buf.len();
+        buf.resize(60, 0);
+        let packet = Packet::from_buffer(&buf).unwrap();
+        assert_eq!(packet.into_buffer((2, 3)).len(), 2 + len + 3);
+    }
+
+    #[test]
+    fn rarp() {
+        let buf = buffer(1, 0x0800, 3, (&MAC_A, &MAC_A), (&[0; 4], &[0; 4]));
+        let request = round_trip(&buf);
+        assert_eq!(request.action, Action::RarpRequest);
+        assert!(request.action.is_rarp());
+        assert_eq!(
+            show(&request),
+            "REQ (02:00:00:00:00:01,0.0.0.0->02:00:00:00:00:01,?)"
+        );
+
+        let buf = buffer(
+            1,
+            0x0800,
+            4,
+            (&MAC_B, &MAC_A),
+            (&[10, 0, 0, 2], &[10, 0, 0, 1]),
+        );
+        let reply = round_trip(&buf);
+        assert_eq!(reply.action, Action::RarpResponse);
+        assert!(reply.action.is_rarp());
+    }
 
-        match self.sw_addr {
-            NLAddressPair::Ipv4(ref sender_addr, ref reciever_addr) => {
-                slc[2] = 0x08;
-                slc[3] = 0x00;
-                slc[8 + hw_len..8 + hw_len + sw_len].copy_from_slice(sender_addr);
-                slc[8 + 2 * hw_len + sw_len..].copy_from_slice(reciever_addr);
+    #[test]
+    fn inverse_arp() {
+        // Frame Relay (hardware type 15) has 2-byte DLCI addresses.
+        let buf = buffer(
+            15,
+            0x0800,
+            8,
+            (&[0x18, 0x41], &[0x18, 0x51]),
+            (&[10, 0, 0, 1], &[0; 4]),
+        );
+        let request = round_trip(&buf);
+        assert_eq!(request.action, Action::InArpRequest);
+        match request.hw_addr {
+            LLAddressPair::Other(ref p) => {
+                assert_eq!(
+                    (p.kind(), p.sender(), p.reciever()),
+                    (15, &[0x18, 0x41][..], &[0x18, 0x51][..])
+                )
             }
-            NLAddressPair::Ipv6(ref sender_addr, ref reciever_addr) => {
-                slc[2] = 0x86;
-                slc[3] = 0xDD;
-                slc[8 + hw_len..8 + hw_len + sw_len].copy_from_slice(sender_addr);
-                slc[8 + 2 * hw_len + sw_len..].copy_from_slice(reciever_addr);
+            ref a => panic!("{:?}", a),
+        }
+        assert_eq!(show(&request), "InREQ (18:41,10.0.0.1->18:51,?)");
+
+        let buf = buffer(
+            15,
+            0x0800,
+            9,
+            (&[0x18, 0x51], &[0x18, 0x41]),
+            (&[10, 0, 0, 2], &[10, 0, 0, 1]),
+        );
+        let reply = round_trip(&buf);
+        assert_eq!(reply.action, Action::InArpResponse);
+        assert_eq!(show(&reply), "InREP (18:51,10.0.0.2->18:41,10.0.0.1)");
+    }
+
+    #[test]
+    fn other_types_are_kept() {
+        // Ethernet addresses of an unusual length, and an unknown protocol.
+        let buf = buffer(1, 0x1234, 1, (&[1; 8], &[2; 8]), (&[3; 3], &[4; 3]));
+        let packet = round_trip(&buf);
+        match (&packet.hw_addr, &packet.sw_addr) {
+            (LLAddressPair::Other(h), NLAddressPair::Other(s)) => {
+                assert_eq!((h.kind(), h.sender().len()), (1, 8));
+                assert_eq!(
+                    (s.kind(), s.sender(), s.reciever()),
+                    (0x1234, &[3; 3][..], &[4; 3][..])
+                );
             }
+            p => panic!("{:?}", p),
         }
-        slc[6] = 0;
-        slc[5] = match self.action {
-            Action::Request => 1,
-            Action::Response => 2,
-        };
 
-        out
+        let buf = buffer(6, 0x86DD, 42, (&MAC_A, &MAC_B), (&[0xfe; 16], &[0xff; 16]));
+        let packet = round_trip(&buf);
+        assert!(matches!(packet.sw_addr, NLAddressPair::Ipv6(_, _)));
+        assert!(matches!(packet.hw_addr, LLAddressPair::Other(_)));
+        assert_eq!(packet.action, Action::Other(42));
+        assert!(show(&packet).starts_with("OP42 (02:00:00:00:00:01,fefe:"));
+    }
+
+    #[test]
+    fn invalid_lengths() {
+        assert!(matches!(
+            Packet::from_buffer(&[0, 1, 8, 0, 6, 4, 0]),
+            Err(Error::InvalidLength)
+        ));
+        let buf = buffer(
+            1,
+            0x0800,
+            1,
+            (&MAC_A, &MAC_B),
+            (&[10, 0, 0, 1], &[10, 0, 0, 2]),
+        );
+        assert!(matches!(
+            Packet::from_buffer(&buf[..buf.len() - 1]),
+            Err(Error::InvalidLengthFields)
+        ));
     }
 }
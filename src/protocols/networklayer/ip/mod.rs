@@ -79,6 +79,27 @@ pub enum Error {
     V6Err(v6::Error),
 }
 
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Error::InvalidLength => write!(f, "IP: packet is too short"),
+            Error::UnknownProtocolVersion => write!(f, "IP: unknown protocol version"),
+            Error::V4Err(e) => write!(f, "{}", e),
+            Error::V6Err(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::V4Err(e) => Some(e),
+            Error::V6Err(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
 impl Packet {
     pub fn from_buffer(slc: &[u8]) -> Result<Packet, Error> {
         if slc.len() < 1 {
@@ -20,6 +20,8 @@ pub struct Packet {
     pub mf: bool,
     pub fragment_offset: FragmentOffset,
     checksum: Option<u16>,
+    /// The options of the header as they were read, including their padding. Datagrams that are constructed have none.
+    options: Box<[u8]>,
     pub next: sessionlayer::Data,
 }
 
@@ -76,6 +78,27 @@ pub enum Error {
     InternalEcnErr,
 }
 
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Error::InvalidLength => write!(f, "IPv4: packet is too short"),
+            Error::InvalidLengthField => write!(f, "IPv4: length fields do not match the packet"),
+            Error::CantParseIP => write!(f, "IPv4: could not parse address"),
+            Error::SessionErr(e) => write!(f, "{}", e),
+            Error::InternalEcnErr => write!(f, "IPv4: invalid ECN value"),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::SessionErr(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
 impl Packet {
     pub fn from_buffer(slc: &[u8]) -> Result<Packet, Error> {
         if slc.len() < 20 {
@@ -87,7 +110,66 @@ impl Packet {
             return Err(Error::InvalidLengthField);
         }
 
-        return Ok(Packet {
+        // A fragment does not hold a whole upper-layer packet, so it is kept as it is.
+        if slc[6] & 0b00111111 != 0 || slc[7] != 0 {
+            return Packet::from_header(
+                &slc[..head_size],
+                sessionlayer::Data::UnknownIpProtocol(slc[9], slc[head_size..tot_size].into()),
+            );
+        }
+
+        let next = match slc[9] {
+            //ICMP has protocol number 0x01.
+            0x01 => sessionlayer::Data::Icmp(
+                match sessionlayer::icmp::Packet::from_buffer(&slc[head_size..tot_size]) {
+                    Ok(x) => x,
+                    Err(e) => {
+                        return Err(Error::SessionErr(sessionlayer::Error::Icmp(e)));
+                    }
+                },
+            ),
+            //TCP has protocol number 0x06.
+            0x06 => sessionlayer::Data::Tcp(
+                match sessionlayer::tcp::Segment::from_buffer(&slc[head_size..tot_size]) {
+                    Ok(x) => x,
+                    Err(e) => {
+                        return Err(Error::SessionErr(sessionlayer::Error::Tcp(e)));
+                    }
+                },
+            ),
+            //UDp has protocol number 0x06.
+            0x11 => sessionlayer::Data::Udp(
+                match sessionlayer::udp::Datagram::from_buffer(&slc[head_size..tot_size]) {
+                    Ok(x) => x,
+                    Err(e) => return Err(Error::SessionErr(sessionlayer::Error::Udp(e))),
+                },
+            ),
+            c => sessionlayer::Data::UnknownIpProtocol(c, slc[head_size..tot_size].into()),
+        };
+        Packet::from_header(&slc[..head_size], next)
+    }
+
+    /// Parses the header of a datagram that has been cut short, as the datagrams that are quoted by ICMP errors are.
+    /// The part of the payload that is present is kept as an unknown protocol, since it can not be parsed.
+    pub fn from_quoted(slc: &[u8]) -> Result<Packet, Error> {
+        if slc.len() < 20 {
+            return Err(Error::InvalidLength);
+        }
+        let tot_size = ((slc[2] as usize) << 8) | (slc[3] as usize);
+        let head_size = 4 * ((slc[0] & 0b00001111) as usize);
+        if head_size > slc.len() || head_size < 20 {
+            return Err(Error::InvalidLengthField);
+        }
+        let payload = &slc[head_size..tot_size.clamp(head_size, slc.len())];
+        Packet::from_header(
+            &slc[..head_size],
+            sessionlayer::Data::UnknownIpProtocol(slc[9], payload.into()),
+        )
+    }
+
+    /// Reads the fields of the header, which must be at least 20 bytes long. Anything after the first 20 bytes is kept as options.
+    fn from_header(slc: &[u8], next: sessionlayer::Data) -> Result<Packet, Error> {
+        Ok(Packet {
             src: match (&slc[12..16]).try_into() {
                 Err(_) => return Err(Error::CantParseIP),
                 Ok(x) => x,
@@ -109,40 +191,15 @@ impl Packet {
                 0 => false,
                 _ => true,
             },
+            // The offset is arbitrary when the DF flag is set, since the datagram can not have been fragmented.
             fragment_offset: match slc[6] & 0b01000000 {
-                0 => FragmentOffset::Arbitrary((((slc[6] << 3) as u16) << 5) + (slc[7] as u16)),
-                _ => FragmentOffset::Meaningfull((((slc[6] << 3) as u16) << 5) + (slc[7] as u16)),
+                0 => FragmentOffset::Meaningfull((((slc[6] << 3) as u16) << 5) + (slc[7] as u16)),
+                _ => FragmentOffset::Arbitrary((((slc[6] << 3) as u16) << 5) + (slc[7] as u16)),
             },
             checksum: Some(((slc[10] as u16) << 8) | (slc[11] as u16)),
-            next: match slc[9] {
-                //ICMP has protocol number 0x01.
-                0x01 => sessionlayer::Data::Icmp(
-                    match sessionlayer::icmp::Packet::from_buffer(&slc[head_size..tot_size]) {
-                        Ok(x) => x,
-                        Err(e) => {
-                            return Err(Error::SessionErr(sessionlayer::Error::Icmp(e)));
-                        }
-                    },
-                ),
-		//TCP has protocol number 0x06.
-                0x06 => sessionlayer::Data::Tcp(
-                    match sessionlayer::tcp::Segment::from_buffer(&slc[head_size..tot_size]) {
-                        Ok(x) => x,
-                        Err(e) => {
-                            return Err(Error::SessionErr(sessionlayer::Error::Tcp(e)));
-                        }
-                    },
-                ),
-		//UDp has protocol number 0x06.
-                0x11 => sessionlayer::Data::Udp(
-                    match sessionlayer::udp::Datagram::from_buffer(&slc[head_size..tot_size]) {
-                        Ok(x) => x,
-                        Err(e) => return Err(Error::SessionErr(sessionlayer::Error::Udp(e))),
-                    },
-                ),
-                c => sessionlayer::Data::UnknownIpProtocol(c, slc[head_size..tot_size].into()),
-            },
-        });
+            options: slc[20..].into(),
+            next,
+        })
     }
     pub fn into_buffer(&self, offset_needed: (usize, usize)) -> Vec<u8> {
         let mut pseudo_header = [0u8; 10];
@@ -150,12 +207,15 @@ impl Packet {
         pseudo_header[4..8].copy_from_slice(&self.dst);
         pseudo_header[9] = match self.next {
             sessionlayer::Data::Icmp(_) => 0x01,
+            sessionlayer::Data::Icmpv6(_) => 0x3A,
             sessionlayer::Data::Tcp(_) => 0x06,
             sessionlayer::Data::Udp(_) => 0x11,
             sessionlayer::Data::UnknownIpProtocol(c, _) => c, //Reserved and not used in real ipv4.
         };
+        // Options are read along with their padding, so the header stays a multiple of 4 bytes long.
+        let head_size = 20 + self.options.len();
         let mut v = self.next.into_buffer(
-            (offset_needed.0 + 20, offset_needed.1),
+            (offset_needed.0 + head_size, offset_needed.1),
             pseudo_header
                 .iter()
                 .fold((true, 0u32), |(is_even, sum), i| {
@@ -174,8 +234,8 @@ impl Packet {
         if v.len() < 20 {
             v.resize(20, 0);
         }
-        let slc = &mut v[offset_needed.0..offset_needed.0 + 20];
-        slc[0] = 0x45;
+        let slc = &mut v[offset_needed.0..offset_needed.0 + head_size];
+        slc[0] = 0x40 | (head_size / 4) as u8;
         slc[1] = (self.dscp << 2) | self.ecn.to_u8();
         slc[2] = ((len as u16) >> 8) as u8;
         slc[3] = len as u8;
@@ -199,6 +259,7 @@ impl Packet {
         slc[9] = pseudo_header[9];
         slc[12..16].clone_from_slice(&self.src);
         slc[16..20].clone_from_slice(&self.dst);
+        slc[20..].copy_from_slice(&self.options);
 
         match self.checksum {
             Some(c) => {
@@ -208,7 +269,7 @@ impl Packet {
             None => {
                 slc[10] = 0;
                 slc[11] = 0;
-                let checksum = crate::utils::ip_checksum::calc_checksum(&slc[..20], 0);
+                let checksum = crate::utils::ip_checksum::calc_checksum(slc, 0);
 
                 slc[10] = (checksum >> 8) as u8;
                 slc[11] = checksum as u8;
@@ -240,7 +301,199 @@ impl Packet {
             mf,
             fragment_offset,
             checksum: None,
+            options: Box::new([]),
             next,
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The header of a UDP datagram of 48 bytes from 10.0.0.1 to 10.0.0.2.
+    fn header() -> Vec<u8> {
+        let mut buf = vec![0x45, 0, 0, 48, 0, 1, 0x40, 0, 64, 0x11, 0xAB, 0xCD];
+        buf.extend_from_slice(&[10, 0, 0, 1, 10, 0, 0, 2]);
+        buf
+    }
+
+    /// A datagram from 10.0.0.1 to 10.0.0.2 with the given flags and offset field, which carries 4 bytes of an unknown protocol.
+    fn datagram(flags_and_offset: [u8; 2]) -> Vec<u8> {
+        let mut buf = vec![0x45, 0, 0, 24, 0, 1];
+        buf.extend_from_slice(&flags_and_offset);
+        buf.extend_from_slice(&[64, 0xFD, 0xAB, 0xCD, 10, 0, 0, 1, 10, 0, 0, 2]);
+        buf.extend_from_slice(&[1, 2, 3, 4]);
+        buf
+    }
+
+    /// Parses a datagram and checks that it is written back as it was.
+    fn round_trip(buf: &[u8]) -> Packet {
+        let packet = Packet::from_buffer(buf).unwrap();
+        assert_eq!(packet.into_buffer((0, 0)), buf);
+        packet
+    }
+
+    #[test]
+    fn dont_fragment() {
+        let packet = round_trip(&datagram([0x40, 0]));
+        assert!(matches!(
+            packet.fragment_offset,
+            FragmentOffset::Arbitrary(0)
+        ));
+        assert!(!packet.mf);
+    }
+
+    #[test]
+    fn unfragmented() {
+        let packet = round_trip(&datagram([0, 0]));
+        assert!(matches!(
+            packet.fragment_offset,
+            FragmentOffset::Meaningfull(0)
+        ));
+        assert!(!packet.mf);
+    }
+
+    #[test]
+    fn fragments() {
+        let packet = round_trip(&datagram([0x20, 0]));
+        assert!(matches!(
+            packet.fragment_offset,
+            FragmentOffset::Meaningfull(0)
+        ));
+        assert!(packet.mf);
+
+        // The offset is 13 bits long, and counts 8-byte units.
+        let packet = round_trip(&datagram([0x1F, 0xFF]));
+        assert!(matches!(
+            packet.fragment_offset,
+            FragmentOffset::Meaningfull(0x1FFF)
+        ));
+        assert!(!packet.mf);
+        match packet.next {
+            sessionlayer::Data::UnknownIpProtocol(0xFD, ref d) => assert_eq!(&d[..], &[1, 2, 3, 4]),
+            ref d => panic!("{:?}", d),
+        }
+    }
+
+    #[test]
+    fn flags_are_written() {
+        let packet = Packet::from_metadata(
+            [10, 0, 0, 1],
+            [10, 0, 0, 2],
+            1,
+            0,
+            super::super::Ecn::NonEcnCapable,
+            true,
+            FragmentOffset::Meaningfull(185),
+            64,
+            sessionlayer::Data::UnknownIpProtocol(0xFD, Box::new([1, 2, 3, 4])),
+        );
+        let buf = packet.into_buffer((0, 0));
+        assert_eq!(&buf[6..8], &[0x20, 185]);
+        // The checksum of the header is calculated, so the header adds up to 0 along with it.
+        assert_eq!(crate::utils::ip_checksum::calc_checksum(&buf[..20], 0), 0);
+        let packet = Packet::from_buffer(&buf).unwrap();
+        assert!(matches!(
+            packet.fragment_offset,
+            FragmentOffset::Meaningfull(185)
+        ));
+        assert!(packet.mf);
+    }
+
+    #[test]
+    fn options_are_kept() {
+        // A record-route option with room for one address, and an end-of-options padding byte.
+        let mut buf = vec![0x46, 0, 0, 28, 0, 1, 0, 0];
+        buf.extend_from_slice(&[64, 0xFD, 0xAB, 0xCD, 10, 0, 0, 1, 10, 0, 0, 2]);
+        buf.extend_from_slice(&[7, 3, 4, 0]);
+        buf.extend_from_slice(&[1, 2, 3, 4]);
+        let mut packet = round_trip(&buf);
+        assert_eq!(&packet.options[..], &[7, 3, 4, 0]);
+        match packet.next {
+            sessionlayer::Data::UnknownIpProtocol(0xFD, ref d) => assert_eq!(&d[..], &[1, 2, 3, 4]),
+            ref d => panic!("{:?}", d),
+        }
+
+        // The checksum covers the options.
+        packet.checksum = None;
+        let out = packet.into_buffer((2, 1));
+        assert_eq!(out.len(), 2 + 28 + 1);
+        assert_eq!(crate::utils::ip_checksum::calc_checksum(&out[2..26], 0), 0);
+    }
+
+    #[test]
+    fn fragments_keep_their_options() {
+        let mut buf = vec![0x4F, 0, 0, 64, 0, 1, 0x20, 0];
+        buf.extend_from_slice(&[64, 0x11, 0xAB, 0xCD, 10, 0, 0, 1, 10, 0, 0, 2]);
+        buf.extend((0..40).map(|i| i as u8));
+        buf.extend_from_slice(&[0x30, 0x39, 0, 53]);
+        let packet = round_trip(&buf);
+        assert_eq!(packet.options.len(), 40);
+        match packet.next {
+            sessionlayer::Data::UnknownIpProtocol(0x11, ref d) => assert_eq!(d.len(), 4),
+            ref d => panic!("{:?}", d),
+        }
+    }
+
+    #[test]
+    fn quoted_datagrams_are_cut_short() {
+        let mut buf = header();
+        buf.extend_from_slice(&[0x30, 0x39, 0, 53, 0, 28, 0, 0]);
+        // The datagram would be too short for its length field if it was parsed as a whole.
+        assert!(matches!(
+            Packet::from_buffer(&buf),
+            Err(Error::InvalidLengthField)
+        ));
+        let packet = Packet::from_quoted(&buf).unwrap();
+        assert_eq!(
+            (packet.src, packet.dst, packet.id),
+            ([10, 0, 0, 1], [10, 0, 0, 2], 1)
+        );
+        match packet.next {
+            sessionlayer::Data::UnknownIpProtocol(0x11, ref d) => assert_eq!(d.len(), 8),
+            ref d => panic!("{:?}", d),
+        }
+    }
+
+    #[test]
+    fn quoted_datagrams_end_at_their_length() {
+        // Anything after the quoted datagram is not part of it, as with Ethernet padding.
+        let mut buf = header();
+        buf[3] = 24;
+        buf.extend_from_slice(&[0x30, 0x39, 0, 53, 0xFF, 0xFF]);
+        match Packet::from_quoted(&buf).unwrap().next {
+            sessionlayer::Data::UnknownIpProtocol(_, ref d) => {
+                assert_eq!(&d[..], &[0x30, 0x39, 0, 53])
+            }
+            ref d => panic!("{:?}", d),
+        }
+        // A length field that is shorter than the header leaves no payload.
+        buf[3] = 8;
+        match Packet::from_quoted(&buf).unwrap().next {
+            sessionlayer::Data::UnknownIpProtocol(_, ref d) => assert!(d.is_empty()),
+            ref d => panic!("{:?}", d),
+        }
+    }
+
+    #[test]
+    fn quoted_headers_must_be_whole() {
+        let buf = header();
+        assert!(matches!(
+            Packet::from_quoted(&buf[..19]),
+            Err(Error::InvalidLength)
+        ));
+        // A header with options that were not quoted.
+        let mut buf = header();
+        buf[0] = 0x46;
+        assert!(matches!(
+            Packet::from_quoted(&buf),
+            Err(Error::InvalidLengthField)
+        ));
+        buf[0] = 0x44;
+        assert!(matches!(
+            Packet::from_quoted(&buf),
+            Err(Error::InvalidLengthField)
+        ));
+    }
+}
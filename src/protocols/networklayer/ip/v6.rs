@@ -1,13 +1,53 @@
 use super::super::super::sessionlayer;
+use std::convert::TryInto;
+
+// The next-header values of the extension headers that are parsed.
+const HOP_BY_HOP: u8 = 0;
+const ROUTING: u8 = 43;
+const FRAGMENT: u8 = 44;
+const DESTINATION_OPTIONS: u8 = 60;
+
+/// An option in a hop-by-hop or destination-options header. Padding options are kept, so that headers are written back exactly as they were read.
+#[derive(Debug, Clone)]
+pub struct TlvOption {
+    /// The option type. A Pad1 option (type 0) has no length or data.
+    pub kind: u8,
+    pub data: Box<[u8]>,
+}
+
+/// An extension header, which sits between the fixed header and the upper-layer protocol.
+#[derive(Debug, Clone)]
+pub enum ExtensionHeader {
+    HopByHop(Vec<TlvOption>),
+    Routing {
+        routing_type: u8,
+        segments_left: u8,
+        /// The type-specific data that follows the segments-left field.
+        data: Box<[u8]>,
+    },
+    Fragment {
+        /// The offset of the fragment, in units of 8 bytes.
+        offset: u16,
+        more: bool,
+        id: u32,
+    },
+    DestinationOptions(Vec<TlvOption>),
+}
 
 #[derive(Debug)]
 pub struct Packet {
-    dst: [u8; 16],
-    src: [u8; 16],
-    flow: u32,
-    traffic: u8,
-    ttl: u8,
-    next: sessionlayer::Data,
+    pub dst: [u8; 16],
+    pub src: [u8; 16],
+    /// The 20-bit flow label.
+    pub flow: u32,
+    /// The traffic class is split like the IPv4 type-of-service field.
+    pub dscp: u8,
+    pub ecn: super::Ecn,
+    /// The hop limit.
+    pub ttl: u8,
+    /// Extension headers, in the order that they appear in the packet.
+    pub extensions: Vec<ExtensionHeader>,
+    pub next: sessionlayer::Data,
 }
 
 pub enum PrintStyle {
@@ -18,11 +58,35 @@ pub struct PrintableData<'a> {
     pub data: &'a Packet,
 }
 
+impl std::fmt::Display for ExtensionHeader {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            ExtensionHeader::HopByHop(o) => write!(f, "HBH-{}", o.len()),
+            ExtensionHeader::Routing {
+                routing_type,
+                segments_left,
+                ..
+            } => write!(f, "RH{}-{}", routing_type, segments_left),
+            ExtensionHeader::Fragment { offset, more, id } => write!(
+                f,
+                "FRAG-{:08X}:{}{}",
+                id,
+                offset * 8,
+                match more {
+                    true => "+",
+                    false => "",
+                }
+            ),
+            ExtensionHeader::DestinationOptions(o) => write!(f, "DST-{}", o.len()),
+        }
+    }
+}
+
 impl<'a> std::fmt::Display for PrintableData<'a> {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
         match self.style {
-            PrintStyle::Full(s) =>
-                write!(f, "({:08X},{:02x}{:02x}:{:02x}{:02x}:{:02x}{:02x}:{:02x}{:02x}:{:02x}{:02x}:{:02x}{:02x}:{:02x}{:02x}:{:02x}{:02x}->{:02x}{:02x}:{:02x}{:02x}:{:02x}{:02x}:{:02x}{:02x}:{:02x}{:02x}:{:02x}{:02x}:{:02x}{:02x}:{:02x}{:02x},TTL-{}) {}", self.data.flow,
+            PrintStyle::Full(s) => {
+                write!(f, "({:05X},{:02x}{:02x}:{:02x}{:02x}:{:02x}{:02x}:{:02x}{:02x}:{:02x}{:02x}:{:02x}{:02x}:{:02x}{:02x}:{:02x}{:02x}->{:02x}{:02x}:{:02x}{:02x}:{:02x}{:02x}:{:02x}{:02x}:{:02x}{:02x}:{:02x}{:02x}:{:02x}{:02x}:{:02x}{:02x},TTL-{}", self.data.flow,
                     self.data.src[0], self.data.src[1], self.data.src[2], self.data.src[3],
                     self.data.src[4], self.data.src[5], self.data.src[6], self.data.src[7],
                     self.data.src[8], self.data.src[9], self.data.src[10], self.data.src[11],
@@ -33,8 +97,20 @@ impl<'a> std::fmt::Display for PrintableData<'a> {
                     self.data.dst[8], self.data.dst[9], self.data.dst[10], self.data.dst[11],
                     self.data.dst[12], self.data.dst[13],  self.data.dst[14],  self.data.dst[15],
 
-                    self.data.ttl, sessionlayer::PrintableData{style: s, data: &self.data.next}
+                    self.data.ttl
+                )?;
+                for e in self.data.extensions.iter() {
+                    write!(f, ",{}", e)?;
+                }
+                write!(
+                    f,
+                    ") {}",
+                    sessionlayer::PrintableData {
+                        style: s,
+                        data: &self.data.next
+                    }
                 )
+            }
         }
     }
 }
@@ -43,74 +119,282 @@ impl<'a> std::fmt::Display for PrintableData<'a> {
 pub enum Error {
     UnknownHeaderLength,
     InvalidPayloadLength,
+    /// An extension header (or one of its options) does not fit in the packet.
+    InvalidExtensionHeader,
+    InternalEcnErr,
     SessionErr(sessionlayer::Error),
 }
 
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Error::UnknownHeaderLength => write!(f, "IPv6: unknown header length"),
+            Error::InvalidPayloadLength => {
+                write!(f, "IPv6: payload length does not match the packet")
+            }
+            Error::InvalidExtensionHeader => write!(f, "IPv6: invalid extension header"),
+            Error::InternalEcnErr => write!(f, "IPv6: invalid ECN value"),
+            Error::SessionErr(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::SessionErr(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl TlvOption {
+    /// Reads the options of a hop-by-hop or destination-options header (everything after its length field).
+    fn parse_all(slc: &[u8]) -> Result<Vec<TlvOption>, Error> {
+        let mut options = Vec::new();
+        let mut i = 0;
+        while i < slc.len() {
+            // Pad1 is the only option without a length field.
+            if slc[i] == 0 {
+                options.push(TlvOption {
+                    kind: 0,
+                    data: Box::new([]),
+                });
+                i += 1;
+                continue;
+            }
+            if i + 2 > slc.len() || i + 2 + slc[i + 1] as usize > slc.len() {
+                return Err(Error::InvalidExtensionHeader);
+            }
+            let end = i + 2 + slc[i + 1] as usize;
+            options.push(TlvOption {
+                kind: slc[i],
+                data: Box::from(&slc[i + 2..end]),
+            });
+            i = end;
+        }
+        Ok(options)
+    }
+
+    fn write_all(options: &[TlvOption], out: &mut Vec<u8>) {
+        for o in options.iter() {
+            out.push(o.kind);
+            if o.kind != 0 {
+                out.push(o.data.len() as u8);
+                out.extend_from_slice(&o.data);
+            }
+        }
+    }
+}
+
+impl ExtensionHeader {
+    /// The next-header value that identifies the extension header.
+    pub fn protocol_number(&self) -> u8 {
+        match self {
+            ExtensionHeader::HopByHop(_) => HOP_BY_HOP,
+            ExtensionHeader::Routing { .. } => ROUTING,
+            ExtensionHeader::Fragment { .. } => FRAGMENT,
+            ExtensionHeader::DestinationOptions(_) => DESTINATION_OPTIONS,
+        }
+    }
+
+    /// Reads an extension header of the given type from the start of a buffer, and returns it along with its next-header value and its length.
+    /// Returns None if the type is not that of a supported extension header.
+    fn from_buffer(
+        protocol: u8,
+        slc: &[u8],
+    ) -> Option<Result<(ExtensionHeader, u8, usize), Error>> {
+        let len = match protocol {
+            FRAGMENT => 8,
+            HOP_BY_HOP | ROUTING | DESTINATION_OPTIONS => match slc.get(1) {
+                // The length field counts 8-byte units, not including the first 8 bytes.
+                Some(l) => (*l as usize + 1) * 8,
+                None => return Some(Err(Error::InvalidExtensionHeader)),
+            },
+            _ => return None,
+        };
+        if slc.len() < len {
+            return Some(Err(Error::InvalidExtensionHeader));
+        }
+        let header = match protocol {
+            HOP_BY_HOP => TlvOption::parse_all(&slc[2..len]).map(ExtensionHeader::HopByHop),
+            DESTINATION_OPTIONS => {
+                TlvOption::parse_all(&slc[2..len]).map(ExtensionHeader::DestinationOptions)
+            }
+            ROUTING => Ok(ExtensionHeader::Routing {
+                routing_type: slc[2],
+                segments_left: slc[3],
+                data: Box::from(&slc[4..len]),
+            }),
+            _ => Ok(ExtensionHeader::Fragment {
+                offset: (((slc[2] as u16) << 8) | (slc[3] as u16)) >> 3,
+                more: slc[3] & 0x01 != 0,
+                id: u32::from_be_bytes(slc[4..8].try_into().unwrap()),
+            }),
+        };
+        Some(header.map(|h| (h, slc[0], len)))
+    }
+
+    /// Writes the extension header, padding it to a multiple of 8 bytes.
+    fn write(&self, next: u8, out: &mut Vec<u8>) {
+        let start = out.len();
+        out.push(next);
+        // The length field is filled in once the header has been written.
+        out.push(0);
+        match self {
+            ExtensionHeader::HopByHop(o) | ExtensionHeader::DestinationOptions(o) => {
+                TlvOption::write_all(o, out);
+                // Options are padded with a Pad1 or a PadN option.
+                match (8 - (out.len() - start) % 8) % 8 {
+                    0 => {}
+                    1 => out.push(0),
+                    n => {
+                        out.push(1);
+                        out.push(n as u8 - 2);
+                        out.resize(out.len() + n - 2, 0);
+                    }
+                }
+            }
+            ExtensionHeader::Routing {
+                routing_type,
+                segments_left,
+                data,
+            } => {
+                out.push(*routing_type);
+                out.push(*segments_left);
+                out.extend_from_slice(data);
+                let padded = start + (out.len() - start).div_ceil(8) * 8;
+                out.resize(padded, 0);
+            }
+            ExtensionHeader::Fragment { offset, more, id } => {
+                let field = (offset << 3) | (*more as u16);
+                out.extend_from_slice(&field.to_be_bytes());
+                out.extend_from_slice(&id.to_be_bytes());
+            }
+        }
+        out[start + 1] = match self {
+            // The length field of a fragment header is reserved.
+            ExtensionHeader::Fragment { .. } => 0,
+            _ => ((out.len() - start) / 8 - 1) as u8,
+        };
+    }
+}
+
 impl Packet {
     pub fn from_buffer(buf: &[u8]) -> Result<Packet, Error> {
         if buf.len() < 40 {
             return Err(Error::UnknownHeaderLength);
         }
-        let l = ((buf[4] as u16) << 8) | (buf[5] as u16);
-        if buf.len() < (40 + l) as usize {
+        let l = ((buf[4] as usize) << 8) | (buf[5] as usize);
+        if buf.len() < 40 + l {
             return Err(Error::InvalidPayloadLength);
         }
-        let mut p = unsafe {
-            Packet {
-                traffic: (buf[0] << 4) | (buf[1] >> 4),
-                flow: (((buf[1] & 0x0F) as u32) << 16) | ((buf[2] as u32) << 8) | (buf[3] as u32),
-                ttl: buf[7],
-                src: std::mem::MaybeUninit::uninit().assume_init(),
-                dst: std::mem::MaybeUninit::uninit().assume_init(),
-                next: match buf[6] {
-		    // ICMP has protocol number 0x01.
-                    0x01 => sessionlayer::Data::Icmp(
-                        match sessionlayer::icmp::Packet::from_buffer(&buf[40..]) {
-                            Ok(x) => x,
-                            Err(e) => {
-                                return Err(Error::SessionErr(sessionlayer::Error::Icmp(e)));
-                            }
-                        },
-                    ),
-		    // TCP has protocol number 0x01.
-                    0x06 => sessionlayer::Data::Tcp(match sessionlayer::tcp::Segment::from_buffer(
-                        &buf[40..],
-                    ) {
+        // Anything after the payload (e.g. Ethernet padding) is not part of the packet.
+        let payload = &buf[40..40 + l];
+
+        let mut extensions = Vec::new();
+        let mut protocol = buf[6];
+        let mut offset = 0;
+        let mut fragmented = false;
+        while let Some(header) = ExtensionHeader::from_buffer(protocol, &payload[offset..]) {
+            let (header, next, len) = header?;
+            let later_fragment = match header {
+                ExtensionHeader::Fragment { offset, more, .. } => {
+                    fragmented = offset != 0 || more;
+                    offset != 0
+                }
+                _ => false,
+            };
+            extensions.push(header);
+            protocol = next;
+            offset += len;
+            // Only the first fragment holds the headers that follow, the others hold data.
+            if later_fragment {
+                break;
+            }
+        }
+        let upper = &payload[offset..];
+
+        Ok(Packet {
+            dscp: ((buf[0] & 0x0F) << 2) | (buf[1] >> 6),
+            ecn: match super::Ecn::from_u8((buf[1] >> 4) & 0x03) {
+                Ok(x) => x,
+                Err(_) => {
+                    return Err(Error::InternalEcnErr);
+                }
+            },
+            flow: (((buf[1] & 0x0F) as u32) << 16) | ((buf[2] as u32) << 8) | (buf[3] as u32),
+            ttl: buf[7],
+            src: buf[8..24].try_into().unwrap(),
+            dst: buf[24..40].try_into().unwrap(),
+            extensions,
+            // A fragment does not hold a whole upper-layer packet, so it is kept as it is.
+            next: match (fragmented, protocol) {
+                (true, c) => sessionlayer::Data::UnknownIpProtocol(c, upper.into()),
+                // ICMP has protocol number 0x01.
+                (false, 0x01) => {
+                    sessionlayer::Data::Icmp(match sessionlayer::icmp::Packet::from_buffer(upper) {
+                        Ok(x) => x,
+                        Err(e) => {
+                            return Err(Error::SessionErr(sessionlayer::Error::Icmp(e)));
+                        }
+                    })
+                }
+                // TCP has protocol number 0x06.
+                (false, 0x06) => {
+                    sessionlayer::Data::Tcp(match sessionlayer::tcp::Segment::from_buffer(upper) {
                         Ok(x) => x,
                         Err(e) => {
                             return Err(Error::SessionErr(sessionlayer::Error::Tcp(e)));
                         }
-                    }),
-		    // UDP has protocol number 0x11.
-                    0x11 => sessionlayer::Data::Udp(
-                        match sessionlayer::udp::Datagram::from_buffer(&buf[40..]) {
-                            Ok(x) => x,
-                            Err(e) => return Err(Error::SessionErr(sessionlayer::Error::Udp(e))),
-                        },
-                    ),
-                    c => sessionlayer::Data::UnknownIpProtocol(c, buf[40..].into()),
-                },
-            }
-        };
-        p.src.copy_from_slice(&buf[8..24]);
-        p.dst.copy_from_slice(&buf[24..40]);
-        return Ok(p);
+                    })
+                }
+                // ICMPv6 has protocol number 0x3A.
+                (false, 0x3A) => sessionlayer::Data::Icmpv6(
+                    match sessionlayer::icmpv6::Packet::from_buffer(upper) {
+                        Ok(x) => x,
+                        Err(e) => return Err(Error::SessionErr(sessionlayer::Error::Icmpv6(e))),
+                    },
+                ),
+                // UDP has protocol number 0x11.
+                (false, 0x11) => {
+                    sessionlayer::Data::Udp(match sessionlayer::udp::Datagram::from_buffer(upper) {
+                        Ok(x) => x,
+                        Err(e) => return Err(Error::SessionErr(sessionlayer::Error::Udp(e))),
+                    })
+                }
+                (false, c) => sessionlayer::Data::UnknownIpProtocol(c, upper.into()),
+            },
+        })
     }
     pub fn into_buffer(&self, offset_needed: (usize, usize)) -> Vec<u8> {
         let mut pseudo_header = [0u8; 36];
         let next: u8 = match &self.next {
             &sessionlayer::Data::Icmp(_) => 0x01,
             &sessionlayer::Data::Tcp(_) => 0x06,
+            &sessionlayer::Data::Icmpv6(_) => 0x3A,
             &sessionlayer::Data::Udp(_) => 0x11,
             &sessionlayer::Data::UnknownIpProtocol(x, _) => x,
         };
 
+        // Every extension header names the header that follows it, and the last one names the upper-layer protocol.
+        let mut extensions = Vec::new();
+        for (i, e) in self.extensions.iter().enumerate() {
+            e.write(
+                self.extensions
+                    .get(i + 1)
+                    .map_or(next, |n| n.protocol_number()),
+                &mut extensions,
+            );
+        }
+
+        // The pseudo-header always holds the upper-layer protocol, even if extension headers are present.
         pseudo_header[..16].copy_from_slice(&self.src);
         pseudo_header[16..32].copy_from_slice(&self.dst);
         pseudo_header[35] = next;
 
         let mut out = self.next.into_buffer(
-            (offset_needed.0 + 40, offset_needed.1),
+            (offset_needed.0 + 40 + extensions.len(), offset_needed.1),
             pseudo_header
                 .iter()
                 .fold((true, 0u32), |(is_even, sum), i| {
@@ -125,16 +409,22 @@ impl Packet {
                 })
                 .1,
         );
+        let payload_len = out.len() - offset_needed.0 - offset_needed.1 - 40;
+        out[offset_needed.0 + 40..offset_needed.0 + 40 + extensions.len()]
+            .copy_from_slice(&extensions);
         let slc = &mut out[offset_needed.0..offset_needed.0 + 40];
-        let payload_len = slc.len() - 40;
 
-        slc[0] = self.traffic >> 4;
-        slc[1] = (self.traffic << 4) | ((self.flow >> 16) as u8);
+        let traffic = (self.dscp << 2) | self.ecn.to_u8();
+        slc[0] = 0x60 | (traffic >> 4);
+        slc[1] = (traffic << 4) | ((self.flow >> 16) as u8 & 0x0F);
         slc[2] = (self.flow >> 8) as u8;
         slc[3] = self.flow as u8;
         slc[4] = (payload_len >> 8) as u8;
         slc[5] = payload_len as u8;
-        slc[6] = next;
+        slc[6] = self
+            .extensions
+            .first()
+            .map_or(next, |e| e.protocol_number());
         slc[7] = self.ttl;
 
         slc[8..24].copy_from_slice(&self.src);
@@ -143,3 +433,194 @@ impl Packet {
         out
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SRC: [u8; 16] = [0xfe, 0x80, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1];
+    const DST: [u8; 16] = [0xfe, 0x80, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 2];
+
+    /// A fixed header with traffic class 0xAB, flow label 0x12345 and hop limit 64.
+    fn header(next: u8, payload: &[u8]) -> Vec<u8> {
+        let mut buf = vec![0x6A, 0xB1, 0x23, 0x45];
+        buf.extend_from_slice(&(payload.len() as u16).to_be_bytes());
+        buf.push(next);
+        buf.push(64);
+        buf.extend_from_slice(&SRC);
+        buf.extend_from_slice(&DST);
+        buf.extend_from_slice(payload);
+        buf
+    }
+
+    #[test]
+    fn fixed_header_round_trip() {
+        let buf = header(59, &[]);
+        let packet = Packet::from_buffer(&buf).unwrap();
+        assert_eq!(packet.dscp, 0x2A);
+        assert!(matches!(
+            packet.ecn,
+            super::super::Ecn::CongestionEncountered
+        ));
+        assert_eq!(packet.flow, 0x12345);
+        assert_eq!(packet.ttl, 64);
+        assert_eq!(packet.src, SRC);
+        assert_eq!(packet.dst, DST);
+        assert!(packet.extensions.is_empty());
+        assert_eq!(packet.into_buffer((0, 0)), buf);
+    }
+
+    #[test]
+    fn extension_headers_round_trip() {
+        let mut payload = Vec::new();
+        // A hop-by-hop header with a router alert and a PadN option.
+        payload.extend_from_slice(&[ROUTING, 0, 5, 2, 0, 0, 1, 0]);
+        // A type 4 routing header of 24 bytes.
+        payload.extend_from_slice(&[DESTINATION_OPTIONS, 2, 4, 1]);
+        payload.extend((0..20).map(|i| i as u8));
+        // A destination-options header with two Pad1 options, a PadN option, and no next header.
+        payload.extend_from_slice(&[59, 0, 0, 0, 1, 2, 0, 0]);
+        let buf = header(HOP_BY_HOP, &payload);
+
+        let packet = Packet::from_buffer(&buf).unwrap();
+        assert_eq!(packet.extensions.len(), 3);
+        match &packet.extensions[0] {
+            ExtensionHeader::HopByHop(o) => {
+                assert_eq!(o.len(), 2);
+                assert_eq!((o[0].kind, &o[0].data[..]), (5, &[0, 0][..]));
+                assert_eq!((o[1].kind, o[1].data.len()), (1, 0));
+            }
+            e => panic!("{:?}", e),
+        }
+        match &packet.extensions[1] {
+            ExtensionHeader::Routing {
+                routing_type,
+                segments_left,
+                data,
+            } => assert_eq!((*routing_type, *segments_left, data.len()), (4, 1, 20)),
+            e => panic!("{:?}", e),
+        }
+        match &packet.extensions[2] {
+            ExtensionHeader::DestinationOptions(o) => {
+                let kinds: Vec<u8> = o.iter().map(|o| o.kind).collect();
+                assert_eq!(kinds, vec![0, 0, 1]);
+            }
+            e => panic!("{:?}", e),
+        }
+        match packet.next {
+            sessionlayer::Data::UnknownIpProtocol(59, ref d) => assert!(d.is_empty()),
+            ref d => panic!("{:?}", d),
+        }
+        assert_eq!(packet.into_buffer((0, 0)), buf);
+    }
+
+    #[test]
+    fn extension_headers_are_padded() {
+        let mut packet = Packet::from_buffer(&header(59, &[])).unwrap();
+        packet.extensions = vec![
+            ExtensionHeader::HopByHop(vec![TlvOption {
+                kind: 5,
+                data: Box::new([0, 0]),
+            }]),
+            ExtensionHeader::DestinationOptions(vec![TlvOption {
+                kind: 0xC9,
+                data: Box::new([1, 2, 3]),
+            }]),
+        ];
+        let buf = packet.into_buffer((0, 0));
+        assert_eq!(buf[6], HOP_BY_HOP);
+        // The first header is padded with a PadN option, and the second with a Pad1 option.
+        assert_eq!(&buf[40..48], &[DESTINATION_OPTIONS, 0, 5, 2, 0, 0, 1, 0]);
+        assert_eq!(&buf[48..], &[59, 0, 0xC9, 3, 1, 2, 3, 0]);
+        let packet = Packet::from_buffer(&buf).unwrap();
+        assert_eq!(packet.extensions.len(), 2);
+    }
+
+    #[test]
+    fn fragments_are_kept_as_they_are() {
+        let mut payload = vec![0x11, 0, 0x05, 0xA9, 0x12, 0x34, 0x56, 0x78];
+        payload.extend_from_slice(&[0xAA; 16]);
+        let buf = header(FRAGMENT, &payload);
+
+        let packet = Packet::from_buffer(&buf).unwrap();
+        match packet.extensions[..] {
+            [ExtensionHeader::Fragment { offset, more, id }] => {
+                assert_eq!((offset, more, id), (0xB5, true, 0x12345678))
+            }
+            ref e => panic!("{:?}", e),
+        }
+        // The datagram is not parsed as UDP, since this is only a fragment of it.
+        match packet.next {
+            sessionlayer::Data::UnknownIpProtocol(0x11, ref d) => assert_eq!(d.len(), 16),
+            ref d => panic!("{:?}", d),
+        }
+        assert_eq!(packet.into_buffer((0, 0)), buf);
+    }
+
+    #[test]
+    fn later_fragments_only_hold_data() {
+        // The data would be an invalid destination options header, if it were parsed as one.
+        let mut payload = vec![60, 0, 0x05, 0xA8, 0x12, 0x34, 0x56, 0x78];
+        payload.extend_from_slice(&[0x3B, 0x00, 0x07, 0x09, 0xAA, 0xAA, 0xAA, 0xAA]);
+        let buf = header(FRAGMENT, &payload);
+
+        let packet = Packet::from_buffer(&buf).unwrap();
+        match packet.extensions[..] {
+            [ExtensionHeader::Fragment { offset, more, .. }] => {
+                assert_eq!((offset, more), (0xB5, false))
+            }
+            ref e => panic!("{:?}", e),
+        }
+        match packet.next {
+            sessionlayer::Data::UnknownIpProtocol(60, ref d) => assert_eq!(d.len(), 8),
+            ref d => panic!("{:?}", d),
+        }
+        assert_eq!(packet.into_buffer((0, 0)), buf);
+    }
+
+    #[test]
+    fn checksums_use_the_upper_layer_protocol() {
+        // An echo request behind a hop-by-hop header, whose checksum is left to be calculated.
+        let mut payload = vec![0x3A, 0, 1, 4, 0, 0, 0, 0];
+        payload.extend_from_slice(&[128, 0, 0, 0, 0, 1, 0, 2, b'h', b'i']);
+        let buf = header(HOP_BY_HOP, &payload);
+        let mut packet = Packet::from_buffer(&buf).unwrap();
+        match packet.next {
+            sessionlayer::Data::Icmpv6(ref mut i) => i.checksum = None,
+            ref d => panic!("{:?}", d),
+        }
+        let out = packet.into_buffer((2, 3));
+        assert_eq!(out.len(), 2 + buf.len() + 3);
+        let out = &out[2..out.len() - 3];
+        assert_eq!(&out[..50], &buf[..50]);
+
+        // The sum over the pseudo-header and the message is 0 when the checksum is correct.
+        let message = &out[48..];
+        let mut pseudo_header = Vec::new();
+        pseudo_header.extend_from_slice(&SRC);
+        pseudo_header.extend_from_slice(&DST);
+        pseudo_header.extend_from_slice(&(message.len() as u32).to_be_bytes());
+        pseudo_header.extend_from_slice(&[0, 0, 0, 0x3A]);
+        pseudo_header.extend_from_slice(message);
+        assert_ne!(&message[2..4], &[0, 0]);
+        assert_eq!(
+            crate::utils::ip_checksum::calc_checksum(&pseudo_header, 0),
+            0
+        );
+    }
+
+    #[test]
+    fn truncated_extension_headers() {
+        let buf = header(HOP_BY_HOP, &[59, 1, 0, 0, 0, 0, 0, 0]);
+        assert!(matches!(
+            Packet::from_buffer(&buf),
+            Err(Error::InvalidExtensionHeader)
+        ));
+        // A PadN option that claims more bytes than the header holds.
+        let buf = header(DESTINATION_OPTIONS, &[59, 0, 1, 5, 0, 0, 0, 0]);
+        assert!(matches!(
+            Packet::from_buffer(&buf),
+            Err(Error::InvalidExtensionHeader)
+        ));
+    }
+}
@@ -1,4 +1,4 @@
-/// The address resolution protocol implementation.
+/// The address resolution protocol implementation, which RARP and Inverse ARP share.
 pub mod arp;
 /// The IP protocls (v4 and v6).
 pub mod ip;
@@ -7,6 +7,9 @@ pub mod ip;
 pub enum Data {
     Ip(ip::Packet),
     Arp(arp::Packet),
+    /// Data of a protocol that is not supported, along with the number that identifies it to the link layer.
+    /// The number is an EtherType, unless the link layer identifies protocols in another way (as PPP does).
+    Unknown(u16, Box<[u8]>),
 }
 
 #[derive(Debug)]
@@ -15,11 +18,58 @@ pub enum Error {
     Arp(arp::Error),
 }
 
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Error::Ip(e) => write!(f, "{}", e),
+            Error::Arp(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Ip(e) => Some(e),
+            Error::Arp(e) => Some(e),
+        }
+    }
+}
+
 impl Data {
+    /// Parses network-layer data according to the EtherType that identifies it. Link-layer protocols other than Ethernet (for example, Linux SLL) use EtherTypes as well.
+    pub fn from_ethertype(ethertype: u16, slc: &[u8]) -> Result<Data, Error> {
+        match ethertype {
+            0x0800 | 0x86DD => match ip::Packet::from_buffer(slc) {
+                Ok(x) => Ok(Data::Ip(x)),
+                Err(e) => Err(Error::Ip(e)),
+            },
+            0x0806 | 0x8035 => match arp::Packet::from_buffer(slc) {
+                Ok(x) => Ok(Data::Arp(x)),
+                Err(e) => Err(Error::Arp(e)),
+            },
+            t => Ok(Data::Unknown(t, slc.into())),
+        }
+    }
+    /// The EtherType that identifies the data.
+    pub fn ethertype(&self) -> u16 {
+        match self {
+            Data::Ip(ip::Packet::V4(_)) => 0x0800,
+            Data::Ip(ip::Packet::V6(_)) => 0x86DD,
+            Data::Arp(p) if p.action.is_rarp() => 0x8035,
+            Data::Arp(_) => 0x0806,
+            Data::Unknown(t, _) => *t,
+        }
+    }
     pub fn into_buffer(&self, offset_needed: (usize, usize)) -> Vec<u8> {
         match self {
             Data::Ip(ref p) => p.into_buffer(offset_needed),
             Data::Arp(ref p) => p.into_buffer(offset_needed),
+            Data::Unknown(_, ref data) => {
+                let mut out = vec![0; offset_needed.0 + data.len() + offset_needed.1];
+                out[offset_needed.0..offset_needed.0 + data.len()].copy_from_slice(data);
+                out
+            }
         }
     }
 }
@@ -46,12 +96,24 @@ impl<'a> std::fmt::Display for PrintableData<'a> {
                 ),
                 &Data::Arp(ref packet) => write!(
                     f,
-                    "ARP-{}",
+                    "{}-{}",
+                    if packet.action.is_rarp() {
+                        "RARP"
+                    } else {
+                        "ARP"
+                    },
                     arp::PrintableData {
                         style: arp::PrintStyle::Normal,
                         data: packet
                     }
                 ),
+                &Data::Unknown(number, ref data) => {
+                    write!(f, "Unknown-0x{:04x} |", number)?;
+                    for e in data.iter() {
+                        write!(f, " {:02X}", e)?;
+                    }
+                    Ok(())
+                }
             },
         }
     }
@@ -1,29 +1,230 @@
+use super::super::networklayer;
+use std::convert::TryInto;
+
 #[derive(Debug)]
 pub enum Error {
     InvalidLength,
-    UnknownAction,
 }
 
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Error::InvalidLength => write!(f, "ICMP: message is too short"),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// The code of a destination-unreachable message.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Unreachable {
+    Network,
+    Host,
+    Protocol,
+    Port,
+    /// The datagram was too big for the next hop, and could not be fragmented. Routers that predate path MTU discovery report an MTU of 0.
+    FragmentationNeeded {
+        mtu: u16,
+    },
+    SourceRouteFailed,
+    NetworkUnknown,
+    HostUnknown,
+    NetworkProhibited,
+    HostProhibited,
+    CommunicationProhibited,
+    Other(u8),
+}
+
+/// The fields of timestamp requests and replies. Timestamps are in milliseconds since midnight UT.
 #[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Timestamps {
+    pub id: u16,
+    pub seq: u16,
+    pub originate: u32,
+    pub receive: u32,
+    pub transmit: u32,
+}
+
+/// The type of a message, along with the fields that are specific to it.
+#[derive(Debug, Clone, PartialEq)]
 pub enum Action {
-    EchoRequest,
-    EchoResponse,
+    EchoRequest {
+        id: u16,
+        seq: u16,
+    },
+    EchoResponse {
+        id: u16,
+        seq: u16,
+    },
+    DestinationUnreachable {
+        reason: Unreachable,
+        /// The rest-of-header field, whose second byte is the length of the quoted datagram in 4-byte words if extensions follow it (RFC 4884). The MTU of a fragmentation-needed error replaces its last 2 bytes.
+        unused: [u8; 4],
+    },
+    Redirect {
+        /// 0 for a network, 1 for a host, and 2 or 3 for a network or a host with a specific type of service.
+        code: u8,
+        gateway: [u8; 4],
+    },
+    TimeExceeded {
+        /// 0 if the TTL has expired in transit, and 1 if reassembly has timed out.
+        code: u8,
+        unused: [u8; 4],
+    },
+    ParameterProblem {
+        code: u8,
+        /// The offset of the byte that caused the error in the datagram that caused it.
+        pointer: u8,
+        /// The bytes that follow the pointer, the first of which is the length of the quoted datagram (RFC 4884).
+        unused: [u8; 3],
+    },
+    TimestampRequest(Timestamps),
+    TimestampReply(Timestamps),
+    RouterAdvertisement {
+        /// The number of seconds for which the addresses are valid.
+        lifetime: u16,
+        /// The addresses of the router, along with their preference levels.
+        routers: Vec<([u8; 4], i32)>,
+    },
+    RouterSolicitation {
+        reserved: [u8; 4],
+    },
+    /// Any other message, including router advertisements with entries that are not 8 bytes long.
+    Unknown {
+        kind: u8,
+        code: u8,
+        rest: [u8; 4],
+    },
+}
+
+impl Unreachable {
+    fn from_code(code: u8, rest: &[u8]) -> Unreachable {
+        match code {
+            0 => Unreachable::Network,
+            1 => Unreachable::Host,
+            2 => Unreachable::Protocol,
+            3 => Unreachable::Port,
+            4 => Unreachable::FragmentationNeeded {
+                mtu: ((rest[2] as u16) << 8) | (rest[3] as u16),
+            },
+            5 => Unreachable::SourceRouteFailed,
+            6 => Unreachable::NetworkUnknown,
+            7 => Unreachable::HostUnknown,
+            9 => Unreachable::NetworkProhibited,
+            10 => Unreachable::HostProhibited,
+            13 => Unreachable::CommunicationProhibited,
+            c => Unreachable::Other(c),
+        }
+    }
+
+    fn code(self) -> u8 {
+        match self {
+            Unreachable::Network => 0,
+            Unreachable::Host => 1,
+            Unreachable::Protocol => 2,
+            Unreachable::Port => 3,
+            Unreachable::FragmentationNeeded { .. } => 4,
+            Unreachable::SourceRouteFailed => 5,
+            Unreachable::NetworkUnknown => 6,
+            Unreachable::HostUnknown => 7,
+            Unreachable::NetworkProhibited => 9,
+            Unreachable::HostProhibited => 10,
+            Unreachable::CommunicationProhibited => 13,
+            Unreachable::Other(c) => c,
+        }
+    }
+}
+
+impl Timestamps {
+    fn from_buffer(buf: &[u8]) -> Timestamps {
+        Timestamps {
+            id: ((buf[4] as u16) << 8) | (buf[5] as u16),
+            seq: ((buf[6] as u16) << 8) | (buf[7] as u16),
+            originate: u32::from_be_bytes(buf[8..12].try_into().unwrap()),
+            receive: u32::from_be_bytes(buf[12..16].try_into().unwrap()),
+            transmit: u32::from_be_bytes(buf[16..20].try_into().unwrap()),
+        }
+    }
 }
 
 impl Action {
-    fn header_fields(self) -> (u8, u8) {
+    fn header_fields(&self) -> (u8, u8) {
+        match self {
+            Action::EchoResponse { .. } => (0, 0),
+            Action::DestinationUnreachable { reason, .. } => (3, reason.code()),
+            Action::Redirect { code, .. } => (5, *code),
+            Action::EchoRequest { .. } => (8, 0),
+            Action::RouterAdvertisement { .. } => (9, 0),
+            Action::RouterSolicitation { .. } => (10, 0),
+            Action::TimeExceeded { code, .. } => (11, *code),
+            Action::ParameterProblem { code, .. } => (12, *code),
+            Action::TimestampRequest(_) => (13, 0),
+            Action::TimestampReply(_) => (14, 0),
+            Action::Unknown { kind, code, .. } => (*kind, *code),
+        }
+    }
+
+    /// Writes the rest-of-header field, and any fields that follow it.
+    fn write_fields(&self, out: &mut Vec<u8>) {
         match self {
-            Action::EchoRequest => (8, 0),
-            Action::EchoResponse => (0, 0),
+            Action::EchoRequest { id, seq } | Action::EchoResponse { id, seq } => {
+                out.extend_from_slice(&id.to_be_bytes());
+                out.extend_from_slice(&seq.to_be_bytes());
+            }
+            Action::DestinationUnreachable {
+                reason: Unreachable::FragmentationNeeded { mtu },
+                unused,
+            } => {
+                out.extend_from_slice(&unused[..2]);
+                out.extend_from_slice(&mtu.to_be_bytes());
+            }
+            Action::Redirect { gateway, .. } => out.extend_from_slice(gateway),
+            Action::ParameterProblem {
+                pointer, unused, ..
+            } => {
+                out.push(*pointer);
+                out.extend_from_slice(unused);
+            }
+            Action::TimestampRequest(t) | Action::TimestampReply(t) => {
+                out.extend_from_slice(&t.id.to_be_bytes());
+                out.extend_from_slice(&t.seq.to_be_bytes());
+                out.extend_from_slice(&t.originate.to_be_bytes());
+                out.extend_from_slice(&t.receive.to_be_bytes());
+                out.extend_from_slice(&t.transmit.to_be_bytes());
+            }
+            Action::RouterAdvertisement { lifetime, routers } => {
+                // Every entry is an address and a preference level, which are 2 words long.
+                out.push(routers.len() as u8);
+                out.push(2);
+                out.extend_from_slice(&lifetime.to_be_bytes());
+                for (addr, preference) in routers.iter() {
+                    out.extend_from_slice(addr);
+                    out.extend_from_slice(&preference.to_be_bytes());
+                }
+            }
+            Action::Unknown { rest, .. } => out.extend_from_slice(rest),
+            Action::DestinationUnreachable { unused, .. }
+            | Action::TimeExceeded { unused, .. }
+            | Action::RouterSolicitation { reserved: unused } => out.extend_from_slice(unused),
         }
     }
 }
 
+/// The start of the datagram that caused an error message, which links the error to the flow of the datagram.
+#[derive(Debug)]
+pub struct Quoted {
+    /// The header of the datagram. Only part of its payload is quoted, so the payload is kept as an unknown protocol.
+    pub header: networklayer::ip::Packet,
+    /// The source and destination ports of the datagram, if it carried TCP or UDP.
+    pub ports: Option<(u16, u16)>,
+}
+
 #[derive(Debug)]
 pub struct Packet {
     pub action: Action,
     pub checksum: u16,
-    pub rest: [u8; 4],
+    /// Everything that follows the fields of the action (e.g. the echoed data, or the start of the datagram that caused an error).
     pub data: Box<[u8]>,
 }
 
@@ -40,18 +241,76 @@ impl<'a> std::fmt::Display for PrintableData<'a> {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
         match self.style {
             PrintStyle::Normal => {
-                write!(
-                    f,
-                    "{} | {:02X} {:02X} {:02x} {:02x}",
-                    match self.data.action {
-                        Action::EchoRequest => "ECHOREQ",
-                        Action::EchoResponse => "ECHOREP",
-                    },
-                    self.data.data[0],
-                    self.data.data[1],
-                    self.data.data[2],
-                    self.data.data[3]
-                )
+                match self.data.action {
+                    Action::EchoRequest { id, seq } => write!(f, "ECHOREQ({},{})", id, seq)?,
+                    Action::EchoResponse { id, seq } => write!(f, "ECHOREP({},{})", id, seq)?,
+                    Action::DestinationUnreachable {
+                        reason: Unreachable::FragmentationNeeded { mtu },
+                        ..
+                    } => write!(f, "UNREACH-FRAG(MTU-{})", mtu)?,
+                    Action::DestinationUnreachable { reason, .. } => {
+                        write!(f, "UNREACH-{}", reason.code())?
+                    }
+                    Action::Redirect { code, gateway } => write!(
+                        f,
+                        "REDIRECT-{}({}.{}.{}.{})",
+                        code, gateway[0], gateway[1], gateway[2], gateway[3]
+                    )?,
+                    Action::TimeExceeded { code, .. } => write!(f, "TIMEEXC-{}", code)?,
+                    Action::ParameterProblem { code, pointer, .. } => {
+                        write!(f, "PARAMPROB-{}@{}", code, pointer)?
+                    }
+                    Action::TimestampRequest(t) => write!(f, "TSREQ({},{})", t.id, t.seq)?,
+                    Action::TimestampReply(t) => write!(f, "TSREP({},{})", t.id, t.seq)?,
+                    Action::RouterAdvertisement {
+                        lifetime,
+                        ref routers,
+                    } => {
+                        write!(f, "ROUTERADV({}s", lifetime)?;
+                        for (a, p) in routers.iter() {
+                            write!(f, ",{}.{}.{}.{}:{}", a[0], a[1], a[2], a[3], p)?;
+                        }
+                        write!(f, ")")?
+                    }
+                    Action::RouterSolicitation { .. } => write!(f, "ROUTERSOL")?,
+                    Action::Unknown { kind, code, .. } => write!(f, "{}-{}", kind, code)?,
+                }
+                write!(f, " |")?;
+                match self.data.quoted() {
+                    Some(Quoted {
+                        header: networklayer::ip::Packet::V4(ref h),
+                        ports,
+                    }) => {
+                        let (src_port, dst_port) = match ports {
+                            Some((s, d)) => (format!(":{}", s), format!(":{}", d)),
+                            None => (String::new(), String::new()),
+                        };
+                        write!(
+                            f,
+                            " {}.{}.{}.{}{}->{}.{}.{}.{}{}",
+                            h.src[0],
+                            h.src[1],
+                            h.src[2],
+                            h.src[3],
+                            src_port,
+                            h.dst[0],
+                            h.dst[1],
+                            h.dst[2],
+                            h.dst[3],
+                            dst_port
+                        )?;
+                        if let super::Data::UnknownIpProtocol(proto, _) = h.next {
+                            write!(f, " ({})", proto)?;
+                        }
+                        Ok(())
+                    }
+                    _ => {
+                        for e in self.data.data.iter().take(4) {
+                            write!(f, " {:02X}", e)?;
+                        }
+                        Ok(())
+                    }
+                }
             }
         }
     }
@@ -62,62 +321,435 @@ impl Packet {
         if buf.len() < 8 {
             return Err(Error::InvalidLength);
         }
-        return Ok(Packet {
-            checksum: ((buf[2] as u16) << 8) + (buf[3] as u16),
-            rest: [buf[4], buf[5], buf[6], buf[7]],
-            action: match (buf[0], buf[1]) {
-                (0, 0) => Action::EchoResponse,
-                (8, 0) => Action::EchoRequest,
-                _ => {
-                    return Err(Error::UnknownAction);
+        let rest = &buf[4..8];
+        let mut data_start = 8;
+        let action = match (buf[0], buf[1]) {
+            (0, 0) | (8, 0) => {
+                let id = ((rest[0] as u16) << 8) | (rest[1] as u16);
+                let seq = ((rest[2] as u16) << 8) | (rest[3] as u16);
+                match buf[0] {
+                    0 => Action::EchoResponse { id, seq },
+                    _ => Action::EchoRequest { id, seq },
                 }
+            }
+            (3, c) => Action::DestinationUnreachable {
+                reason: Unreachable::from_code(c, rest),
+                unused: rest.try_into().unwrap(),
+            },
+            (5, code) => Action::Redirect {
+                code,
+                gateway: rest.try_into().unwrap(),
+            },
+            (9, 0) if rest[1] == 2 && buf.len() >= 8 + rest[0] as usize * 8 => {
+                data_start += rest[0] as usize * 8;
+                Action::RouterAdvertisement {
+                    lifetime: ((rest[2] as u16) << 8) | (rest[3] as u16),
+                    routers: buf[8..data_start]
+                        .chunks(8)
+                        .map(|e| {
+                            (
+                                e[..4].try_into().unwrap(),
+                                i32::from_be_bytes(e[4..].try_into().unwrap()),
+                            )
+                        })
+                        .collect(),
+                }
+            }
+            (10, 0) => Action::RouterSolicitation {
+                reserved: rest.try_into().unwrap(),
+            },
+            (11, code) => Action::TimeExceeded {
+                code,
+                unused: rest.try_into().unwrap(),
+            },
+            (12, code) => Action::ParameterProblem {
+                code,
+                pointer: rest[0],
+                unused: rest[1..].try_into().unwrap(),
             },
-            data: (&buf[8..]).into(),
-        });
+            (13, 0) | (14, 0) => {
+                if buf.len() < 20 {
+                    return Err(Error::InvalidLength);
+                }
+                data_start = 20;
+                match buf[0] {
+                    13 => Action::TimestampRequest(Timestamps::from_buffer(buf)),
+                    _ => Action::TimestampReply(Timestamps::from_buffer(buf)),
+                }
+            }
+            (kind, code) => Action::Unknown {
+                kind,
+                code,
+                rest: rest.try_into().unwrap(),
+            },
+        };
+        Ok(Packet {
+            checksum: ((buf[2] as u16) << 8) + (buf[3] as u16),
+            action,
+            data: (&buf[data_start..]).into(),
+        })
     }
 
     pub fn into_buffer(&self, offset_needed: (usize, usize)) -> Vec<u8> {
-        let mut out: Vec<u8> = vec![0u8; offset_needed.0 + 8 + self.data.len() + offset_needed.1];
-        let slc = &mut out[offset_needed.0..offset_needed.0 + 8 + self.data.len()];
         let (t, c) = self.action.header_fields();
-
-        slc[0] = t;
-        slc[1] = c;
-        slc[2] = (self.checksum >> 8) as u8;
-        slc[3] = self.checksum as u8;
-        slc[4] = self.rest[0];
-        slc[5] = self.rest[1];
-        slc[6] = self.rest[2];
-        slc[7] = self.rest[3];
-        slc[8..].copy_from_slice(self.data.as_ref());
+        let mut out = vec![0u8; offset_needed.0];
+        out.push(t);
+        out.push(c);
+        out.extend_from_slice(&self.checksum.to_be_bytes());
+        self.action.write_fields(&mut out);
+        out.extend_from_slice(self.data.as_ref());
+        out.resize(out.len() + offset_needed.1, 0);
 
         out
     }
 
+    /// Parses the datagram that is quoted by an error message. Returns None if the message is not an error, or if the quoted header is invalid.
+    pub fn quoted(&self) -> Option<Quoted> {
+        match self.action {
+            Action::DestinationUnreachable { .. }
+            | Action::Redirect { .. }
+            | Action::TimeExceeded { .. }
+            | Action::ParameterProblem { .. } => {}
+            _ => return None,
+        }
+        let header = networklayer::ip::v4::Packet::from_quoted(&self.data).ok()?;
+        let ports = match header.next {
+            // Both TCP and UDP start with the source and destination ports.
+            super::Data::UnknownIpProtocol(0x06, ref d)
+            | super::Data::UnknownIpProtocol(0x11, ref d)
+                if d.len() >= 4 =>
+            {
+                Some((
+                    ((d[0] as u16) << 8) | (d[1] as u16),
+                    ((d[2] as u16) << 8) | (d[3] as u16),
+                ))
+            }
+            _ => None,
+        };
+        Some(Quoted {
+            header: networklayer::ip::Packet::V4(header),
+            ports,
+        })
+    }
+
     pub fn calc_checksum(&mut self) {
-        //if looking to send packet - checksum field should be 0
-        let (action, code) = self.action.header_fields();
-        let mut checksum: u32 = 0;
-        checksum += (action as u32) << 8;
-        checksum += code as u32;
-        checksum += self.checksum as u32;
-        checksum += (self.rest[0] as u32) << 8;
-        checksum += self.rest[1] as u32;
-        checksum += (self.rest[2] as u32) << 8;
-        checksum += self.rest[3] as u32;
-
-        let mut calc_even: bool = true;
-        for c in self.data.iter() {
-            checksum += match calc_even {
-                true => (*c as u32) << 8,
-                false => *c as u32,
-            };
-            calc_even = !calc_even;
+        // The checksum field is zeroed, so that the old checksum is not part of the sum.
+        self.checksum = 0;
+        self.checksum = crate::utils::ip_checksum::calc_checksum(&self.into_buffer((0, 0)), 0);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Parses a message and checks that it is written back as it was.
+    fn round_trip(buf: &[u8]) -> Packet {
+        let packet = Packet::from_buffer(buf).unwrap();
+        assert_eq!(packet.checksum, 0x1234);
+        assert_eq!(packet.into_buffer((0, 0)), buf);
+        packet
+    }
+
+    #[test]
+    fn echo() {
+        let packet = round_trip(&[8, 0, 0x12, 0x34, 0, 7, 0, 1, b'h', b'i']);
+        assert_eq!(packet.action, Action::EchoRequest { id: 7, seq: 1 });
+        assert_eq!(&packet.data[..], b"hi");
+        let packet = round_trip(&[0, 0, 0x12, 0x34, 0, 7, 0, 1]);
+        assert_eq!(packet.action, Action::EchoResponse { id: 7, seq: 1 });
+        assert!(packet.data.is_empty());
+    }
+
+    #[test]
+    fn destination_unreachable() {
+        let packet = round_trip(&[3, 3, 0x12, 0x34, 0, 0, 0, 0]);
+        assert_eq!(
+            packet.action,
+            Action::DestinationUnreachable {
+                reason: Unreachable::Port,
+                unused: [0; 4]
+            }
+        );
+        let packet = round_trip(&[3, 13, 0x12, 0x34, 0, 0, 0, 0]);
+        assert_eq!(
+            packet.action,
+            Action::DestinationUnreachable {
+                reason: Unreachable::CommunicationProhibited,
+                unused: [0; 4]
+            }
+        );
+        let packet = round_trip(&[3, 4, 0x12, 0x34, 0, 0, 0x05, 0xDC]);
+        match packet.action {
+            Action::DestinationUnreachable { reason, .. } => {
+                assert_eq!(reason, Unreachable::FragmentationNeeded { mtu: 1500 })
+            }
+            a => panic!("{:?}", a),
         }
+        let packet = round_trip(&[3, 15, 0x12, 0x34, 0, 0, 0, 0]);
+        assert_eq!(
+            packet.action,
+            Action::DestinationUnreachable {
+                reason: Unreachable::Other(15),
+                unused: [0; 4]
+            }
+        );
+    }
+
+    #[test]
+    fn unused_bytes_are_kept() {
+        // The second byte is the length of the quoted datagram when extensions follow it.
+        let packet = round_trip(&[3, 1, 0x12, 0x34, 0xAA, 7, 0xBB, 0xCC]);
+        assert_eq!(
+            packet.action,
+            Action::DestinationUnreachable {
+                reason: Unreachable::Host,
+                unused: [0xAA, 7, 0xBB, 0xCC]
+            }
+        );
+        let packet = round_trip(&[3, 4, 0x12, 0x34, 0xAA, 7, 0x05, 0xDC]);
+        match packet.action {
+            Action::DestinationUnreachable { reason, unused } => {
+                assert_eq!(reason, Unreachable::FragmentationNeeded { mtu: 1500 });
+                assert_eq!(unused[..2], [0xAA, 7]);
+            }
+            a => panic!("{:?}", a),
+        }
+        let packet = round_trip(&[11, 0, 0x12, 0x34, 0xAA, 7, 0xBB, 0xCC]);
+        assert_eq!(
+            packet.action,
+            Action::TimeExceeded {
+                code: 0,
+                unused: [0xAA, 7, 0xBB, 0xCC]
+            }
+        );
+        let packet = round_trip(&[12, 0, 0x12, 0x34, 20, 7, 0xBB, 0xCC]);
+        assert_eq!(
+            packet.action,
+            Action::ParameterProblem {
+                code: 0,
+                pointer: 20,
+                unused: [7, 0xBB, 0xCC]
+            }
+        );
+        let packet = round_trip(&[10, 0, 0x12, 0x34, 1, 2, 3, 4]);
+        assert_eq!(
+            packet.action,
+            Action::RouterSolicitation {
+                reserved: [1, 2, 3, 4]
+            }
+        );
+    }
 
-        checksum = (checksum >> 16) + (checksum & 0x0000FFFF);
-        checksum = (checksum >> 16) + (checksum & 0x0000FFFF);
+    #[test]
+    fn other_errors() {
+        let packet = round_trip(&[5, 1, 0x12, 0x34, 10, 0, 0, 1]);
+        assert_eq!(
+            packet.action,
+            Action::Redirect {
+                code: 1,
+                gateway: [10, 0, 0, 1]
+            }
+        );
+        let packet = round_trip(&[11, 1, 0x12, 0x34, 0, 0, 0, 0]);
+        assert_eq!(
+            packet.action,
+            Action::TimeExceeded {
+                code: 1,
+                unused: [0; 4]
+            }
+        );
+        let packet = round_trip(&[12, 0, 0x12, 0x34, 20, 0, 0, 0]);
+        assert_eq!(
+            packet.action,
+            Action::ParameterProblem {
+                code: 0,
+                pointer: 20,
+                unused: [0; 3]
+            }
+        );
+    }
+
+    #[test]
+    fn timestamps() {
+        let mut buf = vec![13, 0, 0x12, 0x34, 0, 7, 0, 1];
+        buf.extend_from_slice(&1000u32.to_be_bytes());
+        buf.extend_from_slice(&[0; 8]);
+        let packet = round_trip(&buf);
+        assert_eq!(
+            packet.action,
+            Action::TimestampRequest(Timestamps {
+                id: 7,
+                seq: 1,
+                originate: 1000,
+                receive: 0,
+                transmit: 0,
+            })
+        );
+        assert!(packet.data.is_empty());
+
+        buf[0] = 14;
+        buf[12..20].copy_from_slice(&[0, 0, 0x03, 0xE9, 0, 0, 0x03, 0xEA]);
+        match round_trip(&buf).action {
+            Action::TimestampReply(t) => assert_eq!((t.receive, t.transmit), (1001, 1002)),
+            a => panic!("{:?}", a),
+        }
+
+        assert!(matches!(
+            Packet::from_buffer(&buf[..19]),
+            Err(Error::InvalidLength)
+        ));
+    }
+
+    #[test]
+    fn router_discovery() {
+        let mut buf = vec![9, 0, 0x12, 0x34, 2, 2, 0x07, 0x08];
+        buf.extend_from_slice(&[10, 0, 0, 1, 0, 0, 0, 0]);
+        buf.extend_from_slice(&[10, 0, 0, 2, 0xFF, 0xFF, 0xFF, 0xFF]);
+        let packet = round_trip(&buf);
+        assert_eq!(
+            packet.action,
+            Action::RouterAdvertisement {
+                lifetime: 1800,
+                routers: vec![([10, 0, 0, 1], 0), ([10, 0, 0, 2], -1)],
+            }
+        );
+        assert!(packet.data.is_empty());
+
+        // Entries of any other size are not understood, and neither are entries that do not fit in the message.
+        buf[5] = 3;
+        assert!(matches!(
+            round_trip(&buf).action,
+            Action::Unknown { kind: 9, .. }
+        ));
+        buf[5] = 2;
+        buf[4] = 3;
+        assert!(matches!(
+            round_trip(&buf).action,
+            Action::Unknown { kind: 9, .. }
+        ));
+
+        let packet = round_trip(&[10, 0, 0x12, 0x34, 0, 0, 0, 0]);
+        assert_eq!(
+            packet.action,
+            Action::RouterSolicitation { reserved: [0; 4] }
+        );
+    }
+
+    #[test]
+    fn unknown_messages() {
+        let packet = round_trip(&[42, 1, 0x12, 0x34, 1, 2, 3, 4, 5]);
+        assert_eq!(
+            packet.action,
+            Action::Unknown {
+                kind: 42,
+                code: 1,
+                rest: [1, 2, 3, 4]
+            }
+        );
+        assert_eq!(&packet.data[..], &[5]);
+        assert!(matches!(
+            Packet::from_buffer(&[8, 0, 0, 0, 0, 0, 0]),
+            Err(Error::InvalidLength)
+        ));
+    }
+
+    /// A port-unreachable error that quotes the header of a datagram and the first 8 bytes of its payload.
+    fn unreachable(protocol: u8) -> Vec<u8> {
+        let mut buf = vec![3, 3, 0x12, 0x34, 0, 0, 0, 0];
+        buf.extend_from_slice(&[0x45, 0, 0, 48, 0, 1, 0x40, 0, 64, protocol, 0xAB, 0xCD]);
+        buf.extend_from_slice(&[10, 0, 0, 1, 10, 0, 0, 2]);
+        buf.extend_from_slice(&[0x30, 0x39, 0, 53, 0, 28, 0, 0]);
+        buf
+    }
+
+    #[test]
+    fn quoted_datagrams() {
+        for protocol in [0x06, 0x11].iter() {
+            let packet = round_trip(&unreachable(*protocol));
+            let quoted = packet.quoted().unwrap();
+            assert_eq!(quoted.ports, Some((12345, 53)));
+            match quoted.header {
+                networklayer::ip::Packet::V4(h) => {
+                    assert_eq!((h.src, h.dst, h.ttl), ([10, 0, 0, 1], [10, 0, 0, 2], 64));
+                    match h.next {
+                        super::super::Data::UnknownIpProtocol(p, ref d) => {
+                            assert_eq!((p, d.len()), (*protocol, 8))
+                        }
+                        ref d => panic!("{:?}", d),
+                    }
+                }
+                h => panic!("{:?}", h),
+            }
+            assert_eq!(
+                format!(
+                    "{}",
+                    PrintableData {
+                        style: PrintStyle::Normal,
+                        data: &packet
+                    }
+                ),
+                format!("UNREACH-3 | 10.0.0.1:12345->10.0.0.2:53 ({})", protocol)
+            );
+        }
+
+        // Other protocols have no ports.
+        let packet = round_trip(&unreachable(0x2F));
+        assert_eq!(packet.quoted().unwrap().ports, None);
+        assert_eq!(
+            format!(
+                "{}",
+                PrintableData {
+                    style: PrintStyle::Normal,
+                    data: &packet
+                }
+            ),
+            "UNREACH-3 | 10.0.0.1->10.0.0.2 (47)"
+        );
+    }
+
+    #[test]
+    fn quoted_datagrams_are_only_read_from_errors() {
+        let mut buf = unreachable(0x11);
+        buf[0] = 8;
+        buf[1] = 0;
+        assert!(round_trip(&buf).quoted().is_none());
+
+        // A quote that is too short for an IPv4 header is shown as data.
+        let mut buf = unreachable(0x11);
+        buf.truncate(8 + 19);
+        let packet = round_trip(&buf);
+        assert!(packet.quoted().is_none());
+        assert_eq!(
+            format!(
+                "{}",
+                PrintableData {
+                    style: PrintStyle::Normal,
+                    data: &packet
+                }
+            ),
+            "UNREACH-3 | 45 00 00 30"
+        );
+
+        // Ports are only read if both of them were quoted.
+        let mut buf = unreachable(0x06);
+        buf.truncate(8 + 20 + 3);
+        assert_eq!(round_trip(&buf).quoted().unwrap().ports, None);
+    }
 
-        self.checksum = !(checksum as u16);
+    #[test]
+    fn checksums() {
+        let mut packet = Packet::from_buffer(&[8, 0, 0x12, 0x34, 0, 7, 0, 1, b'h', b'i']).unwrap();
+        packet.calc_checksum();
+        assert_ne!(packet.checksum, 0x1234);
+        // The message adds up to 0 along with a correct checksum.
+        assert_eq!(
+            crate::utils::ip_checksum::calc_checksum(&packet.into_buffer((0, 0)), 0),
+            0
+        );
+        // The old checksum does not change the new one.
+        let checksum = packet.checksum;
+        packet.calc_checksum();
+        assert_eq!(packet.checksum, checksum);
     }
 }
@@ -0,0 +1,758 @@
+use super::super::linklayer::ethernet::HW_ADDR_LEN;
+use std::convert::TryInto;
+
+#[derive(Debug)]
+pub enum Error {
+    InvalidLength,
+    /// An NDP option has a length of 0 or does not fit in the message.
+    InvalidOption,
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Error::InvalidLength => write!(f, "ICMPv6: message is too short"),
+            Error::InvalidOption => write!(f, "ICMPv6: invalid NDP option"),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// An option of a Neighbor Discovery message.
+#[derive(Debug, Clone)]
+pub enum NdpOption {
+    SourceLinkLayerAddress([u8; HW_ADDR_LEN]),
+    TargetLinkLayerAddress([u8; HW_ADDR_LEN]),
+    /// Any other option, including link-layer addresses that are not Ethernet addresses. The data follows the type and length fields.
+    Other {
+        kind: u8,
+        data: Box<[u8]>,
+    },
+}
+
+#[derive(Debug)]
+pub enum Message {
+    DestinationUnreachable {
+        code: u8,
+        /// The unused field, whose first byte is the length of the quoted packet in 8-byte units if extensions follow it (RFC 4884).
+        unused: [u8; 4],
+        /// As much of the packet that caused the error as fits in the minimum IPv6 MTU.
+        data: Box<[u8]>,
+    },
+    PacketTooBig {
+        code: u8,
+        mtu: u32,
+        data: Box<[u8]>,
+    },
+    TimeExceeded {
+        code: u8,
+        unused: [u8; 4],
+        data: Box<[u8]>,
+    },
+    ParameterProblem {
+        code: u8,
+        /// The offset of the field that caused the error in the packet that caused it.
+        pointer: u32,
+        data: Box<[u8]>,
+    },
+    EchoRequest {
+        code: u8,
+        id: u16,
+        seq: u16,
+        data: Box<[u8]>,
+    },
+    EchoReply {
+        code: u8,
+        id: u16,
+        seq: u16,
+        data: Box<[u8]>,
+    },
+    RouterSolicitation {
+        reserved: [u8; 4],
+        options: Vec<NdpOption>,
+    },
+    RouterAdvertisement {
+        hop_limit: u8,
+        managed: bool,
+        other: bool,
+        /// Whether the router is a Mobile IPv6 home agent (RFC 6275).
+        home_agent: bool,
+        /// The preference of the default route (RFC 4191), a 2-bit signed value.
+        preference: u8,
+        /// Whether the router is an ND proxy (RFC 4389).
+        proxy: bool,
+        /// The 2 lowest bits of the flags.
+        reserved: u8,
+        /// The lifetime of the default route in seconds, or 0 if the router is not a default router.
+        lifetime: u16,
+        reachable_time: u32,
+        retrans_timer: u32,
+        options: Vec<NdpOption>,
+    },
+    NeighborSolicitation {
+        reserved: [u8; 4],
+        target: [u8; 16],
+        options: Vec<NdpOption>,
+    },
+    NeighborAdvertisement {
+        router: bool,
+        solicited: bool,
+        overrides: bool,
+        /// The 29 bits that follow the flags.
+        reserved: u32,
+        target: [u8; 16],
+        options: Vec<NdpOption>,
+    },
+    /// Any other message. The body is everything that follows the checksum.
+    Unknown {
+        kind: u8,
+        code: u8,
+        body: Box<[u8]>,
+    },
+}
+
+#[derive(Debug)]
+pub struct Packet {
+    pub message: Message,
+    /// None if the checksum should be calculated when the packet is written.
+    pub checksum: Option<u16>,
+}
+
+pub enum PrintStyle {
+    Normal,
+}
+
+pub struct PrintableData<'a> {
+    pub style: PrintStyle,
+    pub data: &'a Packet,
+}
+
+fn fmt_options(f: &mut std::fmt::Formatter, options: &[NdpOption]) -> std::fmt::Result {
+    for o in options.iter() {
+        match o {
+            NdpOption::SourceLinkLayerAddress(a) | NdpOption::TargetLinkLayerAddress(a) => write!(
+                f,
+                " {}{:02x}:{:02x}:{:02x}:{:02x}:{:02x}:{:02x}",
+                match o {
+                    NdpOption::SourceLinkLayerAddress(_) => "SLL-",
+                    _ => "TLL-",
+                },
+                a[0],
+                a[1],
+                a[2],
+                a[3],
+                a[4],
+                a[5]
+            )?,
+            NdpOption::Other { kind, data } => write!(f, " OPT{}-{}", kind, data.len())?,
+        }
+    }
+    Ok(())
+}
+
+impl<'a> std::fmt::Display for PrintableData<'a> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self.style {
+            PrintStyle::Normal => match self.data.message {
+                Message::DestinationUnreachable { code, ref data, .. } => {
+                    write!(f, "UNREACH-{} | {} bytes", code, data.len())
+                }
+                Message::PacketTooBig { mtu, ref data, .. } => {
+                    write!(f, "TOOBIG-{} | {} bytes", mtu, data.len())
+                }
+                Message::TimeExceeded { code, ref data, .. } => {
+                    write!(f, "TIMEEXC-{} | {} bytes", code, data.len())
+                }
+                Message::ParameterProblem {
+                    code,
+                    pointer,
+                    ref data,
+                } => write!(f, "PARAMPROB-{}@{} | {} bytes", code, pointer, data.len()),
+                Message::EchoRequest {
+                    id, seq, ref data, ..
+                } => {
+                    write!(f, "ECHOREQ({},{}) | {} bytes", id, seq, data.len())
+                }
+                Message::EchoReply {
+                    id, seq, ref data, ..
+                } => {
+                    write!(f, "ECHOREP({},{}) | {} bytes", id, seq, data.len())
+                }
+                Message::RouterSolicitation { ref options, .. } => {
+                    write!(f, "RS |")?;
+                    fmt_options(f, options)
+                }
+                Message::RouterAdvertisement {
+                    hop_limit,
+                    lifetime,
+                    ref options,
+                    ..
+                } => {
+                    write!(f, "RA(TTL-{},{}s) |", hop_limit, lifetime)?;
+                    fmt_options(f, options)
+                }
+                Message::NeighborSolicitation {
+                    target,
+                    ref options,
+                    ..
+                } => {
+                    write!(f, "NS({}) |", std::net::Ipv6Addr::from(target))?;
+                    fmt_options(f, options)
+                }
+                Message::NeighborAdvertisement {
+                    router,
+                    solicited,
+                    overrides,
+                    target,
+                    ref options,
+                    ..
+                } => {
+                    write!(
+                        f,
+                        "NA({},{}{}{}) |",
+                        std::net::Ipv6Addr::from(target),
+                        match router {
+                            true => "R",
+                            false => "",
+                        },
+                        match solicited {
+                            true => "S",
+                            false => "",
+                        },
+                        match overrides {
+                            true => "O",
+                            false => "",
+                        }
+                    )?;
+                    fmt_options(f, options)
+                }
+                Message::Unknown {
+                    kind,
+                    code,
+                    ref body,
+                } => {
+                    write!(f, "{}-{} |", kind, code)?;
+                    for e in body.iter() {
+                        write!(f, " {:02X}", e)?;
+                    }
+                    Ok(())
+                }
+            },
+        }
+    }
+}
+
+impl NdpOption {
+    fn parse_all(slc: &[u8]) -> Result<Vec<NdpOption>, Error> {
+        let mut options = Vec::new();
+        let mut i = 0;
+        while i < slc.len() {
+            if i + 2 > slc.len() {
+                return Err(Error::InvalidOption);
+            }
+            // The length field counts 8-byte units, including the type and length fields.
+            let len = slc[i + 1] as usize * 8;
+            if len == 0 || i + len > slc.len() {
+                return Err(Error::InvalidOption);
+            }
+            let data = &slc[i + 2..i + len];
+            options.push(match (slc[i], len) {
+                (1, 8) => NdpOption::SourceLinkLayerAddress(data[..6].try_into().unwrap()),
+                (2, 8) => NdpOption::TargetLinkLayerAddress(data[..6].try_into().unwrap()),
+                (kind, _) => NdpOption::Other {
+                    kind,
+                    data: data.into(),
+                },
+            });
+            i += len;
+        }
+        Ok(options)
+    }
+
+    fn write_all(options: &[NdpOption], out: &mut Vec<u8>) {
+        for o in options.iter() {
+            let (kind, data): (u8, &[u8]) = match o {
+                NdpOption::SourceLinkLayerAddress(a) => (1, a),
+                NdpOption::TargetLinkLayerAddress(a) => (2, a),
+                NdpOption::Other { kind, data } => (*kind, data),
+            };
+            // Options are padded with zeros to a multiple of 8 bytes.
+            let len = (data.len() + 2).div_ceil(8);
+            out.push(kind);
+            out.push(len as u8);
+            out.extend_from_slice(data);
+            out.resize(out.len() + len * 8 - 2 - data.len(), 0);
+        }
+    }
+}
+
+impl Message {
+    fn header_fields(&self) -> (u8, u8) {
+        match self {
+            Message::DestinationUnreachable { code, .. } => (1, *code),
+            Message::PacketTooBig { code, .. } => (2, *code),
+            Message::TimeExceeded { code, .. } => (3, *code),
+            Message::ParameterProblem { code, .. } => (4, *code),
+            Message::EchoRequest { code, .. } => (128, *code),
+            Message::EchoReply { code, .. } => (129, *code),
+            Message::RouterSolicitation { .. } => (133, 0),
+            Message::RouterAdvertisement { .. } => (134, 0),
+            Message::NeighborSolicitation { .. } => (135, 0),
+            Message::NeighborAdvertisement { .. } => (136, 0),
+            Message::Unknown { kind, code, .. } => (*kind, *code),
+        }
+    }
+
+    /// Writes everything that follows the checksum.
+    fn write_body(&self, out: &mut Vec<u8>) {
+        match self {
+            Message::DestinationUnreachable { unused, data, .. }
+            | Message::TimeExceeded { unused, data, .. } => {
+                out.extend_from_slice(unused);
+                out.extend_from_slice(data);
+            }
+            Message::PacketTooBig { mtu: x, data, .. }
+            | Message::ParameterProblem {
+                pointer: x, data, ..
+            } => {
+                out.extend_from_slice(&x.to_be_bytes());
+                out.extend_from_slice(data);
+            }
+            Message::EchoRequest { id, seq, data, .. }
+            | Message::EchoReply { id, seq, data, .. } => {
+                out.extend_from_slice(&id.to_be_bytes());
+                out.extend_from_slice(&seq.to_be_bytes());
+                out.extend_from_slice(data);
+            }
+            Message::RouterSolicitation { reserved, options } => {
+                out.extend_from_slice(reserved);
+                NdpOption::write_all(options, out);
+            }
+            Message::RouterAdvertisement {
+                hop_limit,
+                managed,
+                other,
+                home_agent,
+                preference,
+                proxy,
+                reserved,
+                lifetime,
+                reachable_time,
+                retrans_timer,
+                options,
+            } => {
+                out.push(*hop_limit);
+                out.push(
+                    ((*managed as u8) << 7)
+                        | ((*other as u8) << 6)
+                        | ((*home_agent as u8) << 5)
+                        | ((preference & 0x03) << 3)
+                        | ((*proxy as u8) << 2)
+                        | (reserved & 0x03),
+                );
+                out.extend_from_slice(&lifetime.to_be_bytes());
+                out.extend_from_slice(&reachable_time.to_be_bytes());
+                out.extend_from_slice(&retrans_timer.to_be_bytes());
+                NdpOption::write_all(options, out);
+            }
+            Message::NeighborSolicitation {
+                reserved,
+                target,
+                options,
+            } => {
+                out.extend_from_slice(reserved);
+                out.extend_from_slice(target);
+                NdpOption::write_all(options, out);
+            }
+            Message::NeighborAdvertisement {
+                router,
+                solicited,
+                overrides,
+                reserved,
+                target,
+                options,
+            } => {
+                let flags = ((*router as u32) << 31)
+                    | ((*solicited as u32) << 30)
+                    | ((*overrides as u32) << 29)
+                    | (reserved & 0x1FFF_FFFF);
+                out.extend_from_slice(&flags.to_be_bytes());
+                out.extend_from_slice(target);
+                NdpOption::write_all(options, out);
+            }
+            Message::Unknown { body, .. } => out.extend_from_slice(body),
+        }
+    }
+}
+
+impl Packet {
+    pub fn from_buffer(buf: &[u8]) -> Result<Packet, Error> {
+        if buf.len() < 4 {
+            return Err(Error::InvalidLength);
+        }
+        let body = &buf[4..];
+        // Every known message has at least 4 bytes after the checksum, and NDP messages have more.
+        let min_len = match buf[0] {
+            1..=4 | 128 | 129 | 133 => 4,
+            134 => 12,
+            135 | 136 => 20,
+            _ => 0,
+        };
+        if body.len() < min_len {
+            return Err(Error::InvalidLength);
+        }
+        Ok(Packet {
+            checksum: Some(((buf[2] as u16) << 8) | (buf[3] as u16)),
+            message: match buf[0] {
+                1 => Message::DestinationUnreachable {
+                    code: buf[1],
+                    unused: body[..4].try_into().unwrap(),
+                    data: body[4..].into(),
+                },
+                2 => Message::PacketTooBig {
+                    code: buf[1],
+                    mtu: u32::from_be_bytes(body[..4].try_into().unwrap()),
+                    data: body[4..].into(),
+                },
+                3 => Message::TimeExceeded {
+                    code: buf[1],
+                    unused: body[..4].try_into().unwrap(),
+                    data: body[4..].into(),
+                },
+                4 => Message::ParameterProblem {
+                    code: buf[1],
+                    pointer: u32::from_be_bytes(body[..4].try_into().unwrap()),
+                    data: body[4..].into(),
+                },
+                128 | 129 => {
+                    let code = buf[1];
+                    let id = ((body[0] as u16) << 8) | (body[1] as u16);
+                    let seq = ((body[2] as u16) << 8) | (body[3] as u16);
+                    let data = body[4..].into();
+                    match buf[0] {
+                        128 => Message::EchoRequest {
+                            code,
+                            id,
+                            seq,
+                            data,
+                        },
+                        _ => Message::EchoReply {
+                            code,
+                            id,
+                            seq,
+                            data,
+                        },
+                    }
+                }
+                133 => Message::RouterSolicitation {
+                    reserved: body[..4].try_into().unwrap(),
+                    options: NdpOption::parse_all(&body[4..])?,
+                },
+                134 => Message::RouterAdvertisement {
+                    hop_limit: body[0],
+                    managed: body[1] & 0x80 != 0,
+                    other: body[1] & 0x40 != 0,
+                    home_agent: body[1] & 0x20 != 0,
+                    preference: (body[1] >> 3) & 0x03,
+                    proxy: body[1] & 0x04 != 0,
+                    reserved: body[1] & 0x03,
+                    lifetime: ((body[2] as u16) << 8) | (body[3] as u16),
+                    reachable_time: u32::from_be_bytes(body[4..8].try_into().unwrap()),
+                    retrans_timer: u32::from_be_bytes(body[8..12].try_into().unwrap()),
+                    options: NdpOption::parse_all(&body[12..])?,
+                },
+                135 => Message::NeighborSolicitation {
+                    reserved: body[..4].try_into().unwrap(),
+                    target: body[4..20].try_into().unwrap(),
+                    options: NdpOption::parse_all(&body[20..])?,
+                },
+                136 => Message::NeighborAdvertisement {
+                    router: body[0] & 0x80 != 0,
+                    solicited: body[0] & 0x40 != 0,
+                    overrides: body[0] & 0x20 != 0,
+                    reserved: u32::from_be_bytes(body[..4].try_into().unwrap()) & 0x1FFF_FFFF,
+                    target: body[4..20].try_into().unwrap(),
+                    options: NdpOption::parse_all(&body[20..])?,
+                },
+                kind => Message::Unknown {
+                    kind,
+                    code: buf[1],
+                    body: body.into(),
+                },
+            },
+        })
+    }
+
+    /// The checksum covers the IPv6 pseudo-header, whose sum is passed by the network layer.
+    pub fn into_buffer(&self, offset_needed: (usize, usize), pseudoheader_sum: u32) -> Vec<u8> {
+        let (kind, code) = self.message.header_fields();
+        let mut out = vec![0u8; offset_needed.0];
+        out.push(kind);
+        out.push(code);
+        out.extend_from_slice(&[0; 2]);
+        self.message.write_body(&mut out);
+        let checksum = match self.checksum {
+            Some(x) => x,
+            None => {
+                let slc = &out[offset_needed.0..];
+                crate::utils::ip_checksum::calc_checksum(slc, pseudoheader_sum + (slc.len() as u32))
+            }
+        };
+        out[offset_needed.0 + 2] = (checksum >> 8) as u8;
+        out[offset_needed.0 + 3] = checksum as u8;
+        out.resize(out.len() + offset_needed.1, 0);
+
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TARGET: [u8; 16] = [0xfe, 0x80, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 2];
+
+    /// Parses a message and checks that it is written back as it was.
+    fn round_trip(buf: &[u8]) -> Message {
+        let packet = Packet::from_buffer(buf).unwrap();
+        assert_eq!(packet.checksum, Some(0x1234));
+        assert_eq!(packet.into_buffer((0, 0), 0), buf);
+        packet.message
+    }
+
+    #[test]
+    fn echo() {
+        match round_trip(&[128, 0, 0x12, 0x34, 0, 7, 0, 1, b'h', b'i']) {
+            Message::EchoRequest { id, seq, data, .. } => {
+                assert_eq!((id, seq, &data[..]), (7, 1, &b"hi"[..]))
+            }
+            m => panic!("{:?}", m),
+        }
+        // Codes other than 0 are not valid, but they are kept as they are.
+        match round_trip(&[129, 5, 0x12, 0x34, 0, 7, 0, 2]) {
+            Message::EchoReply {
+                code,
+                id,
+                seq,
+                data,
+            } => assert_eq!((code, id, seq, data.len()), (5, 7, 2, 0)),
+            m => panic!("{:?}", m),
+        }
+    }
+
+    #[test]
+    fn errors() {
+        match round_trip(&[1, 4, 0x12, 0x34, 0, 0, 0, 0, 0x60, 0]) {
+            Message::DestinationUnreachable { code, unused, data } => {
+                assert_eq!((code, unused, &data[..]), (4, [0; 4], &[0x60, 0][..]))
+            }
+            m => panic!("{:?}", m),
+        }
+        // The first unused byte is the length of the quoted packet when extensions follow it.
+        match round_trip(&[1, 3, 0x12, 0x34, 1, 0, 0, 7, 0x60, 0]) {
+            Message::DestinationUnreachable { code, unused, .. } => {
+                assert_eq!((code, unused), (3, [1, 0, 0, 7]))
+            }
+            m => panic!("{:?}", m),
+        }
+        match round_trip(&[2, 0, 0x12, 0x34, 0, 0, 0x05, 0x00, 0x60]) {
+            Message::PacketTooBig { mtu, data, .. } => assert_eq!((mtu, data.len()), (1280, 1)),
+            m => panic!("{:?}", m),
+        }
+        match round_trip(&[2, 1, 0x12, 0x34, 0, 0, 0x05, 0x00]) {
+            Message::PacketTooBig { code, mtu, .. } => assert_eq!((code, mtu), (1, 1280)),
+            m => panic!("{:?}", m),
+        }
+        match round_trip(&[3, 1, 0x12, 0x34, 0, 0, 0, 0]) {
+            Message::TimeExceeded { code, data, .. } => assert_eq!((code, data.len()), (1, 0)),
+            m => panic!("{:?}", m),
+        }
+        match round_trip(&[3, 0, 0x12, 0x34, 2, 0, 0xAB, 0, 0x60]) {
+            Message::TimeExceeded { unused, data, .. } => {
+                assert_eq!((unused, data.len()), ([2, 0, 0xAB, 0], 1))
+            }
+            m => panic!("{:?}", m),
+        }
+        match round_trip(&[4, 1, 0x12, 0x34, 0, 0, 0, 40, 0x60]) {
+            Message::ParameterProblem {
+                code,
+                pointer,
+                data,
+            } => assert_eq!((code, pointer, data.len()), (1, 40, 1)),
+            m => panic!("{:?}", m),
+        }
+    }
+
+    #[test]
+    fn router_discovery() {
+        let mut buf = vec![133, 0, 0x12, 0x34, 0, 0, 0, 0];
+        buf.extend_from_slice(&[1, 1, 2, 0, 0, 0, 0, 1]);
+        match round_trip(&buf) {
+            Message::RouterSolicitation { options, .. } => match options[..] {
+                [NdpOption::SourceLinkLayerAddress(a)] => assert_eq!(a, [2, 0, 0, 0, 0, 1]),
+                ref o => panic!("{:?}", o),
+            },
+            m => panic!("{:?}", m),
+        }
+        match round_trip(&[133, 0, 0x12, 0x34, 1, 2, 3, 4]) {
+            Message::RouterSolicitation { reserved, options } => {
+                assert_eq!((reserved, options.len()), ([1, 2, 3, 4], 0))
+            }
+            m => panic!("{:?}", m),
+        }
+
+        let mut buf = vec![134, 0, 0x12, 0x34, 64, 0x80, 0x07, 0x08];
+        buf.extend_from_slice(&[0, 0, 0x75, 0x30, 0, 0, 0x03, 0xE8]);
+        // A prefix information option, which is kept as it is.
+        buf.extend_from_slice(&[3, 4, 64, 0xC0]);
+        buf.extend((0..28).map(|i| i as u8));
+        match round_trip(&buf) {
+            Message::RouterAdvertisement {
+                hop_limit,
+                managed,
+                other,
+                lifetime,
+                reachable_time,
+                retrans_timer,
+                options,
+                ..
+            } => {
+                assert_eq!((hop_limit, managed, other), (64, true, false));
+                assert_eq!(
+                    (lifetime, reachable_time, retrans_timer),
+                    (1800, 30000, 1000)
+                );
+                match options[..] {
+                    [NdpOption::Other { kind: 3, ref data }] => assert_eq!(data.len(), 30),
+                    ref o => panic!("{:?}", o),
+                }
+            }
+            m => panic!("{:?}", m),
+        }
+
+        // Home agent, a low preference, proxy and a reserved bit.
+        match round_trip(&[134, 0, 0x12, 0x34, 64, 0x7D, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]) {
+            Message::RouterAdvertisement {
+                managed,
+                other,
+                home_agent,
+                preference,
+                proxy,
+                reserved,
+                ..
+            } => {
+                assert_eq!((managed, other, home_agent), (false, true, true));
+                assert_eq!((preference, proxy, reserved), (3, true, 1));
+            }
+            m => panic!("{:?}", m),
+        }
+    }
+
+    #[test]
+    fn neighbor_discovery() {
+        let mut buf = vec![135, 0, 0x12, 0x34, 0, 0, 0, 0];
+        buf.extend_from_slice(&TARGET);
+        buf.extend_from_slice(&[1, 1, 2, 0, 0, 0, 0, 1]);
+        match round_trip(&buf) {
+            Message::NeighborSolicitation {
+                reserved,
+                target,
+                options,
+            } => {
+                assert_eq!((reserved, target), ([0; 4], TARGET));
+                match options[..] {
+                    [NdpOption::SourceLinkLayerAddress(a)] => assert_eq!(a, [2, 0, 0, 0, 0, 1]),
+                    ref o => panic!("{:?}", o),
+                }
+            }
+            m => panic!("{:?}", m),
+        }
+
+        let mut buf = vec![136, 0, 0x12, 0x34, 0x61, 0, 0, 3];
+        buf.extend_from_slice(&TARGET);
+        buf.extend_from_slice(&[2, 1, 2, 0, 0, 0, 0, 2]);
+        match round_trip(&buf) {
+            Message::NeighborAdvertisement {
+                router,
+                solicited,
+                overrides,
+                reserved,
+                target,
+                options,
+            } => {
+                assert_eq!((router, solicited, overrides), (false, true, true));
+                assert_eq!(reserved, 0x0100_0003);
+                assert_eq!(target, TARGET);
+                match options[..] {
+                    [NdpOption::TargetLinkLayerAddress(a)] => assert_eq!(a, [2, 0, 0, 0, 0, 2]),
+                    ref o => panic!("{:?}", o),
+                }
+            }
+            m => panic!("{:?}", m),
+        }
+    }
+
+    #[test]
+    fn options_are_padded() {
+        let packet = Packet {
+            message: Message::RouterSolicitation {
+                reserved: [0; 4],
+                options: vec![NdpOption::Other {
+                    kind: 14,
+                    data: Box::new([1, 2, 3, 4, 5, 6, 7]),
+                }],
+            },
+            checksum: Some(0),
+        };
+        assert_eq!(
+            &packet.into_buffer((0, 0), 0)[8..],
+            &[14, 2, 1, 2, 3, 4, 5, 6, 7, 0, 0, 0, 0, 0, 0, 0]
+        );
+    }
+
+    #[test]
+    fn invalid_options() {
+        // An option with a length of 0.
+        let buf = [133, 0, 0, 0, 0, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0, 0];
+        assert!(matches!(
+            Packet::from_buffer(&buf),
+            Err(Error::InvalidOption)
+        ));
+        // An option that is longer than the message.
+        let buf = [133, 0, 0, 0, 0, 0, 0, 0, 1, 2, 0, 0, 0, 0, 0, 0];
+        assert!(matches!(
+            Packet::from_buffer(&buf),
+            Err(Error::InvalidOption)
+        ));
+        // A neighbor solicitation without a whole target.
+        let buf = [135, 0, 0, 0, 0, 0, 0, 0, 0xfe, 0x80];
+        assert!(matches!(
+            Packet::from_buffer(&buf),
+            Err(Error::InvalidLength)
+        ));
+    }
+
+    #[test]
+    fn unknown_messages() {
+        match round_trip(&[200, 3, 0x12, 0x34, 1, 2, 3]) {
+            Message::Unknown { kind, code, body } => {
+                assert_eq!((kind, code, &body[..]), (200, 3, &[1, 2, 3][..]))
+            }
+            m => panic!("{:?}", m),
+        }
+    }
+
+    #[test]
+    fn checksums() {
+        let mut packet = Packet::from_buffer(&[128, 0, 0x12, 0x34, 0, 7, 0, 1]).unwrap();
+        packet.checksum = None;
+        let out = packet.into_buffer((1, 1), 0x3A);
+        assert_eq!(out.len(), 10);
+        // The checksum covers the message, its length and the pseudo-header sum, so they add up to 0 along with it.
+        assert_eq!(
+            crate::utils::ip_checksum::calc_checksum(&out[1..9], 0x3A + 8),
+            0
+        );
+    }
+}
@@ -1,4 +1,5 @@
 pub mod icmp;
+pub mod icmpv6;
 pub mod tcp;
 pub mod udp;
 
@@ -6,6 +7,7 @@ pub mod udp;
 pub enum Data {
     Tcp(tcp::Segment),
     Icmp(icmp::Packet),
+    Icmpv6(icmpv6::Packet),
     Udp(udp::Datagram),
     /// This instance is used for protocols that are yet to be implemented, so that their packets can still be constructed.
     UnknownIpProtocol(u8, Box<[u8]>),
@@ -17,7 +19,12 @@ impl Data {
             Data::Tcp(ref s) => s.into_buffer(offset_needed, checksum_offset),
             Data::Udp(ref d) => d.into_buffer(offset_needed, checksum_offset),
             Data::Icmp(ref i) => i.into_buffer(offset_needed),
-            _ => vec![0; offset_needed.0 + offset_needed.1],
+            Data::Icmpv6(ref i) => i.into_buffer(offset_needed, checksum_offset),
+            Data::UnknownIpProtocol(_, ref data) => {
+                let mut out = vec![0; offset_needed.0 + data.len() + offset_needed.1];
+                out[offset_needed.0..offset_needed.0 + data.len()].copy_from_slice(data);
+                out
+            }
         }
     }
 }
@@ -60,6 +67,14 @@ impl<'a> std::fmt::Display for PrintableData<'a> {
                             data: p
                         }
                     ),
+                    &Data::Icmpv6(ref p) => write!(
+                        f,
+                        "ICMPv6-{}",
+                        icmpv6::PrintableData {
+                            style: icmpv6::PrintStyle::Normal,
+                            data: p
+                        }
+                    ),
                     &Data::Udp(ref d) => write!(
                         f,
                         "UDP {}",
@@ -78,5 +93,28 @@ impl<'a> std::fmt::Display for PrintableData<'a> {
 pub enum Error {
     Tcp(tcp::Error),
     Icmp(icmp::Error),
+    Icmpv6(icmpv6::Error),
     Udp(udp::Error),
 }
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Error::Tcp(e) => write!(f, "TCP: {}", e),
+            Error::Icmp(e) => write!(f, "ICMP: {}", e),
+            Error::Icmpv6(e) => write!(f, "ICMPv6: {}", e),
+            Error::Udp(e) => write!(f, "UDP: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Tcp(e) => Some(e),
+            Error::Icmp(e) => Some(e),
+            Error::Icmpv6(e) => Some(e),
+            Error::Udp(e) => Some(e),
+        }
+    }
+}
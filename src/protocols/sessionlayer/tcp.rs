@@ -74,6 +74,17 @@ pub enum Error {
     InvalidLengthField,
 }
 
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Error::InvalidLength => write!(f, "TCP: segment is too short"),
+            Error::InvalidLengthField => write!(f, "TCP: data offset does not match the segment"),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
 impl Segment {
     pub fn from_buffer(slc: &[u8]) -> Result<Segment, Error> {
         if slc.len() < 20 {
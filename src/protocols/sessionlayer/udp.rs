@@ -4,6 +4,17 @@ pub enum Error {
     InvalidLengthField,
 }
 
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Error::InvalidLength => write!(f, "UDP: datagram is too short"),
+            Error::InvalidLengthField => write!(f, "UDP: length field does not match the datagram"),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
 #[derive(Debug)]
 pub struct Datagram {
     src_port: u16,
@@ -66,7 +77,7 @@ impl Datagram {
             return Err(Error::InvalidLength);
         }
         let l = ((slc[4] as usize) << 8) + (slc[5] as usize);
-        if slc.len() < l {
+        if slc.len() < l || l < 8 {
             return Err(Error::InvalidLengthField);
         }
         Ok(Datagram {
@@ -76,6 +87,10 @@ impl Datagram {
             payload: Box::from(&slc[8..l]),
         })
     }
+    /// Drops the checksum that has been read from the buffer, so that it is calculated again when the datagram is written (e.g. after its addresses have changed).
+    pub fn clear_checksum(&mut self) {
+        self.checksum = None;
+    }
     pub fn into_buffer(&self, offset_needed: (usize, usize), pseudoheader_sum: u32) -> Vec<u8> {
         let l = 8 + self.payload.len();
         let mut out = vec![0; offset_needed.0 + offset_needed.1 + l];
@@ -86,25 +101,29 @@ impl Datagram {
         slc[1] = self.src_port as u8;
         slc[2] = (self.dst_port >> 8) as u8;
         slc[3] = self.dst_port as u8;
-        slc[0] = (l >> 8) as u8;
-        slc[1] = l as u8;
+        slc[4] = (l >> 8) as u8;
+        slc[5] = l as u8;
+        // The payload is covered by the checksum, so it is written first.
+        slc[8..].copy_from_slice(self.payload.as_ref());
         match self.checksum {
             Some(x) => {
                 slc[6] = (x >> 8) as u8;
                 slc[7] = x as u8;
             }
             None => {
-                let checksum: u16 = crate::utils::ip_checksum::calc_checksum(
+                let checksum = match crate::utils::ip_checksum::calc_checksum(
                     slc,
                     pseudoheader_sum + (slc.len() as u32),
-                );
+                ) {
+                    // A checksum of 0 means that no checksum has been calculated, so it is sent as its complement.
+                    0 => 0xffff,
+                    c => c,
+                };
                 slc[6] = (checksum >> 8) as u8;
                 slc[7] = checksum as u8;
             }
         }
 
-        slc[8..].copy_from_slice(self.payload.as_ref());
-
         out
     }
 }